@@ -0,0 +1,286 @@
+use std::ffi::OsString;
+use std::fs::Permissions;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context as TaskContext, Poll};
+
+use anyhow::Context;
+use axum::{
+    extract::{Path, State},
+    http::{header, Request, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use hyper::server::accept::Accept;
+use tokio::net::{UnixListener, UnixStream};
+use uuid::Uuid;
+
+use crate::config::AgentConfig;
+use crate::dto::{ActiveTaskInfo, ActiveTasks, AdminCommand, EvictionReport, Readiness, TaskType};
+use crate::infrastructure::ioc::Container;
+use crate::infrastructure::service::deploy_log::DeployLogState;
+use crate::infrastructure::service::health::HealthCheck;
+use crate::infrastructure::service::software_cache::SoftwareCache;
+
+type ApiResult<T> = Result<T, (StatusCode, String)>;
+
+/// Local HTTP API for inspecting and controlling tasks, also serving the metrics and probes
+pub struct AdminApi {
+    listener: Listener,
+    router: Router,
+}
+
+enum Listener {
+    Tcp(std::net::TcpListener),
+    Unix(UnixListener),
+}
+
+#[derive(Clone)]
+struct AdminState {
+    container: Arc<Container>,
+    save_dir: Arc<PathBuf>,
+    token: Option<Arc<str>>,
+}
+
+impl AdminApi {
+    pub async fn bind(container: Arc<Container>, config: &AgentConfig) -> anyhow::Result<Self> {
+        let admin = &config.admin;
+        let listener = match &admin.unix_socket {
+            Some(path) => {
+                let listener = bind_private(path)
+                    .await
+                    .with_context(|| format!("Failed to listen on {path}"))?;
+                Listener::Unix(listener)
+            }
+            None => {
+                anyhow::ensure!(
+                    admin.token.is_some(),
+                    "`admin.token` is required to serve the admin API on TCP"
+                );
+                let host = &config.common.host;
+                let address = (host.bind_address.as_str(), host.bind_port);
+                let listener = std::net::TcpListener::bind(address).with_context(|| {
+                    format!(
+                        "Failed to listen on {}:{}",
+                        host.bind_address, host.bind_port
+                    )
+                })?;
+                Listener::Tcp(listener)
+            }
+        };
+
+        let state = AdminState {
+            container,
+            save_dir: Arc::new(PathBuf::from(&config.save_path)),
+            token: admin.token.as_deref().map(Arc::from),
+        };
+        let router = Router::new()
+            .route("/tasks", get(list_tasks))
+            .route("/tasks/:id", get(get_task))
+            .route("/tasks/:id/script", get(get_script))
+            .route("/tasks/:id/:command", post(control_task))
+            .route(
+                "/software/eviction",
+                get(plan_eviction).post(purge_software),
+            )
+            .route("/deploy-logs/:id", get(get_deploy_log))
+            .route("/metrics", get(metrics))
+            .route_layer(middleware::from_fn_with_state(state.clone(), authorize))
+            // Probes are open, as they reveal nothing about the tasks
+            .route("/healthz", get(liveness))
+            .route("/readyz", get(readiness))
+            .with_state(state);
+
+        Ok(Self { listener, router })
+    }
+
+    pub async fn run(self) {
+        let service = self.router.into_make_service();
+        let result = match self.listener {
+            Listener::Tcp(listener) => match axum::Server::from_tcp(listener) {
+                Ok(server) => server.serve(service).await,
+                Err(e) => Err(e),
+            },
+            Listener::Unix(listener) => {
+                axum::Server::builder(UnixAccept(listener)).serve(service).await
+            }
+        };
+        if let Err(e) = result {
+            tracing::error!("Admin API stopped: {e}");
+        }
+    }
+}
+
+/// Bind the socket in a directory only the agent can enter, then move it into place,
+/// so that others can't connect before its permissions are restricted
+async fn bind_private(path: &str) -> io::Result<UnixListener> {
+    let mut dir = OsString::from(path);
+    dir.push(".d");
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    tokio::fs::DirBuilder::new().mode(0o700).create(&dir).await?;
+
+    let temp = std::path::Path::new(&dir).join("socket");
+    let result = async {
+        let listener = UnixListener::bind(&temp)?;
+        tokio::fs::set_permissions(&temp, Permissions::from_mode(0o600)).await?;
+        // Replaces the socket file left if the agent didn't stop normally
+        tokio::fs::rename(&temp, path).await?;
+        Ok(listener)
+    }
+    .await;
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    result
+}
+
+async fn authorize<B>(
+    State(state): State<AdminState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if let Some(token) = &state.token {
+        let bearer = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        if !bearer.is_some_and(|bearer| constant_time_eq(bearer.as_bytes(), token.as_bytes())) {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+/// Compare without returning early, so that the time taken doesn't reveal the token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+async fn list_tasks(State(state): State<AdminState>) -> Json<ActiveTasks> {
+    Json(state.container.active_tasks().await)
+}
+
+async fn get_task(
+    State(state): State<AdminState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<ActiveTaskInfo>> {
+    let (_, info) = state.container.active_task(id).await.ok_or_else(task_not_found)?;
+    Ok(Json(info))
+}
+
+async fn get_script(State(state): State<AdminState>, Path(id): Path<Uuid>) -> ApiResult<String> {
+    let (r#type, info) = state.container.active_task(id).await.ok_or_else(task_not_found)?;
+    let node_id = match (r#type, info.node_id) {
+        (TaskType::ExecuteUsecase, Some(node_id)) => node_id,
+        _ => return Err((StatusCode::NOT_FOUND, "The task has no script".to_owned())),
+    };
+
+    // The path of the script is decided by the job service
+    let path = state.save_dir.join(node_id.to_string()).join("run.sh");
+    match tokio::fs::read_to_string(&path).await {
+        Ok(script) => Ok(script),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err((
+            StatusCode::NOT_FOUND,
+            "The script isn't generated yet".to_owned(),
+        )),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+async fn control_task(
+    State(state): State<AdminState>,
+    Path((id, command)): Path<(Uuid, AdminCommand)>,
+) -> ApiResult<StatusCode> {
+    tracing::info!(task_id = %id, "Admin API: {command:?}");
+    if state.container.control(id, command).await {
+        Ok(StatusCode::ACCEPTED)
+    } else {
+        Err(task_not_found())
+    }
+}
+
+/// The full output of a failed software deployment
+async fn get_deploy_log(
+    State(state): State<AdminState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<String> {
+    let path = DeployLogState::dir(state.save_dir.as_ref()).join(format!("{id}.log"));
+    match tokio::fs::read_to_string(&path).await {
+        Ok(log) => Ok(log),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Err((
+            StatusCode::NOT_FOUND,
+            "No log of the failed deployment".to_owned(),
+        )),
+        Err(e) => Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string())),
+    }
+}
+
+/// The software that would be evicted now, without uninstalling it
+async fn plan_eviction(State(state): State<AdminState>) -> ApiResult<Json<EvictionReport>> {
+    let cache = SoftwareCache::inj_ref(state.container.as_ref());
+    cache
+        .evict(true)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Evict the software now according to the eviction rules
+async fn purge_software(State(state): State<AdminState>) -> ApiResult<Json<EvictionReport>> {
+    tracing::info!("Admin API: evict software");
+    let cache = SoftwareCache::inj_ref(state.container.as_ref());
+    cache
+        .evict(false)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+async fn liveness(State(state): State<AdminState>) -> (StatusCode, String) {
+    match HealthCheck::inj_ref(state.container.as_ref()).liveness() {
+        Ok(()) => (StatusCode::OK, "ok".to_owned()),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, format!("{e:#}")),
+    }
+}
+
+async fn readiness(State(state): State<AdminState>) -> (StatusCode, Json<Readiness>) {
+    let readiness = state.container.check_readiness().await;
+    let status = if readiness.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+async fn metrics(State(state): State<AdminState>) -> ApiResult<String> {
+    state
+        .container
+        .encode_metrics()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+fn task_not_found() -> (StatusCode, String) {
+    (StatusCode::NOT_FOUND, "Task not found or ended".to_owned())
+}
+
+struct UnixAccept(UnixListener);
+
+impl Accept for UnixAccept {
+    type Conn = UnixStream;
+    type Error = io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let (stream, _) = ready!(self.0.poll_accept(cx))?;
+        Poll::Ready(Some(Ok(stream)))
+    }
+}
@@ -0,0 +1,20 @@
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::Duration;
+
+use infrastructure::sync::timer;
+
+use crate::infrastructure::ioc::Container;
+use crate::infrastructure::service::health::HealthCheck;
+use crate::infrastructure::service::workspace::WorkspaceManager;
+
+pub async fn clean_workspaces(container: Arc<Container>, interval: Duration) {
+    timer::new::<(), _, _>(interval, || async {
+        HealthCheck::inj_ref(container.as_ref()).beat("clean_workspaces", interval);
+        if let Err(e) = WorkspaceManager::inj_ref(container.as_ref()).collect_garbage().await {
+            tracing::error!("Failed to clean workspaces: {e}");
+        }
+        ControlFlow::Continue(())
+    })
+    .await;
+}
@@ -0,0 +1,20 @@
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::Duration;
+
+use infrastructure::sync::timer;
+
+use crate::infrastructure::ioc::Container;
+use crate::infrastructure::service::health::HealthCheck;
+use crate::infrastructure::service::software_cache::SoftwareCache;
+
+pub async fn evict_software(container: Arc<Container>, interval: Duration) {
+    timer::new::<(), _, _>(interval, || async {
+        HealthCheck::inj_ref(container.as_ref()).beat("evict_software", interval);
+        if let Err(e) = SoftwareCache::inj_ref(container.as_ref()).evict(false).await {
+            tracing::error!("Failed to evict software: {e}");
+        }
+        ControlFlow::Continue(())
+    })
+    .await;
+}
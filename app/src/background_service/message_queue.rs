@@ -2,6 +2,7 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use alice_infrastructure::config::MessageQueueConfig;
 use anyhow::Context;
@@ -15,24 +16,49 @@ use rdkafka::{
     message::BorrowedMessage,
     ClientConfig, Message,
 };
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::Instrument;
 
 use crate::dto;
 use crate::dto::TaskCommand;
 use crate::infrastructure::ioc::Container;
-use crate::infrastructure::service::SelectTaskService;
+use crate::infrastructure::service::{health::HealthCheck, SelectTaskService};
+use crate::infrastructure::trace::task_span;
+
+/// Interval of checking whether partitions are assigned to the consumer
+const READINESS_INTERVAL: Duration = Duration::from_secs(10);
 
 pub struct KafkaMessageQueue {
     stream_consumer: StreamConsumer,
     service: Arc<Container>,
+    tasks: TaskTracker,
 }
 
 impl KafkaMessageQueue {
-    pub async fn run(&self) {
+    /// Consume commands until `shutdown` is cancelled
+    pub async fn run(&self, shutdown: CancellationToken) {
         let mut stream = self.stream_consumer.stream();
+        let mut readiness = tokio::time::interval(READINESS_INTERVAL);
+        // Cleared once a message is consumed
+        let mut failed_at: Option<Instant> = None;
         loop {
-            match stream.next().await {
+            let message = tokio::select! {
+                message = stream.next() => message,
+                _ = readiness.tick() => {
+                    // Ready while assigned partitions, if consuming hasn't failed lately
+                    let recovered = failed_at.is_none_or(|at| at.elapsed() >= READINESS_INTERVAL);
+                    self.set_subscribed(recovered && self.is_assigned());
+                    continue;
+                }
+                _ = shutdown.cancelled() => break,
+            };
+            match message {
                 Some(Ok(borrowed_message)) => {
+                    failed_at = None;
+                    self.set_subscribed(true);
+                    self.count("consumed");
                     if let Err(e) = self.routine(borrowed_message).await {
+                        self.count("failed");
                         tracing::error!("{e}");
                         continue;
                     }
@@ -41,39 +67,56 @@ impl KafkaMessageQueue {
                     KafkaError::PartitionEOF(partition) => {
                         tracing::info!("at end of partition {partition:?}");
                     }
-                    _ => tracing::error!("errors from kafka, {kafka_error}"),
+                    _ => {
+                        failed_at = Some(Instant::now());
+                        self.set_subscribed(false);
+                        self.count("failed");
+                        tracing::error!("errors from kafka, {kafka_error}");
+                    }
                 },
                 None => (),
             }
         }
+        self.set_subscribed(false);
     }
 }
 
 impl KafkaMessageQueue {
-    async fn routine(&self, message: BorrowedMessage<'_>) -> serde_json::Result<()> {
-        let message = message.payload_view::<str>().and_then(Result::ok).unwrap_or("{}");
+    fn set_subscribed(&self, subscribed: bool) {
+        HealthCheck::inj_ref(self.service.as_ref()).set_kafka_subscribed(subscribed);
+    }
+
+    fn is_assigned(&self) -> bool {
+        self.stream_consumer.assignment().is_ok_and(|assignment| assignment.count() > 0)
+    }
+
+    fn count(&self, result: &str) {
+        self.service.metrics.kafka_messages.with_label_values(&[result]).inc();
+    }
+
+    async fn routine(&self, borrowed_message: BorrowedMessage<'_>) -> serde_json::Result<()> {
+        let message = borrowed_message.payload_view::<str>().and_then(Result::ok).unwrap_or("{}");
         tracing::debug!(incoming_message = %message);
         let task = serde_json::from_str::<dto::Task>(message)?;
+        let span = task_span(task.id, &task.command, borrowed_message.headers());
 
         if let dto::TaskCommand::Start = task.command {
-            let dto::TaskStart { node_id, body } = serde_json::from_str::<dto::TaskStart>(message)?;
+            let start = serde_json::from_str::<dto::TaskStart>(message)?;
             let service = self.service.clone();
-            tokio::spawn(async move { service.start(task.id, node_id, body).await });
+            let routine = async move { service.start(task.id, start).await };
+            self.tasks.spawn(routine.instrument(span));
         } else {
             let r#type = serde_json::from_str::<dto::TaskType>(message)?;
             let service = self.service.clone();
-            match task.command {
-                TaskCommand::Resume => {
-                    tokio::spawn(async move { service.resume(r#type, task.id).await });
+            let routine = async move {
+                match task.command {
+                    TaskCommand::Resume => service.resume(r#type, task.id).await,
+                    TaskCommand::Pause => service.pause(r#type, task.id).await,
+                    TaskCommand::Cancel => service.cancel(r#type, task.id).await,
+                    _ => unreachable!(),
                 }
-                TaskCommand::Pause => {
-                    tokio::spawn(async move { service.pause(r#type, task.id).await });
-                }
-                TaskCommand::Cancel => {
-                    tokio::spawn(async move { service.cancel(r#type, task.id).await });
-                }
-                _ => unreachable!(),
-            }
+            };
+            self.tasks.spawn(routine.instrument(span));
         }
 
         Ok(())
@@ -83,6 +126,7 @@ impl KafkaMessageQueue {
 impl KafkaMessageQueue {
     pub async fn new(
         service: Arc<Container>,
+        tasks: TaskTracker,
         mq: &MessageQueueConfig,
         extra_topics: impl IntoIterator<Item = String>,
     ) -> anyhow::Result<Self> {
@@ -114,11 +158,13 @@ impl KafkaMessageQueue {
             }
         }
 
+        // Ready once partitions are assigned, see `run`
         stream_consumer.subscribe(&topics.iter().map(String::as_str).collect::<Vec<&str>>())?;
 
         Ok(Self {
             stream_consumer,
             service,
+            tasks,
         })
     }
 }
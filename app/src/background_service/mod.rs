@@ -1,12 +1,22 @@
+mod admin_api;
+mod clean_workspaces;
+mod evict_software;
 pub mod message_queue;
 mod refresh_jobs;
+mod refresh_token;
 pub mod resource_reporter;
+mod tail_logs;
 
 pub mod prelude {
     #[rustfmt::skip]
     pub use super::{
+        admin_api::AdminApi,
+        clean_workspaces::clean_workspaces,
+        evict_software::evict_software,
         message_queue::KafkaMessageQueue,
         refresh_jobs::refresh_jobs,
+        refresh_token::refresh_token,
         resource_reporter::ResourceReporter,
+        tail_logs::tail_logs,
     };
 }
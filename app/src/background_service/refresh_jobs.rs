@@ -6,9 +6,11 @@ use domain::service::JobService;
 use infrastructure::sync::timer;
 
 use crate::infrastructure::ioc::Container;
+use crate::infrastructure::service::health::HealthCheck;
 
 pub async fn refresh_jobs(job_sv: Arc<Container>, interval: Duration) {
     timer::new::<(), _, _>(interval, || async {
+        HealthCheck::inj_ref(job_sv.as_ref()).beat("refresh_jobs", interval);
         job_sv.refresh_all().await;
         ControlFlow::Continue(())
     })
@@ -0,0 +1,8 @@
+use std::sync::Arc;
+
+use crate::infrastructure::ioc::Container;
+
+/// Refresh the access token before it expires, so that requests are rarely rejected
+pub async fn refresh_token(container: Arc<Container>) {
+    container.tokens.keep_fresh().await;
+}
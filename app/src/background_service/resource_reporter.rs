@@ -1,35 +1,64 @@
+use std::collections::BTreeSet;
 use std::ops::ControlFlow;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+use domain::model::entity::task::deploy_software::DeployerType;
+use domain::service::SelectSoftwareDeployer;
 use infrastructure::sync::timer;
 use reqwest_middleware::ClientWithMiddleware;
+use tokio::time::interval;
 use url::Url;
 
+use crate::config::ResourceReportConfig;
+use crate::dto::{InstalledSoftware, SoftwareInventory};
 use crate::infrastructure::ioc::Container;
-use crate::infrastructure::service::resource_stat::ResourceStat;
-
-/// the period for reporting
-const REPORT_PERIOD: Duration = Duration::from_secs(60 * 60);
+use crate::infrastructure::service::health::HealthCheck;
+use crate::infrastructure::service::resource_stat::{ResourceStat, TotalResources};
 
 pub struct ResourceReporter {
     stat: Arc<Container>,
-    update_url: Url,
+    used_url: Url,
+    total_url: Url,
+    software_url: Url,
     http_client: Arc<ClientWithMiddleware>,
+    config: ResourceReportConfig,
+    deployers: Vec<DeployerType>,
 }
 
 impl ResourceReporter {
-    pub fn new(container: Arc<Container>, base_url: Url) -> Self {
+    pub fn new(
+        container: Arc<Container>,
+        base_url: Url,
+        config: ResourceReportConfig,
+        deployers: Vec<DeployerType>,
+    ) -> Self {
         Self {
             http_client: container.default_http_client.clone(),
             stat: container,
-            update_url: base_url.join("agent/UpdateUsedResource").unwrap(),
+            used_url: base_url.join("agent/UpdateUsedResource").unwrap(),
+            total_url: base_url.join("agent/UpdateTotalResource").unwrap(),
+            software_url: base_url.join("agent/UpdateSoftwareInventory").unwrap(),
+            config,
+            deployers,
         }
     }
 
     pub async fn run(&self) {
-        timer::new::<(), _, _>(REPORT_PERIOD, || async {
-            if let Err(e) = self.update().await {
+        tokio::join!(
+            self.report_used(),
+            self.report_total(),
+            self.report_software()
+        );
+    }
+}
+
+impl ResourceReporter {
+    async fn report_used(&self) {
+        let interval = Duration::from_secs(self.config.used_interval.max(1));
+        timer::new::<(), _, _>(interval, || async {
+            HealthCheck::inj_ref(self.stat.as_ref()).beat("report_used", interval);
+            if let Err(e) = self.update_used().await {
                 tracing::error!(
                     "Failed to update resources on computing orchestration system: {e}"
                 );
@@ -38,13 +67,135 @@ impl ResourceReporter {
         })
         .await;
     }
-}
 
-impl ResourceReporter {
-    async fn update(&self) -> anyhow::Result<()> {
+    /// Check total resources frequently,
+    /// reporting them periodically or at once when they change beyond the threshold.
+    async fn report_total(&self) {
+        let report_interval = Duration::from_secs(self.config.total_interval);
+        let mut interval = interval(Duration::from_secs(self.config.check_interval.max(1)));
+        let mut reported: Option<(TotalResources, Instant)> = None;
+
+        loop {
+            interval.tick().await;
+            let total = match self.stat.total().await {
+                Ok(total) => total,
+                Err(e) => {
+                    tracing::warn!("Failed to stat total resources: {e}");
+                    continue;
+                }
+            };
+            self.stat.metrics.record_resources("total", total.values());
+
+            let due = match &reported {
+                Some((last, at)) => {
+                    at.elapsed() >= report_interval
+                        || total.changed(last, self.config.change_threshold)
+                }
+                None => true,
+            };
+            if !due {
+                continue;
+            }
+            tracing::info!("Reporting total resources: {total:#?}");
+            match self.update_total(&total).await {
+                Ok(()) => reported = Some((total, Instant::now())),
+                Err(e) => tracing::error!(
+                    "Failed to update total resources on computing orchestration system: {e}"
+                ),
+            }
+        }
+    }
+
+    /// Check installed software of the enabled deployers frequently,
+    /// reporting all of them periodically or the changes at once.
+    async fn report_software(&self) {
+        if self.deployers.is_empty() {
+            return;
+        }
+        let report_interval = Duration::from_secs(self.config.software_interval);
+        let mut interval = interval(Duration::from_secs(
+            self.config.software_check_interval.max(1),
+        ));
+        let mut reported: Option<(BTreeSet<InstalledSoftware>, Instant)> = None;
+
+        loop {
+            interval.tick().await;
+            let Some(software) = self.installed_software().await else {
+                continue;
+            };
+
+            let inventory = match &reported {
+                Some((last, at)) if at.elapsed() < report_interval => {
+                    SoftwareInventory::diff(last, &software)
+                }
+                _ => SoftwareInventory::full(&software),
+            };
+            if inventory.is_empty() {
+                continue;
+            }
+            tracing::info!(
+                full = inventory.full,
+                added = inventory.added.len(),
+                removed = inventory.removed.len(),
+                "Reporting installed software"
+            );
+            match self.update_software(&inventory).await {
+                Ok(()) => reported = Some((software, Instant::now())),
+                Err(e) => tracing::error!(
+                    "Failed to update installed software on computing orchestration system: {e}"
+                ),
+            }
+        }
+    }
+
+    /// Installed software of all the enabled deployers,
+    /// or `None` if any of them fails so that its software is not reported as removed
+    async fn installed_software(&self) -> Option<BTreeSet<InstalledSoftware>> {
+        let mut software = BTreeSet::new();
+        for &deployer in &self.deployers {
+            match self.stat.select(deployer).load_installed().await {
+                Ok(installed) => software.extend(
+                    installed.into_iter().map(|options| InstalledSoftware::new(deployer, options)),
+                ),
+                Err(e) => {
+                    tracing::warn!("Failed to load software installed by {deployer}: {e}");
+                    return None;
+                }
+            }
+        }
+        Some(software)
+    }
+
+    async fn update_used(&self) -> anyhow::Result<()> {
         let resources = self.stat.used().await?;
+        self.stat.metrics.record_resources("used", resources.values());
         tracing::info!("Reporting resources: {resources:#?}");
-        self.http_client.post(self.update_url.clone()).json(&resources).send().await?;
+        self.http_client
+            .post(self.used_url.clone())
+            .json(&resources)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn update_software(&self, inventory: &SoftwareInventory<'_>) -> anyhow::Result<()> {
+        self.http_client
+            .post(self.software_url.clone())
+            .json(inventory)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn update_total(&self, resources: &TotalResources) -> anyhow::Result<()> {
+        self.http_client
+            .post(self.total_url.clone())
+            .json(resources)
+            .send()
+            .await?
+            .error_for_status()?;
         Ok(())
     }
 }
@@ -0,0 +1,18 @@
+use std::ops::ControlFlow;
+use std::sync::Arc;
+use std::time::Duration;
+
+use infrastructure::sync::timer;
+
+use crate::infrastructure::ioc::Container;
+use crate::infrastructure::service::health::HealthCheck;
+use crate::infrastructure::service::log_tailer::LogTailer;
+
+pub async fn tail_logs(container: Arc<Container>, interval: Duration) {
+    timer::new::<(), _, _>(interval, || async {
+        HealthCheck::inj_ref(container.as_ref()).beat("tail_logs", interval);
+        LogTailer::inj_ref(container.as_ref()).tail_all().await;
+        ControlFlow::Continue(())
+    })
+    .await;
+}
@@ -1,8 +1,13 @@
 use alice_infrastructure::config::CommonConfig;
+use anyhow::Context;
 use bytesize::ByteSize;
+use domain::model::entity::task::deploy_software::DeployerType;
+use domain::model::vo::job::{ExecMode, GpuFlag, ResubmitPolicy};
 use serde::*;
 use url::Url;
 
+use crate::dto::TaskType;
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct AgentConfig {
     #[serde(default, flatten)]
@@ -21,6 +26,10 @@ pub struct AgentConfig {
     #[serde(default = "AgentConfig::default_refresh_jobs_interval")]
     pub refresh_jobs_interval: u64,
 
+    /// Seconds to wait for running tasks to finish when shutting down
+    #[serde(default = "AgentConfig::default_shutdown_timeout")]
+    pub shutdown_timeout: u64,
+
     #[serde(default = "AgentConfig::default_save_path")]
     pub save_path: String,
 
@@ -50,6 +59,52 @@ pub struct AgentConfig {
 
     #[serde(default = "AgentConfig::default_apptainer")]
     pub apptainer: bool,
+
+    #[serde(default = "Default::default")]
+    pub apptainer_image: ApptainerImageConfig,
+
+    #[serde(default = "Default::default")]
+    pub apptainer_exec: ApptainerExecConfig,
+
+    #[serde(default = "Default::default")]
+    pub conda: CondaConfig,
+
+    #[serde(default = "Default::default")]
+    pub modules: ModulesConfig,
+
+    #[serde(default = "Default::default")]
+    pub software_cache: SoftwareCacheConfig,
+
+    #[serde(default = "Default::default")]
+    pub task_queue: TaskQueueConfig,
+
+    #[serde(default = "Default::default")]
+    pub transfer: TransferConfig,
+
+    #[serde(default = "Default::default")]
+    pub task_policy: TaskPolicyConfig,
+
+    /// Resubmit the jobs which fail for infrastructure reasons
+    #[serde(default = "Default::default")]
+    pub resubmit: ResubmitPolicy,
+
+    #[serde(default = "Default::default")]
+    pub workspace: WorkspaceConfig,
+
+    #[serde(default = "Default::default")]
+    pub admin: AdminConfig,
+
+    #[serde(default = "Default::default")]
+    pub health: HealthConfig,
+
+    #[serde(default = "Default::default")]
+    pub login: LoginConfig,
+
+    #[serde(default = "Default::default")]
+    pub resource_report: ResourceReportConfig,
+
+    #[serde(default = "Default::default")]
+    pub log_tail: LogTailConfig,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -77,9 +132,292 @@ pub struct SshProxyConfig {
     pub save_dir: String,
 }
 
-#[derive(Debug, Clone, Deserialize, Default)]
+/// Max count of running tasks of each type, the others wait in queue
+#[derive(Debug, Clone, Deserialize)]
+pub struct TaskQueueConfig {
+    #[serde(default = "TaskQueueConfig::default_deploy_software")]
+    pub deploy_software: usize,
+
+    #[serde(default = "TaskQueueConfig::default_download_file")]
+    pub download_file: usize,
+
+    #[serde(default = "TaskQueueConfig::default_execute_usecase")]
+    pub execute_usecase: usize,
+
+    #[serde(default = "TaskQueueConfig::default_upload_file")]
+    pub upload_file: usize,
+
+    #[serde(default = "TaskQueueConfig::default_collect_output")]
+    pub collect_output: usize,
+}
+
+/// Limits shared by all file uploads and downloads
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransferConfig {
+    /// Max count of blocks transmitting at the same time for one file
+    #[serde(default = "TransferConfig::default_max_workers_per_file")]
+    pub max_workers_per_file: usize,
+
+    /// Max count of blocks transmitting at the same time for all files
+    #[serde(default = "TransferConfig::default_max_workers")]
+    pub max_workers: usize,
+
+    /// Max bytes transmitted per second for all files, no limit if absent
+    #[serde(default = "Default::default")]
+    pub bandwidth: Option<ByteSize>,
+}
+
+/// Timeout and retry policy of each task type
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TaskPolicyConfig {
+    #[serde(default = "Default::default")]
+    pub deploy_software: TaskPolicy,
+
+    #[serde(default = "Default::default")]
+    pub download_file: TaskPolicy,
+
+    #[serde(default = "Default::default")]
+    pub execute_usecase: TaskPolicy,
+
+    #[serde(default = "Default::default")]
+    pub upload_file: TaskPolicy,
+
+    #[serde(default = "Default::default")]
+    pub collect_output: TaskPolicy,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct TaskPolicy {
+    /// Seconds an attempt can run, no limit if absent
+    #[serde(default = "Default::default")]
+    pub timeout: Option<u64>,
+
+    /// Times to retry after the first attempt fails
+    #[serde(default = "Default::default")]
+    pub retry: u32,
+
+    /// Seconds to wait before retrying
+    #[serde(default = "TaskPolicy::default_retry_interval")]
+    pub retry_interval: u64,
+}
+
+/// Retention rules of the workspaces of nodes
+#[derive(Debug, Clone, Deserialize)]
+pub struct WorkspaceConfig {
+    /// Clean the workspaces periodically or not
+    #[serde(default = "Default::default")]
+    pub enable: bool,
+
+    /// Seconds between two cleanings
+    #[serde(default = "WorkspaceConfig::default_interval")]
+    pub interval: u64,
+
+    /// Hours to keep a workspace since its last modification
+    #[serde(default = "WorkspaceConfig::default_max_age")]
+    pub max_age: u64,
+
+    /// Max total size of the workspaces, the oldest ones are removed when exceeded
+    #[serde(default = "Default::default")]
+    pub quota: Option<ByteSize>,
+
+    /// Keep the workspaces having failed tasks for troubleshooting
+    #[serde(default = "WorkspaceConfig::default_keep_failed")]
+    pub keep_failed: bool,
+
+    /// Remove the workspace once the outputs of the node are uploaded
+    #[serde(default = "WorkspaceConfig::default_remove_after_upload")]
+    pub remove_after_upload: bool,
+}
+
+/// Local HTTP API for inspecting and controlling tasks.
+///
+/// It also serves the metrics and the probes, which are unavailable when it's disabled.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AdminConfig {
+    /// Serve the API or not
+    #[serde(default = "Default::default")]
+    pub enable: bool,
+
+    /// Listen on the Unix socket instead of `host.bind_address:bind_port`
+    #[serde(default = "Default::default")]
+    pub unix_socket: Option<String>,
+
+    /// Token required in the `Authorization: Bearer` header, necessary when listening on TCP
+    #[serde(default = "Default::default")]
+    pub token: Option<String>,
+}
+
+/// Thresholds of the readiness check
+#[derive(Debug, Clone, Deserialize)]
+pub struct HealthConfig {
+    /// Min free space of the save path
+    #[serde(default = "HealthConfig::default_min_free_space")]
+    pub min_free_space: ByteSize,
+}
+
+/// How often to report resources to the computing orchestration system
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResourceReportConfig {
+    /// Seconds between two reports of used resources
+    #[serde(default = "ResourceReportConfig::default_used_interval")]
+    pub used_interval: u64,
+
+    /// Seconds between two reports of total resources
+    #[serde(default = "ResourceReportConfig::default_total_interval")]
+    pub total_interval: u64,
+
+    /// Seconds between two checks of total resources for changes
+    #[serde(default = "ResourceReportConfig::default_check_interval")]
+    pub check_interval: u64,
+
+    /// Report total resources at once when any of them changes by more than this ratio,
+    /// or the number of nodes changes
+    #[serde(default = "ResourceReportConfig::default_change_threshold")]
+    pub change_threshold: f64,
+
+    /// Seconds between two full reports of installed software
+    #[serde(default = "ResourceReportConfig::default_software_interval")]
+    pub software_interval: u64,
+
+    /// Seconds between two checks of installed software, reporting the changes if any
+    #[serde(default = "ResourceReportConfig::default_software_check_interval")]
+    pub software_check_interval: u64,
+}
+
+/// How to obtain Apptainer images
+#[derive(Debug, Clone, Deserialize)]
+pub struct ApptainerImageConfig {
+    /// Build images from definition files with `--fakeroot`
+    #[serde(default = "ApptainerImageConfig::default_fakeroot")]
+    pub fakeroot: bool,
+
+    /// Credentials of private registries, used for `docker://` and `oras://` sources
+    #[serde(default = "Default::default")]
+    pub credentials: Vec<RegistryCredential>,
+}
+
+/// How to run jobs in Apptainer containers
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ApptainerExecConfig {
+    /// `exec` the command in the container,
+    /// or `run` the runscript of the image with the command as its arguments
+    #[serde(default = "Default::default")]
+    pub mode: ExecMode,
+
+    /// Site paths to bind besides the working directory of the job, e.g. `/data:/data:ro`
+    #[serde(default = "Default::default")]
+    pub binds: Vec<String>,
+
+    #[serde(default = "Default::default")]
+    pub overlays: Vec<String>,
+
+    /// Don't pass the host environment, except the environment variables of the job
+    #[serde(default = "Default::default")]
+    pub clean_env: bool,
+
+    /// `nv` or `rocm` to make GPUs available in the container
+    #[serde(default = "Default::default")]
+    pub gpu: Option<GpuFlag>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RegistryCredential {
+    /// Host of the registry like `ghcr.io`, or `docker.io` for Docker Hub
+    pub registry: String,
+
+    pub username: String,
+
+    #[serde(default = "Default::default")]
+    pub password: Option<String>,
+
+    /// File containing the password, e.g. a mounted secret, read on each use
+    #[serde(default = "Default::default")]
+    pub password_file: Option<String>,
+}
+
+/// How to deploy software with conda, mamba or micromamba
+#[derive(Debug, Clone, Deserialize)]
+pub struct CondaConfig {
+    /// Deploy conda packages or not
+    #[serde(default = "Default::default")]
+    pub enable: bool,
+
+    /// `micromamba`, `mamba` or `conda`, or the path to one of them
+    #[serde(default = "CondaConfig::default_executable")]
+    pub executable: String,
+
+    /// Directory keeping the environments on the cluster,
+    /// `.conda-envs` in the save path (or `ssh_proxy.save_dir`) by default
+    #[serde(default = "Default::default")]
+    pub envs_dir: Option<String>,
+}
+
+/// How to find software installed by the site with Environment Modules or Lmod
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ModulesConfig {
+    /// Load modules matching Spack specs when Spack doesn't have them
+    #[serde(default = "Default::default")]
+    pub enable: bool,
+
+    /// A command asking the admin to install a missing package instead of Spack,
+    /// called with the name and Spack parameters and printing the module to load
+    #[serde(default = "Default::default")]
+    pub install_hook: Option<String>,
+}
+
+/// How to evict deployed software no longer used by jobs
+#[derive(Debug, Clone, Deserialize)]
+pub struct SoftwareCacheConfig {
+    /// Evict software periodically or not, it can still be purged through the admin API
+    #[serde(default = "Default::default")]
+    pub enable: bool,
+
+    /// Seconds between two evictions
+    #[serde(default = "SoftwareCacheConfig::default_interval")]
+    pub interval: u64,
+
+    /// Hours to keep software since a job used it last.
+    /// Software with no use recorded, also before restarting, is regarded as used at the start.
+    #[serde(default = "SoftwareCacheConfig::default_max_idle")]
+    pub max_idle: Option<u64>,
+
+    /// Max number of software installed by each deployer,
+    /// the least recently used ones are uninstalled when exceeded
+    #[serde(default = "Default::default")]
+    pub max_installed: Option<usize>,
+}
+
+/// How to stream the outputs of running jobs to the backend
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogTailConfig {
+    /// Stream the outputs or not
+    #[serde(default = "LogTailConfig::default_enable")]
+    pub enable: bool,
+
+    /// Seconds between two reads of the outputs
+    #[serde(default = "LogTailConfig::default_interval")]
+    pub interval: u64,
+
+    /// Max size of each output sent per interval, the rest is sent in the next intervals.
+    /// The logs of failed deployments are sent in chunks of this size too, even if disabled.
+    #[serde(default = "LogTailConfig::default_chunk_size")]
+    pub chunk_size: ByteSize,
+}
+
+/// How to login without a person approving the device
+#[derive(Debug, Clone, Deserialize)]
 pub struct LoginConfig {
-    pub client_id: String,
+    /// Secret of a confidential client, logging in with the client credentials grant if set
+    #[serde(default = "Default::default")]
+    pub client_secret: Option<String>,
+
+    /// Request an offline token in the device flow, which outlives the SSO session
+    #[serde(default = "Default::default")]
+    pub offline: bool,
+
+    /// File keeping the refresh token to reuse on restart, relative to the save path
+    #[serde(default = "LoginConfig::default_token_file")]
+    pub token_file: String,
 }
 
 impl AgentConfig {
@@ -107,6 +445,10 @@ impl AgentConfig {
         60
     }
 
+    pub fn default_shutdown_timeout() -> u64 {
+        30
+    }
+
     pub fn default_mpi() -> bool {
         true
     }
@@ -118,6 +460,20 @@ impl AgentConfig {
     pub fn default_apptainer() -> bool {
         true
     }
+
+    /// The enabled software deployers
+    pub fn deployers(&self) -> Vec<DeployerType> {
+        [
+            (DeployerType::Spack, self.spack),
+            (DeployerType::SpackEnv, self.spack),
+            (DeployerType::Apptainer, self.apptainer),
+            (DeployerType::Conda, self.conda.enable),
+            (DeployerType::Module, self.modules.enable),
+        ]
+        .into_iter()
+        .filter_map(|(deployer, enable)| enable.then_some(deployer))
+        .collect()
+    }
 }
 
 impl Default for SchedulerConfig {
@@ -135,6 +491,278 @@ impl SchedulerConfig {
     }
 }
 
+impl Default for TaskQueueConfig {
+    fn default() -> Self {
+        Self {
+            deploy_software: Self::default_deploy_software(),
+            download_file: Self::default_download_file(),
+            execute_usecase: Self::default_execute_usecase(),
+            upload_file: Self::default_upload_file(),
+            collect_output: Self::default_collect_output(),
+        }
+    }
+}
+
+impl TaskQueueConfig {
+    pub fn default_deploy_software() -> usize {
+        5
+    }
+
+    pub fn default_download_file() -> usize {
+        8
+    }
+
+    pub fn default_execute_usecase() -> usize {
+        16
+    }
+
+    pub fn default_upload_file() -> usize {
+        8
+    }
+
+    pub fn default_collect_output() -> usize {
+        16
+    }
+}
+
+impl Default for TransferConfig {
+    fn default() -> Self {
+        Self {
+            max_workers_per_file: Self::default_max_workers_per_file(),
+            max_workers: Self::default_max_workers(),
+            bandwidth: None,
+        }
+    }
+}
+
+impl TransferConfig {
+    pub fn default_max_workers_per_file() -> usize {
+        16
+    }
+
+    pub fn default_max_workers() -> usize {
+        32
+    }
+}
+
+impl TaskPolicyConfig {
+    pub fn of(&self, r#type: &TaskType) -> TaskPolicy {
+        match r#type {
+            TaskType::DeploySoftware => self.deploy_software,
+            TaskType::DownloadFile => self.download_file,
+            TaskType::ExecuteUsecase => self.execute_usecase,
+            TaskType::UploadFile => self.upload_file,
+            TaskType::CollectOuput => self.collect_output,
+        }
+    }
+}
+
+impl Default for TaskPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: None,
+            retry: 0,
+            retry_interval: Self::default_retry_interval(),
+        }
+    }
+}
+
+impl TaskPolicy {
+    pub fn default_retry_interval() -> u64 {
+        10
+    }
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            interval: Self::default_interval(),
+            max_age: Self::default_max_age(),
+            quota: None,
+            keep_failed: Self::default_keep_failed(),
+            remove_after_upload: Self::default_remove_after_upload(),
+        }
+    }
+}
+
+impl WorkspaceConfig {
+    pub fn default_interval() -> u64 {
+        10 * 60
+    }
+
+    pub fn default_max_age() -> u64 {
+        7 * 24
+    }
+
+    pub fn default_keep_failed() -> bool {
+        true
+    }
+
+    pub fn default_remove_after_upload() -> bool {
+        true
+    }
+}
+
+impl Default for SoftwareCacheConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            interval: Self::default_interval(),
+            max_idle: Self::default_max_idle(),
+            max_installed: None,
+        }
+    }
+}
+
+impl SoftwareCacheConfig {
+    pub fn default_interval() -> u64 {
+        60 * 60
+    }
+
+    pub fn default_max_idle() -> Option<u64> {
+        Some(30 * 24)
+    }
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            min_free_space: Self::default_min_free_space(),
+        }
+    }
+}
+
+impl HealthConfig {
+    pub fn default_min_free_space() -> ByteSize {
+        ByteSize::gib(1)
+    }
+}
+
+impl Default for ResourceReportConfig {
+    fn default() -> Self {
+        Self {
+            used_interval: Self::default_used_interval(),
+            total_interval: Self::default_total_interval(),
+            check_interval: Self::default_check_interval(),
+            change_threshold: Self::default_change_threshold(),
+            software_interval: Self::default_software_interval(),
+            software_check_interval: Self::default_software_check_interval(),
+        }
+    }
+}
+
+impl ResourceReportConfig {
+    pub fn default_used_interval() -> u64 {
+        60 * 60
+    }
+
+    pub fn default_total_interval() -> u64 {
+        60 * 60
+    }
+
+    pub fn default_check_interval() -> u64 {
+        60
+    }
+
+    pub fn default_change_threshold() -> f64 {
+        0.05
+    }
+
+    pub fn default_software_interval() -> u64 {
+        60 * 60 * 24
+    }
+
+    pub fn default_software_check_interval() -> u64 {
+        60 * 10
+    }
+}
+
+impl Default for ApptainerImageConfig {
+    fn default() -> Self {
+        Self {
+            fakeroot: Self::default_fakeroot(),
+            credentials: vec![],
+        }
+    }
+}
+
+impl ApptainerImageConfig {
+    pub fn default_fakeroot() -> bool {
+        true
+    }
+}
+
+impl RegistryCredential {
+    pub async fn password(&self) -> anyhow::Result<String> {
+        match (&self.password, &self.password_file) {
+            (Some(password), _) => Ok(password.clone()),
+            (None, Some(file)) => Ok(tokio::fs::read_to_string(file)
+                .await
+                .with_context(|| format!("Unable to read the password of {}", self.registry))?
+                .trim()
+                .to_owned()),
+            (None, None) => anyhow::bail!("No password for {}", self.registry),
+        }
+    }
+}
+
+impl Default for CondaConfig {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            executable: Self::default_executable(),
+            envs_dir: None,
+        }
+    }
+}
+
+impl CondaConfig {
+    pub fn default_executable() -> String {
+        "micromamba".to_owned()
+    }
+}
+
+impl Default for LogTailConfig {
+    fn default() -> Self {
+        Self {
+            enable: Self::default_enable(),
+            interval: Self::default_interval(),
+            chunk_size: Self::default_chunk_size(),
+        }
+    }
+}
+
+impl LogTailConfig {
+    pub fn default_enable() -> bool {
+        true
+    }
+
+    pub fn default_interval() -> u64 {
+        10
+    }
+
+    pub fn default_chunk_size() -> ByteSize {
+        ByteSize::kib(64)
+    }
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        Self {
+            client_secret: None,
+            offline: false,
+            token_file: Self::default_token_file(),
+        }
+    }
+}
+
+impl LoginConfig {
+    pub fn default_token_file() -> String {
+        ".refresh_token".to_owned()
+    }
+}
+
 impl Default for SshProxyConfig {
     fn default() -> Self {
         Self {
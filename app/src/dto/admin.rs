@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+
+use domain::model::entity::{job::JobState, task::TaskStatus};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// 按类型分组的运行中任务
+pub type ActiveTasks = BTreeMap<&'static str, Vec<ActiveTaskInfo>>;
+
+/// 运行中的任务
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveTaskInfo {
+    /// 任务 id
+    pub id: Uuid,
+    /// 节点 id，重启前提交的作业没有记录
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<Uuid>,
+    /// 最近上报的任务状态
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<TaskStatus>,
+    /// 调度器中的作业 id
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_id: Option<String>,
+    /// 调度器中的作业状态
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub job_state: Option<JobState>,
+    /// 文件传输进度
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<TransferProgress>,
+}
+
+/// 文件传输进度，以分块计
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransferProgress {
+    /// 总块数
+    pub total_blocks: u64,
+    /// 已完成的块数
+    pub finished_blocks: u64,
+}
+
+/// 管理接口对任务的操作
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AdminCommand {
+    Pause,
+    Resume,
+    Cancel,
+}
+
+/// 就绪检查结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Readiness {
+    /// 所有检查都通过
+    pub ready: bool,
+    /// 各项检查的结果
+    pub checks: BTreeMap<&'static str, CheckResult>,
+}
+
+/// 单项检查的结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckResult {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Readiness {
+    pub fn new(checks: impl IntoIterator<Item = (&'static str, CheckResult)>) -> Self {
+        let checks: BTreeMap<_, _> = checks.into_iter().collect();
+        Self {
+            ready: checks.values().all(|check| check.ok),
+            checks,
+        }
+    }
+}
@@ -1,11 +1,17 @@
+pub mod admin;
 pub mod reply;
+pub mod software_inventory;
 pub mod task;
+pub mod task_log;
 pub mod text_storage;
 pub mod upload;
 
 #[rustfmt::skip]
 pub use self::{
+    admin::*,
     reply::*,
+    software_inventory::*,
     task::*,
+    task_log::*,
     upload::*,
 };
@@ -0,0 +1,122 @@
+use std::collections::BTreeSet;
+
+use domain::model::entity::{task::deploy_software::DeployerType, SoftwareInstallOptions};
+use serde::Serialize;
+
+/// 已安装的软件
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstalledSoftware {
+    /// 部署器，如 spack、apptainer
+    pub deployer: String,
+    pub name: String,
+    pub version: String,
+    /// 变体，如 spack 的 `+cuda`、conda 的频道
+    pub variants: Vec<String>,
+    /// 部署器中的标识，如 spack 哈希、镜像路径
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl InstalledSoftware {
+    pub fn new(deployer: DeployerType, options: SoftwareInstallOptions) -> Self {
+        let mut variants = options.parameters;
+        variants.sort();
+        Self {
+            deployer: deployer.to_string(),
+            name: options.name,
+            version: options.version,
+            variants,
+            hash: options.hash,
+        }
+    }
+}
+
+/// 软件清单的变化，`full` 为真时 `added` 即为全部软件
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SoftwareInventory<'a> {
+    pub full: bool,
+    pub added: Vec<&'a InstalledSoftware>,
+    pub removed: Vec<&'a InstalledSoftware>,
+}
+
+impl<'a> SoftwareInventory<'a> {
+    pub fn full(current: &'a BTreeSet<InstalledSoftware>) -> Self {
+        Self {
+            full: true,
+            added: current.iter().collect(),
+            removed: vec![],
+        }
+    }
+
+    pub fn diff(
+        previous: &'a BTreeSet<InstalledSoftware>,
+        current: &'a BTreeSet<InstalledSoftware>,
+    ) -> Self {
+        Self {
+            full: false,
+            added: current.difference(previous).collect(),
+            removed: previous.difference(current).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.full && self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// 淘汰软件的结果
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvictionReport {
+    /// 为真时只列出将被淘汰的软件，并未卸载
+    pub dry_run: bool,
+    pub evicted: Vec<EvictedSoftware>,
+    /// 卸载失败的软件
+    pub failed: Vec<EvictedSoftware>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EvictedSoftware {
+    #[serde(flatten)]
+    pub software: InstalledSoftware,
+    /// 距上次被作业使用的秒数
+    pub idle: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use domain::model::entity::{task::deploy_software::DeployerType, SoftwareInstallOptions};
+
+    use super::{InstalledSoftware, SoftwareInventory};
+
+    fn software(name: &str, version: &str) -> InstalledSoftware {
+        InstalledSoftware::new(
+            DeployerType::Spack,
+            SoftwareInstallOptions {
+                parameters: vec!["mpi".to_owned(), "cuda".to_owned()],
+                version: version.to_owned(),
+                name: name.to_owned(),
+                hash: None,
+            },
+        )
+    }
+
+    #[test]
+    fn test_software_inventory_diff() {
+        let previous = BTreeSet::from([software("gromacs", "2023.1"), software("lammps", "2023")]);
+        let current = BTreeSet::from([software("gromacs", "2023.1"), software("lammps", "2024")]);
+
+        let diff = SoftwareInventory::diff(&previous, &current);
+        assert_eq!(diff.added, [&software("lammps", "2024")]);
+        assert_eq!(diff.removed, [&software("lammps", "2023")]);
+        assert_eq!(diff.added[0].deployer, "spack");
+        assert_eq!(diff.added[0].variants, ["cuda", "mpi"]);
+        assert!(SoftwareInventory::diff(&current, &current).is_empty());
+        assert!(!SoftwareInventory::full(&current).is_empty());
+    }
+}
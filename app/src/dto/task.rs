@@ -1,4 +1,5 @@
 use domain::model::entity::{job::JobResources, task::*};
+use domain::service::TaskEntity;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -23,6 +24,15 @@ pub enum TaskCommand {
 #[serde(rename_all = "camelCase")]
 pub struct TaskStart {
     pub node_id: Uuid,
+    /// 排队优先级，越大越先执行
+    #[serde(default)]
+    pub priority: i32,
+    /// 单次执行的超时时间（s），覆盖该类任务的默认配置
+    #[serde(default)]
+    pub timeout: Option<u64>,
+    /// 失败后的重试次数，覆盖该类任务的默认配置
+    #[serde(default)]
+    pub retry: Option<u32>,
     #[serde(flatten)]
     pub body: StartTaskBody,
 }
@@ -35,14 +45,14 @@ pub enum StartTaskBody {
     /// 文件下载
     DownloadFile(DownloadFile),
     /// 用例执行
-    ExecuteUsecase(ExecuteUsecase),
+    ExecuteUsecase(Box<ExecuteUsecase>),
     /// 文件上传
     UploadFile(UploadFile),
     /// 输出收集
     CollectOuput(CollectOutput),
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
 #[serde(tag = "type")]
 pub enum TaskType {
     /// 软件部署
@@ -95,7 +105,7 @@ impl StartTaskBody {
 }
 
 impl TaskType {
-    pub fn to_str(&self) -> &'static str {
+    pub fn to_str(self) -> &'static str {
         match self {
             Self::DeploySoftware => "deploy software",
             Self::DownloadFile => "download file",
@@ -104,6 +114,17 @@ impl TaskType {
             Self::CollectOuput => "collect output",
         }
     }
+
+    /// The name of the task entity, i.e. [`TaskEntity::TYPE`]
+    pub fn entity_type(self) -> &'static str {
+        match self {
+            Self::DeploySoftware => DeploySoftware::TYPE,
+            Self::DownloadFile => DownloadFile::TYPE,
+            Self::ExecuteUsecase => ExecuteUsecase::TYPE,
+            Self::UploadFile => UploadFile::TYPE,
+            Self::CollectOuput => CollectOutput::TYPE,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -139,6 +160,32 @@ mod tests {
         serde_json::from_str::<TaskStart>(s).unwrap();
     }
 
+    #[test]
+    fn test_start_options() {
+        let s = indoc! {
+            r#"{
+                 "id": "46099d7c-a982-41a0-9370-cac6df35114e",
+                 "command": "Start",
+                 "nodeId": "10b712f0-5577-4f79-a582-330b51abbc13",
+                 "priority": 3,
+                 "timeout": 600,
+                 "retry": 2,
+                 "type": "DeploySoftware",
+                 "body": {
+                   "facilityKind": {
+                     "type": "Singularity",
+                     "image": "ubuntu",
+                     "tag": "22.04"
+                   }
+                 }
+               }"#
+        };
+        let start = serde_json::from_str::<TaskStart>(s).unwrap();
+        assert_eq!(start.priority, 3);
+        assert_eq!(start.timeout, Some(600));
+        assert_eq!(start.retry, Some(2));
+    }
+
     #[test]
     fn test_other() {
         let s = indoc! {
@@ -0,0 +1,36 @@
+use serde::Serialize;
+use uuid::Uuid;
+
+/// 运行中作业或部署失败的软件的一段输出
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskLog<'a> {
+    pub id: Uuid,
+    pub stream: LogStream,
+    /// 这段输出在文件中的字节偏移量
+    pub offset: u64,
+    pub content: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+    /// 部署软件的完整日志
+    Deploy,
+}
+
+impl LogStream {
+    /// 作业的输出
+    pub const ALL: [Self; 2] = [Self::Stdout, Self::Stderr];
+
+    /// 输出所在的文件名
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Self::Stdout => "STDOUT",
+            Self::Stderr => "STDERR",
+            Self::Deploy => "DEPLOY",
+        }
+    }
+}
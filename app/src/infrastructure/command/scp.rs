@@ -3,7 +3,8 @@ use std::path::Path;
 
 use tokio::process::Command;
 
-use super::ssh_proxy::SshConfig;
+use super::ssh_proxy::{SshConfig, TRACEPARENT};
+use crate::infrastructure::trace::traceparent;
 
 pub trait Scp {
     fn scp(&self) -> Option<(ScpCommand, &SshConfig)>;
@@ -15,8 +16,13 @@ where
 {
     fn scp(&self) -> Option<(ScpCommand, &SshConfig)> {
         self.as_ref().as_ref().map(|ssh| {
+            tracing::debug!(host = %ssh.username_host, "Copying files through scp");
             let mut base = Command::new("scp");
             base.args(["-P", &ssh.port]);
+            // Only seen remotely if the server accepts it with `AcceptEnv`
+            if let Some(traceparent) = traceparent() {
+                base.args(["-o", &format!("SetEnv={TRACEPARENT}={traceparent}")]);
+            }
             (ScpCommand { ssh, base }, ssh)
         })
     }
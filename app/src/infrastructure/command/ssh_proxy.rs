@@ -1,6 +1,7 @@
 use tokio::process::Command;
 
 use crate::config::SshProxyConfig;
+use crate::infrastructure::trace::traceparent;
 
 #[derive(Debug, Clone)]
 pub struct SshConfig {
@@ -10,6 +11,9 @@ pub struct SshConfig {
     pub save_dir: String,
 }
 
+/// The variable passing the trace context to subprocesses
+pub(super) const TRACEPARENT: &str = "TRACEPARENT";
+
 /// An ssh proxy for command. It's transparent if not using ssh.
 pub trait MaybeSsh {
     fn command(&self, cmd: &str) -> Command;
@@ -22,11 +26,22 @@ where
 {
     fn command(&self, cmd: &str) -> Command {
         let Some(ssh) = self.as_ref() else {
-            return Command::new(cmd);
+            tracing::debug!(cmd, "Running command");
+            let mut command = Command::new(cmd);
+            if let Some(traceparent) = traceparent() {
+                command.env(TRACEPARENT, traceparent);
+            }
+            return command;
         };
 
+        tracing::debug!(cmd, host = %ssh.username_host, "Running command through ssh");
         let mut command = Command::new("ssh");
-        command.args(["-p", &ssh.port, &ssh.username_host, cmd]);
+        command.args(["-p", &ssh.port, &ssh.username_host]);
+        // The remote command line may chain several commands, so the variable is exported
+        if let Some(traceparent) = traceparent() {
+            command.arg(format!("export {TRACEPARENT}={traceparent};"));
+        }
+        command.arg(cmd);
         command
     }
 
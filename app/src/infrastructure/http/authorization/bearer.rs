@@ -43,6 +43,17 @@ impl JwtPayload {
     }
 }
 
+/// The expiration time in the claims of the token, as a Unix timestamp
+pub fn expiration(token: &str) -> Option<i64> {
+    #[derive(Deserialize)]
+    struct Claims {
+        exp: Option<i64>,
+    }
+
+    let data = base64_url::decode(token.split('.').nth(1)?).ok()?;
+    serde_json::from_slice::<Claims>(&data).ok()?.exp
+}
+
 impl FromStr for JwtPayload {
     type Err = anyhow::Error;
 
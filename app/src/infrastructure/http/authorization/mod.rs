@@ -1,3 +1,3 @@
 mod bearer;
 
-pub use self::bearer::{Bearer, JwtPayload};
+pub use self::bearer::{expiration, Bearer, JwtPayload};
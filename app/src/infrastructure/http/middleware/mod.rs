@@ -1,6 +1,7 @@
 mod authorization;
 mod retry;
 mod timeout;
+mod trace;
 
 use std::sync::Arc;
 use std::time::Duration;
@@ -9,11 +10,14 @@ use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
 use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
 use typed_builder::TypedBuilder;
 
+use crate::infrastructure::metrics::Metrics;
+
 #[rustfmt::skip]
 pub use self::{
     authorization::AuthMiddleware,
     retry::{RetryOnError, RetryStreamClient},
     timeout::TimeoutMiddleware,
+    trace::TraceMiddleware,
 };
 
 #[derive(TypedBuilder)]
@@ -22,6 +26,7 @@ pub struct MiddlewareMenu {
     retries: Option<u32>,
     auth: Arc<AuthMiddleware>,
     timeout: Duration,
+    metrics: Arc<Metrics>,
 }
 
 impl MiddlewareMenu {
@@ -30,13 +35,14 @@ impl MiddlewareMenu {
             retries,
             auth,
             timeout,
+            metrics,
         } = self;
 
         /*
          * Pay attention here.
          * The correct order to attach our middlewares is
          *  ```
-         *  (Retry ->) Auth -> Timeout
+         *  Trace -> (Retry ->) Auth -> Timeout
          *  ```
          *  because middlewares run in the order they were attached.
          *
@@ -44,10 +50,13 @@ impl MiddlewareMenu {
          *  Using [`RetryStreamRequest`].
          */
 
-        let cb = ClientBuilder::new(reqwest::Client::new());
+        let cb = ClientBuilder::new(reqwest::Client::new()).with(TraceMiddleware);
         let cb = if let Some(retries) = retries {
             let policy = ExponentialBackoff::builder().build_with_max_retries(retries);
-            let mdw = RetryTransientMiddleware::new_with_policy_and_strategy(policy, RetryOnError);
+            let mdw = RetryTransientMiddleware::new_with_policy_and_strategy(
+                policy,
+                RetryOnError::new(metrics),
+            );
             cb.with(mdw)
         } else {
             cb
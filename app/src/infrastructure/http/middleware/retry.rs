@@ -1,4 +1,5 @@
 use std::future::Future;
+use std::sync::Arc;
 
 use chrono::Utc;
 use reqwest::{Response, StatusCode};
@@ -9,11 +10,21 @@ use reqwest_retry::{
 };
 use retry_policies::RetryDecision;
 
-pub struct RetryOnError;
+use crate::infrastructure::metrics::Metrics;
+
+pub struct RetryOnError {
+    metrics: Arc<Metrics>,
+}
+
+impl RetryOnError {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
 
 impl RetryableStrategy for RetryOnError {
     fn handle(&self, res: &reqwest_middleware::Result<Response>) -> Option<Retryable> {
-        match res {
+        let retryable = match res {
             // [`TimeoutMiddleware`] returns `Error::Middleware` when timeout,
             // [`reqwest_retry::DefaultRetryableStrategy`] won't retry in such case.
             Err(reqwest_middleware::Error::Middleware(_)) => Some(Retryable::Transient),
@@ -26,7 +37,12 @@ impl RetryableStrategy for RetryOnError {
                     default_on_request_success(resp)
                 }
             }
+        };
+
+        if matches!(retryable, Some(Retryable::Transient)) {
+            self.metrics.http_retries.inc();
         }
+        retryable
     }
 }
 
@@ -0,0 +1,39 @@
+use reqwest::{Request, Response};
+use reqwest_middleware::{Middleware, Next};
+use task_local_extensions::Extensions;
+use tracing::{Instrument, Span};
+
+use crate::infrastructure::trace::inject_context;
+
+/// Send the request in a span, passing its trace context to the server.
+///
+/// It should be the **first** middleware of client so that retries are in the same span.
+pub struct TraceMiddleware;
+
+#[async_trait::async_trait]
+impl Middleware for TraceMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> reqwest_middleware::Result<Response> {
+        let span = tracing::info_span!(
+            "http",
+            method = %req.method(),
+            url = %req.url(),
+            status = tracing::field::Empty,
+        );
+        async move {
+            let mut req = req;
+            inject_context(req.headers_mut());
+            let resp = next.run(req, extensions).await;
+            if let Ok(resp) = &resp {
+                Span::current().record("status", resp.status().as_u16());
+            }
+            resp
+        }
+        .instrument(span)
+        .await
+    }
+}
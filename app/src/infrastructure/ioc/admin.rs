@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+
+use domain::model::entity::Job;
+use uuid::Uuid;
+
+use super::Container;
+use crate::dto::{ActiveTaskInfo, ActiveTasks, AdminCommand, TaskType, TransferProgress};
+use crate::infrastructure::service::{
+    download_file::DownloadFileService, task_registry::ActiveTask, upload_file::UploadFileService,
+    SelectTaskService,
+};
+
+impl Container {
+    /// Tasks not ended yet, grouped by type
+    pub async fn active_tasks(&self) -> ActiveTasks {
+        let mut jobs: HashMap<Uuid, Job> = self.job.jobs().into_iter().collect();
+        let mut tasks = ActiveTasks::new();
+
+        for (id, task) in self.task_registry.list() {
+            let job = jobs.remove(&id);
+            let (r#type, info) = self.task_info(id, Some(task), job).await;
+            tasks.entry(r#type.to_str()).or_default().push(info);
+        }
+        // Jobs watched again after restarting aren't started by this agent
+        for (id, job) in jobs {
+            let (r#type, info) = self.task_info(id, None, Some(job)).await;
+            tasks.entry(r#type.to_str()).or_default().push(info);
+        }
+
+        for group in tasks.values_mut() {
+            group.sort_by_key(|task| task.id);
+        }
+        tasks
+    }
+
+    /// The task if it hasn't ended yet
+    pub async fn active_task(&self, id: Uuid) -> Option<(TaskType, ActiveTaskInfo)> {
+        let task = self.task_registry.get(id);
+        let job = self.job.job(id);
+        if task.is_none() && job.is_none() {
+            return None;
+        }
+        Some(self.task_info(id, task, job).await)
+    }
+
+    /// Refresh the metrics of the running tasks, and encode all metrics
+    pub async fn encode_metrics(&self) -> prometheus::Result<String> {
+        let metrics = &self.metrics;
+        let mut jobs: HashMap<Uuid, Job> = self.job.jobs().into_iter().collect();
+
+        metrics.active_tasks.reset();
+        for (id, task) in self.task_registry.list() {
+            jobs.remove(&id);
+            let status = task.status.to_string();
+            metrics
+                .active_tasks
+                .with_label_values(&[task.r#type.entity_type(), &status])
+                .inc();
+        }
+        // Jobs watched again after restarting aren't started by this agent
+        let restored = TaskType::ExecuteUsecase.entity_type();
+        metrics
+            .active_tasks
+            .with_label_values(&[restored, "unknown"])
+            .add(jobs.len() as i64);
+
+        metrics.transfer_throughput.reset();
+        let downloads = DownloadFileService::inj_ref(self).throughputs().await;
+        let uploads = UploadFileService::inj_ref(self).throughputs().await;
+        for (direction, throughputs) in [("download", downloads), ("upload", uploads)] {
+            for (id, throughput) in throughputs {
+                let id = id.to_string();
+                metrics.transfer_throughput.with_label_values(&[direction, &id]).set(throughput);
+            }
+        }
+
+        metrics.encode()
+    }
+
+    /// Pause, resume or cancel the task
+    ///
+    /// # return
+    ///
+    /// `false` if the task is not found.
+    pub async fn control(&self, id: Uuid, command: AdminCommand) -> bool {
+        let Some(r#type) = self.active_task_type(id) else {
+            return false;
+        };
+
+        match command {
+            AdminCommand::Pause => self.pause(r#type, id).await,
+            AdminCommand::Resume => self.resume(r#type, id).await,
+            AdminCommand::Cancel => self.cancel(r#type, id).await,
+        }
+        true
+    }
+
+    fn active_task_type(&self, id: Uuid) -> Option<TaskType> {
+        match self.task_registry.get(id) {
+            Some(task) => Some(task.r#type),
+            None => self.job.job(id).map(|_| TaskType::ExecuteUsecase),
+        }
+    }
+
+    async fn task_info(
+        &self,
+        id: Uuid,
+        task: Option<ActiveTask>,
+        job: Option<Job>,
+    ) -> (TaskType, ActiveTaskInfo) {
+        let r#type = task.as_ref().map_or(TaskType::ExecuteUsecase, |task| task.r#type);
+        let info = ActiveTaskInfo {
+            id,
+            node_id: task.as_ref().map(|task| task.node_id),
+            status: task.map(|task| task.status),
+            job_id: job.as_ref().map(|job| job.id.to_string()),
+            job_state: job.map(|job| job.state),
+            progress: self.transfer_progress(r#type, id).await,
+        };
+        (r#type, info)
+    }
+
+    async fn transfer_progress(&self, r#type: TaskType, id: Uuid) -> Option<TransferProgress> {
+        match r#type {
+            TaskType::DownloadFile => DownloadFileService::inj_ref(self).progress(id).await,
+            TaskType::UploadFile => UploadFileService::inj_ref(self).progress(id).await,
+            _ => None,
+        }
+    }
+}
@@ -1,42 +1,58 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::pin;
+use std::time::Duration;
+
 use domain::{
     model::{
         entity::{
             job::JobResources,
-            task::{collect_output::*, deploy_software::DeployerType, TaskStatus},
+            task::{collect_output::*, deploy_software::DeployerType, ExecuteUsecase, TaskStatus},
             Job, Task,
         },
-        vo::job::ScriptInfo,
+        vo::job::{QueueEstimate, ScriptInfo},
     },
     service::{
-        FileLoadService, JobResourcesReporter, JobScheduler, JobService, SelectSoftwareDeployer,
-        SoftwareDeployer, TaskEntity, TaskService, TaskStatusReporter,
+        DeployLogStore, FileLoadService, JobResourcesReporter, JobScheduler, JobService,
+        SelectSoftwareDeployer, SoftwareDeployer, TaskEntity, TaskService, TaskStatusReporter,
     },
 };
 use service::{
     collect_output::CollectOutputService, deploy_software::DeploySoftwareService,
     job::JobServiceImpl,
 };
+use tokio::time::timeout;
+use tracing::Instrument;
 use uuid::Uuid;
 
 use super::container::JobSchedulerState;
 use super::Container;
 use crate::{
-    dto::{StartTaskBody, TaskType},
+    config::TaskPolicy,
+    dto::{StartTaskBody, TaskStart, TaskType},
     infrastructure::service::{
+        deploy_log::DeployLog,
         download_file::DownloadFileService,
         file_load::FileLoadServiceImpl,
         job_scheduler::{LsfClient, PbsClient, SlurmClient},
         resource_stat::{
-            Pbs, ResourceStat, ResourceStatImpl, SchedulerStat, SchedulerTotalResources,
-            SchedulerUsedResources, Slurm, TotalResources, UsedResources,
+            Inventory, Lsf, Pbs, ResourceStat, ResourceStatImpl, SchedulerStat,
+            SchedulerTotalResources, SchedulerUsedResources, Slurm, TotalResources, UsedResources,
+        },
+        software_deployer::{
+            ApptainerDeployer, CondaDeployer, ModuleDeployer, SpackDeployer, SpackEnvDeployer,
         },
-        software_deployer::{ApptainerDeployer, SpackDeployer},
+        task_queue::TaskQueue,
         task_status_reporter::TaskStatusReporterImpl,
         upload_file::UploadFileService,
+        workspace::WorkspaceManager,
         SelectTaskService,
     },
 };
 
+/// Time for a timed out attempt to stop after cancelled
+const STOP_TIMEOUT: Duration = Duration::from_secs(60);
+
 #[async_trait::async_trait]
 impl FileLoadService for Container {
     async fn load_file(&self, node_id: Uuid, from: &CollectFrom) -> anyhow::Result<String> {
@@ -57,10 +73,22 @@ impl FileLoadService for Container {
 #[async_trait::async_trait]
 impl<T: TaskEntity> TaskStatusReporter<T> for Container {
     async fn report(&self, id: Uuid, status: TaskStatus) -> anyhow::Result<()> {
+        if self.is_stopping(id, &status) {
+            return Ok(());
+        }
+        self.count_report(T::TYPE, &status);
+        self.task_registry.update(id, &status);
+        WorkspaceManager::inj_ref(self).on_status(id, &status).await;
         TaskStatusReporter::<T>::report(TaskStatusReporterImpl::inj_ref(self), id, status).await
     }
 
     async fn report_msg(&self, id: Uuid, status: TaskStatus, message: &str) -> anyhow::Result<()> {
+        if self.is_stopping(id, &status) {
+            return Ok(());
+        }
+        self.count_report(T::TYPE, &status);
+        self.task_registry.update(id, &status);
+        WorkspaceManager::inj_ref(self).on_status(id, &status).await;
         TaskStatusReporter::<T>::report_msg(
             TaskStatusReporterImpl::inj_ref(self),
             id,
@@ -79,6 +107,9 @@ impl JobResourcesReporter for Container {
         status: TaskStatus,
         resources: JobResources,
     ) -> anyhow::Result<()> {
+        self.count_report(ExecuteUsecase::TYPE, &status);
+        self.task_registry.update(id, &status);
+        WorkspaceManager::inj_ref(self).on_status(id, &status).await;
         TaskStatusReporterImpl::inj_ref(self)
             .report_resources(id, status, resources)
             .await
@@ -89,73 +120,147 @@ impl SelectSoftwareDeployer for Container {
     fn select(&self, r#type: DeployerType) -> &(dyn SoftwareDeployer + Send + Sync) {
         match r#type {
             DeployerType::Spack => SpackDeployer::inj_ref(self),
+            DeployerType::SpackEnv => SpackEnvDeployer::inj_ref(self),
             DeployerType::Apptainer => ApptainerDeployer::inj_ref(self),
+            DeployerType::Conda => CondaDeployer::inj_ref(self),
+            DeployerType::Module => ModuleDeployer::inj_ref(self),
         }
     }
 }
 
+#[async_trait::async_trait]
+impl DeployLogStore for Container {
+    async fn save_deploy_log(&self, id: Uuid, log: &str) -> anyhow::Result<String> {
+        DeployLog::inj_ref(self).save_deploy_log(id, log).await
+    }
+}
+
 #[async_trait::async_trait]
 impl JobScheduler for Container {
     async fn get_jobs(&self) -> anyhow::Result<Vec<Job>> {
-        match self.job_scheduler {
-            JobSchedulerState::Pbs(_) => PbsClient::inj_ref(self).get_jobs().await,
-            JobSchedulerState::Slurm(_) => SlurmClient::inj_ref(self).get_jobs().await,
-            JobSchedulerState::Lsf(_) => LsfClient::inj_ref(self).get_jobs().await,
-        }
+        self.metrics
+            .scheduler_command("get_jobs", async {
+                match self.job_scheduler {
+                    JobSchedulerState::Pbs(_) => PbsClient::inj_ref(self).get_jobs().await,
+                    JobSchedulerState::Slurm(_) => SlurmClient::inj_ref(self).get_jobs().await,
+                    JobSchedulerState::Lsf(_) => LsfClient::inj_ref(self).get_jobs().await,
+                }
+            })
+            .await
     }
 
     async fn get_job(&self, id: &str) -> anyhow::Result<Job> {
-        match self.job_scheduler {
-            JobSchedulerState::Pbs(_) => PbsClient::inj_ref(self).get_job(id).await,
-            JobSchedulerState::Slurm(_) => SlurmClient::inj_ref(self).get_job(id).await,
-            JobSchedulerState::Lsf(_) => LsfClient::inj_ref(self).get_job(id).await,
-        }
+        self.metrics
+            .scheduler_command("get_job", async {
+                match self.job_scheduler {
+                    JobSchedulerState::Pbs(_) => PbsClient::inj_ref(self).get_job(id).await,
+                    JobSchedulerState::Slurm(_) => SlurmClient::inj_ref(self).get_job(id).await,
+                    JobSchedulerState::Lsf(_) => LsfClient::inj_ref(self).get_job(id).await,
+                }
+            })
+            .await
     }
 
     async fn submit_job_script(&self, script_info: ScriptInfo) -> anyhow::Result<String> {
-        match self.job_scheduler {
-            JobSchedulerState::Pbs(_) => {
-                PbsClient::inj_ref(self).submit_job_script(script_info).await
-            }
-            JobSchedulerState::Slurm(_) => {
-                SlurmClient::inj_ref(self).submit_job_script(script_info).await
-            }
-            JobSchedulerState::Lsf(_) => {
-                LsfClient::inj_ref(self).submit_job_script(script_info).await
-            }
-        }
+        self.metrics
+            .scheduler_command("submit_job_script", async {
+                match self.job_scheduler {
+                    JobSchedulerState::Pbs(_) => {
+                        PbsClient::inj_ref(self).submit_job_script(script_info).await
+                    }
+                    JobSchedulerState::Slurm(_) => {
+                        SlurmClient::inj_ref(self).submit_job_script(script_info).await
+                    }
+                    JobSchedulerState::Lsf(_) => {
+                        LsfClient::inj_ref(self).submit_job_script(script_info).await
+                    }
+                }
+            })
+            .await
     }
 
     async fn submit_job(&self, script_path: &str) -> anyhow::Result<String> {
-        match self.job_scheduler {
-            JobSchedulerState::Pbs(_) => PbsClient::inj_ref(self).submit_job(script_path).await,
-            JobSchedulerState::Slurm(_) => SlurmClient::inj_ref(self).submit_job(script_path).await,
-            JobSchedulerState::Lsf(_) => LsfClient::inj_ref(self).submit_job(script_path).await,
-        }
+        self.metrics
+            .scheduler_command("submit_job", async {
+                match self.job_scheduler {
+                    JobSchedulerState::Pbs(_) => {
+                        PbsClient::inj_ref(self).submit_job(script_path).await
+                    }
+                    JobSchedulerState::Slurm(_) => {
+                        SlurmClient::inj_ref(self).submit_job(script_path).await
+                    }
+                    JobSchedulerState::Lsf(_) => {
+                        LsfClient::inj_ref(self).submit_job(script_path).await
+                    }
+                }
+            })
+            .await
     }
 
     async fn delete_job(&self, job_id: &str) -> anyhow::Result<()> {
-        match self.job_scheduler {
-            JobSchedulerState::Pbs(_) => PbsClient::inj_ref(self).delete_job(job_id).await,
-            JobSchedulerState::Slurm(_) => SlurmClient::inj_ref(self).delete_job(job_id).await,
-            JobSchedulerState::Lsf(_) => LsfClient::inj_ref(self).delete_job(job_id).await,
-        }
+        self.metrics
+            .scheduler_command("delete_job", async {
+                match self.job_scheduler {
+                    JobSchedulerState::Pbs(_) => PbsClient::inj_ref(self).delete_job(job_id).await,
+                    JobSchedulerState::Slurm(_) => {
+                        SlurmClient::inj_ref(self).delete_job(job_id).await
+                    }
+                    JobSchedulerState::Lsf(_) => LsfClient::inj_ref(self).delete_job(job_id).await,
+                }
+            })
+            .await
     }
 
     async fn pause_job(&self, job_id: &str) -> anyhow::Result<()> {
-        match self.job_scheduler {
-            JobSchedulerState::Pbs(_) => PbsClient::inj_ref(self).pause_job(job_id).await,
-            JobSchedulerState::Slurm(_) => SlurmClient::inj_ref(self).pause_job(job_id).await,
-            JobSchedulerState::Lsf(_) => LsfClient::inj_ref(self).pause_job(job_id).await,
-        }
+        self.metrics
+            .scheduler_command("pause_job", async {
+                match self.job_scheduler {
+                    JobSchedulerState::Pbs(_) => PbsClient::inj_ref(self).pause_job(job_id).await,
+                    JobSchedulerState::Slurm(_) => {
+                        SlurmClient::inj_ref(self).pause_job(job_id).await
+                    }
+                    JobSchedulerState::Lsf(_) => LsfClient::inj_ref(self).pause_job(job_id).await,
+                }
+            })
+            .await
     }
 
     async fn continue_job(&self, job_id: &str) -> anyhow::Result<()> {
-        match self.job_scheduler {
-            JobSchedulerState::Pbs(_) => PbsClient::inj_ref(self).continue_job(job_id).await,
-            JobSchedulerState::Slurm(_) => SlurmClient::inj_ref(self).continue_job(job_id).await,
-            JobSchedulerState::Lsf(_) => LsfClient::inj_ref(self).continue_job(job_id).await,
-        }
+        self.metrics
+            .scheduler_command("continue_job", async {
+                match self.job_scheduler {
+                    JobSchedulerState::Pbs(_) => {
+                        PbsClient::inj_ref(self).continue_job(job_id).await
+                    }
+                    JobSchedulerState::Slurm(_) => {
+                        SlurmClient::inj_ref(self).continue_job(job_id).await
+                    }
+                    JobSchedulerState::Lsf(_) => {
+                        LsfClient::inj_ref(self).continue_job(job_id).await
+                    }
+                }
+            })
+            .await
+    }
+    async fn queue_estimates(
+        &self,
+        job_ids: &[String],
+    ) -> anyhow::Result<HashMap<String, QueueEstimate>> {
+        self.metrics
+            .scheduler_command("queue_estimates", async {
+                match self.job_scheduler {
+                    JobSchedulerState::Pbs(_) => {
+                        PbsClient::inj_ref(self).queue_estimates(job_ids).await
+                    }
+                    JobSchedulerState::Slurm(_) => {
+                        SlurmClient::inj_ref(self).queue_estimates(job_ids).await
+                    }
+                    JobSchedulerState::Lsf(_) => {
+                        LsfClient::inj_ref(self).queue_estimates(job_ids).await
+                    }
+                }
+            })
+            .await
     }
 }
 
@@ -168,25 +273,47 @@ impl JobService for Container {
 
 #[async_trait::async_trait]
 impl SelectTaskService for Container {
-    async fn start(&self, id: Uuid, node_id: Uuid, body: StartTaskBody) {
-        let task_type = body.r#type().to_str();
+    async fn start(&self, id: Uuid, task: TaskStart) {
+        let TaskStart {
+            node_id,
+            priority,
+            timeout,
+            retry,
+            body,
+        } = task;
+        let r#type = body.r#type();
+        let mut policy = self.task_policy.of(&r#type);
+        policy.timeout = timeout.or(policy.timeout);
+        policy.retry = retry.unwrap_or(policy.retry);
+        let options = StartOptions {
+            r#type: &r#type,
+            priority,
+            policy,
+        };
+
         if let Err(e) = match body {
             StartTaskBody::DeploySoftware(body) => {
-                DeploySoftwareService::inj_ref(self).start(Task::new(id, node_id, body)).await
+                let service = DeploySoftwareService::inj_ref(self);
+                self.run_task(service, Task::new(id, node_id, body), options).await
             }
             StartTaskBody::ExecuteUsecase(body) => {
-                JobServiceImpl::inj_ref(self).start(Task::new(id, node_id, body)).await
+                let service = JobServiceImpl::inj_ref(self);
+                self.run_task(service, Task::new(id, node_id, *body), options).await
             }
             StartTaskBody::CollectOuput(body) => {
-                CollectOutputService::inj_ref(self).start(Task::new(id, node_id, body)).await
+                let service = CollectOutputService::inj_ref(self);
+                self.run_task(service, Task::new(id, node_id, body), options).await
             }
             StartTaskBody::UploadFile(body) => {
-                UploadFileService::inj_ref(self).start(Task::new(id, node_id, body)).await
+                let service = UploadFileService::inj_ref(self);
+                self.run_task(service, Task::new(id, node_id, body), options).await
             }
             StartTaskBody::DownloadFile(body) => {
-                DownloadFileService::inj_ref(self).start(Task::new(id, node_id, body)).await
+                let service = DownloadFileService::inj_ref(self);
+                self.run_task(service, Task::new(id, node_id, body), options).await
             }
         } {
+            let task_type = r#type.to_str();
             tracing::error!(task_id = %id, %task_type, "[run] {e}");
         }
     }
@@ -218,6 +345,10 @@ impl SelectTaskService for Container {
     }
 
     async fn cancel(&self, r#type: TaskType, id: Uuid) {
+        if TaskQueue::inj_ref(self).cancel(id).await {
+            return;
+        }
+
         if let Err(e) = match r#type {
             TaskType::DeploySoftware => DeploySoftwareService::inj_ref(self).cancel(id).await,
             TaskType::ExecuteUsecase => JobServiceImpl::inj_ref(self).cancel(id).await,
@@ -231,13 +362,117 @@ impl SelectTaskService for Container {
     }
 }
 
+struct StartOptions<'a> {
+    r#type: &'a TaskType,
+    priority: i32,
+    policy: TaskPolicy,
+}
+
+impl Container {
+    fn count_report(&self, r#type: &str, status: &TaskStatus) {
+        let status = status.to_string();
+        self.metrics.task_reports.with_label_values(&[r#type, &status]).inc();
+    }
+
+    /// The cancellation of a timed out attempt, which is retried or reported failed instead
+    fn is_stopping(&self, id: Uuid, status: &TaskStatus) -> bool {
+        matches!(status, TaskStatus::Cancelled) && self.task_registry.is_timed_out(id)
+    }
+
+    /// Cancel the timed out attempt, so that the jobs, transfers or processes it started
+    /// don't keep running, and let it finish stopping them
+    async fn stop_timed_out<S>(&self, service: &S, id: Uuid, start: impl Future)
+    where
+        S: TaskService + Sync,
+    {
+        self.task_registry.set_timed_out(id, true);
+        let (cancelled, _) = tokio::join!(service.cancel(id), timeout(STOP_TIMEOUT, start));
+        if let Err(e) = cancelled {
+            tracing::warn!(task_id = %id, "Failed to cancel the timed out task: {e}");
+        }
+        self.task_registry.set_timed_out(id, false);
+    }
+
+    /// Run the task with the timeout and retry policy.
+    /// Every attempt waits in the admission queue of its type, and runs with the permit held.
+    async fn run_task<S>(
+        &self,
+        service: &S,
+        task: Task<S::Body>,
+        options: StartOptions<'_>,
+    ) -> anyhow::Result<()>
+    where
+        S: TaskService + Sync,
+        S::Body: TaskEntity + Clone + Send + Sync,
+    {
+        let StartOptions {
+            r#type,
+            priority,
+            policy,
+        } = options;
+        let id = task.id;
+        let task_queue = TaskQueue::inj_ref(self);
+        self.task_registry.track(id, task.node_id, *r#type);
+        WorkspaceManager::inj_ref(self).track(id, task.node_id, r#type);
+        let attempts = policy.retry.saturating_add(1);
+
+        for attempt in 1..=attempts {
+            let Some(permit) = task_queue.admit::<S::Body>(id, r#type, priority).await? else {
+                return Ok(());
+            };
+
+            let span = tracing::info_span!("start", task_type = r#type.to_str(), attempt);
+            let mut start = pin!(service.start(task.clone()).instrument(span));
+            let result = match policy.timeout {
+                Some(secs) => match timeout(Duration::from_secs(secs), &mut start).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        self.stop_timed_out(service, id, start).await;
+                        Err(anyhow::anyhow!("Timed out after {secs}s"))
+                    }
+                },
+                None => start.await,
+            };
+            let Err(e) = result else {
+                return Ok(());
+            };
+            drop(permit);
+
+            if attempt == attempts {
+                let message = format!("{e:#} (attempt {attempt}/{attempts})");
+                return TaskStatusReporter::<S::Body>::report_msg(
+                    self,
+                    id,
+                    TaskStatus::Failed,
+                    &message,
+                )
+                .await;
+            }
+
+            let message = format!(
+                "Attempt {attempt}/{attempts} failed, retry in {}s: {e:#}",
+                policy.retry_interval
+            );
+            tracing::warn!(task_id = %id, "{message}");
+            TaskStatusReporter::<S::Body>::report_msg(self, id, TaskStatus::Queued, &message)
+                .await?;
+            let interval = Duration::from_secs(policy.retry_interval);
+            if !task_queue.delay::<S::Body>(id, interval).await? {
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+}
+
 #[async_trait::async_trait]
 impl SchedulerStat for Container {
     async fn total(&self) -> anyhow::Result<SchedulerTotalResources> {
         match self.job_scheduler {
             JobSchedulerState::Pbs(_) => Pbs::inj_ref(self).total().await,
             JobSchedulerState::Slurm(_) => Slurm::inj_ref(self).total().await,
-            JobSchedulerState::Lsf(_) => anyhow::bail!("Lsf doesn't implement resource_stat"),
+            JobSchedulerState::Lsf(_) => Lsf::inj_ref(self).total().await,
         }
     }
 
@@ -245,7 +480,15 @@ impl SchedulerStat for Container {
         match self.job_scheduler {
             JobSchedulerState::Pbs(_) => Pbs::inj_ref(self).used().await,
             JobSchedulerState::Slurm(_) => Slurm::inj_ref(self).used().await,
-            JobSchedulerState::Lsf(_) => anyhow::bail!("Lsf doesn't implement resource_stat"),
+            JobSchedulerState::Lsf(_) => Lsf::inj_ref(self).used().await,
+        }
+    }
+
+    async fn inventory(&self) -> anyhow::Result<Inventory> {
+        match self.job_scheduler {
+            JobSchedulerState::Pbs(_) => Pbs::inj_ref(self).inventory().await,
+            JobSchedulerState::Slurm(_) => Slurm::inj_ref(self).inventory().await,
+            JobSchedulerState::Lsf(_) => Lsf::inj_ref(self).inventory().await,
         }
     }
 }
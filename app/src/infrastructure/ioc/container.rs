@@ -6,15 +6,28 @@ use service::{
 };
 use typed_builder::TypedBuilder;
 
+use crate::config::TaskPolicyConfig;
 use crate::infrastructure::{
     command::SshConfig,
+    metrics::Metrics,
     service::{
+        deploy_log::DeployLogState,
         download_file::DownloadFileState,
         file_load::FileLoadState,
+        health::HealthState,
         job_scheduler::{LsfClientState, PBSClientState, SlurmClientState},
-        software_deployer::{ApptainerDeployerState, SpackDeployerState},
+        log_tailer::LogTailerState,
+        software_cache::SoftwareCacheState,
+        software_deployer::{
+            ApptainerDeployerState, CondaDeployerState, ModuleDeployerState, SpackDeployerState,
+            SpackEnvDeployerState,
+        },
+        task_queue::TaskQueueState,
+        task_registry::TaskRegistry,
         task_status_reporter::TaskStatusReporterState,
+        token_manager::TokenManager,
         upload_file::UploadFileState,
+        workspace::WorkspaceState,
     },
 };
 
@@ -25,6 +38,13 @@ pub struct Container {
 
     pub default_http_client: Arc<ClientWithMiddleware>,
 
+    pub metrics: Arc<Metrics>,
+
+    pub tokens: Arc<TokenManager>,
+
+    #[as_ref]
+    pub(super) health: HealthState,
+
     #[as_ref]
     pub(super) file_load: FileLoadState,
 
@@ -34,11 +54,27 @@ pub struct Container {
     #[as_ref]
     pub(super) spack: SpackDeployerState,
 
+    #[as_ref]
+    pub(super) spack_env: SpackEnvDeployerState,
+
     #[as_ref]
     pub(super) apptainer: ApptainerDeployerState,
 
+    #[as_ref]
+    pub(super) conda: CondaDeployerState,
+
+    #[as_ref]
+    pub(super) modules: ModuleDeployerState,
+
     pub(super) job_scheduler: JobSchedulerState,
 
+    #[as_ref]
+    pub(super) task_queue: TaskQueueState,
+
+    pub(super) task_policy: TaskPolicyConfig,
+
+    pub(super) task_registry: TaskRegistry,
+
     #[as_ref]
     pub(super) deploy_software: DeploySoftwareState,
 
@@ -53,6 +89,18 @@ pub struct Container {
 
     #[as_ref]
     pub(super) upload_file: UploadFileState,
+
+    #[as_ref]
+    pub(super) workspace: WorkspaceState,
+
+    #[as_ref]
+    pub(super) log_tailer: LogTailerState,
+
+    #[as_ref]
+    pub(super) software_cache: SoftwareCacheState,
+
+    #[as_ref]
+    pub(super) deploy_log: DeployLogState,
 }
 
 pub(super) enum JobSchedulerState {
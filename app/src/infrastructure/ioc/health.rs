@@ -0,0 +1,57 @@
+use std::future::Future;
+use std::time::Duration;
+
+use tokio::time::timeout;
+
+use super::container::JobSchedulerState;
+use super::Container;
+use crate::dto::{CheckResult, Readiness};
+use crate::infrastructure::service::health::HealthCheck;
+
+/// How long a check can take
+const CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+impl Container {
+    /// Check whether the agent is ready to run tasks
+    pub async fn check_readiness(&self) -> Readiness {
+        let health = HealthCheck::inj_ref(self);
+        let (program, args): (_, &[_]) = match self.job_scheduler {
+            JobSchedulerState::Pbs(_) => ("qstat", &["-B"]),
+            JobSchedulerState::Slurm(_) => ("sinfo", &["-h", "-o", "%P"]),
+            JobSchedulerState::Lsf(_) => ("lsid", &[]),
+        };
+
+        let (kafka, token, server, scheduler, ssh, save_path) = tokio::join!(
+            check(async { health.kafka() }),
+            check(self.tokens.check()),
+            check(health.server()),
+            check(health.command(program, args)),
+            check(health.ssh()),
+            check(health.save_path()),
+        );
+        Readiness::new([
+            ("kafka", kafka),
+            ("token", token),
+            ("server", server),
+            ("scheduler", scheduler),
+            ("ssh", ssh),
+            ("savePath", save_path),
+        ])
+    }
+}
+
+async fn check(fut: impl Future<Output = anyhow::Result<()>>) -> CheckResult {
+    let result = timeout(CHECK_TIMEOUT, fut)
+        .await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("Timed out after {CHECK_TIMEOUT:?}")));
+    match result {
+        Ok(()) => CheckResult {
+            ok: true,
+            error: None,
+        },
+        Err(e) => CheckResult {
+            ok: false,
+            error: Some(format!("{e:#}")),
+        },
+    }
+}
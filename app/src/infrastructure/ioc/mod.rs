@@ -1,5 +1,8 @@
+mod admin;
 mod boilerplate;
 mod container;
+mod health;
+mod snapshot;
 
 use std::path::Path;
 use std::sync::Arc;
@@ -16,12 +19,27 @@ use crate::{
         command::SshConfig,
         http::middleware::{AuthMiddleware, MiddlewareMenu},
         ioc::container::JobSchedulerState,
+        metrics::Metrics,
         service::{
+            deploy_log::DeployLogState,
             download_file::{DownloadFileState, RawDownloadFileService},
             file_load::FileLoadState,
+            health::HealthState,
             job_scheduler::{PBSClientState, SlurmClientState},
-            software_deployer::{ApptainerDeployerState, SpackDeployerState},
+            keycloak::GrantInfo,
+            log_tailer::LogTailerState,
+            software_cache::SoftwareCacheState,
+            software_deployer::{
+                ApptainerDeployerState, CondaDeployerState, ModuleDeployerState,
+                SpackDeployerState, SpackEnvDeployerState,
+            },
+            task_queue::TaskQueueState,
+            task_registry::TaskRegistry,
+            token_manager::{Credential, TokenManager},
+            token_store::TokenStore,
+            transfer_limit::TransferLimit,
             upload_file::{RawUploadFileService, UploadFileState},
+            workspace::WorkspaceState,
         },
     },
 };
@@ -33,25 +51,33 @@ use super::service::{
 };
 
 impl Container {
-    pub async fn new(
-        config: &AgentConfig,
-        access_token: &str,
-        refresh_token: String,
-    ) -> anyhow::Result<Self> {
+    pub async fn new(config: &AgentConfig, grant_info: GrantInfo) -> anyhow::Result<Self> {
         let ssh_config = config.ssh_proxy.as_ref().map(SshConfig::new);
 
-        let auth_middleware = Arc::new(AuthMiddleware::new(
-            config.oidc_server.clone().join("token").unwrap(),
+        let metrics = Arc::new(Metrics::new()?);
+
+        let credential = match &config.login.client_secret {
+            Some(secret) => Credential::ClientSecret(secret.clone()),
+            None => Credential::Device {
+                store: TokenStore::new(config),
+                offline: config.login.offline,
+            },
+        };
+        let tokens = Arc::new(TokenManager::new(
+            config.oidc_server.clone(),
             &config.client_id,
-            access_token,
-            refresh_token,
+            credential,
+            grant_info,
             REQ_TIMEOUT,
+            metrics.clone(),
         ));
+        let auth_middleware = Arc::new(AuthMiddleware::new(tokens.clone()));
         let default_http_client = Arc::new(
             MiddlewareMenu::builder()
                 .retries(5)
                 .auth(auth_middleware.clone())
                 .timeout(REQ_TIMEOUT)
+                .metrics(metrics.clone())
                 .build()
                 .make(),
         );
@@ -62,12 +88,27 @@ impl Container {
             default_http_client.clone(),
         );
 
+        let log_tailer = LogTailerState::new(
+            &config.save_path,
+            &config.server,
+            default_http_client.clone(),
+            &config.log_tail,
+        );
+
+        let deploy_log = DeployLogState::new(
+            &config.save_path,
+            &config.server,
+            default_http_client.clone(),
+            &config.log_tail,
+        );
+
         let task_status_reporter = TaskStatusReporterState::new(
             config.server.clone(),
             MiddlewareMenu::builder()
                 .retries(3)
                 .auth(auth_middleware.clone())
                 .timeout(REQ_TIMEOUT)
+                .metrics(metrics.clone())
                 .build()
                 .make(),
         );
@@ -76,8 +117,15 @@ impl Container {
             "apptainer".to_string(),
             config.container_save_path.clone(),
             None,
+            &config.apptainer_image,
+            &config.apptainer_exec,
         );
 
+        let spack_env = SpackEnvDeployerState::new(&config.save_path, ssh_config.as_ref());
+        let conda = CondaDeployerState::new(&config.conda, &config.save_path, ssh_config.as_ref());
+
+        let transfer_limit = Arc::new(TransferLimit::new(&config.transfer));
+
         let download_file: DownloadFileState = RawDownloadFileService::builder()
             .save_dir(config.save_path.clone())
             .base_url(config.server.clone())
@@ -87,6 +135,7 @@ impl Container {
                     .retries(3)
                     .auth(auth_middleware.clone())
                     .timeout(REQ_TIMEOUT)
+                    .metrics(metrics.clone())
                     .build()
                     .make(),
             )
@@ -95,9 +144,12 @@ impl Container {
                     .retries(4)
                     .auth(auth_middleware.clone())
                     .timeout(Duration::from_secs(30))
+                    .metrics(metrics.clone())
                     .build()
                     .make(),
             )
+            .transfer_limit(transfer_limit.clone())
+            .metrics(metrics.clone())
             .build()
             .into();
 
@@ -110,6 +162,7 @@ impl Container {
                     .retries(10)
                     .auth(auth_middleware.clone())
                     .timeout(REQ_TIMEOUT)
+                    .metrics(metrics.clone())
                     .build()
                     .make(),
             )
@@ -117,10 +170,14 @@ impl Container {
                 MiddlewareMenu::builder()
                     .auth(auth_middleware.clone())
                     .timeout(Duration::from_secs(30))
+                    .metrics(metrics.clone())
                     .build()
                     .make(),
                 5,
+                metrics.clone(),
             )
+            .transfer_limit(transfer_limit)
+            .metrics(metrics.clone())
             .build()
             .into();
 
@@ -152,16 +209,45 @@ impl Container {
         let container = Container::builder()
             .ssh_config(ssh_config)
             .default_http_client(default_http_client)
+            .metrics(metrics)
+            .tokens(tokens)
+            .health(HealthState::new(config))
             .file_load(file_load)
             .task_status_reporter(task_status_reporter)
             .spack(SpackDeployerState::new())
+            .spack_env(spack_env)
             .apptainer(apptainer)
+            .conda(conda)
+            .modules(ModuleDeployerState::new(&config.modules))
             .job_scheduler(job_scheduler)
-            .deploy_software(DeploySoftwareState::new(5))
+            .task_queue(TaskQueueState::new(&config.task_queue))
+            .task_policy(config.task_policy.clone())
+            .task_registry(TaskRegistry::default())
+            .deploy_software(DeploySoftwareState::new(
+                config.spack,
+                config.modules.enable,
+                config.modules.install_hook.is_some(),
+            ))
             .download_file(download_file)
-            .job(JobServiceState::new(config.spack, config.apptainer))
+            .job(JobServiceState::new(
+                config.spack,
+                config.apptainer,
+                config.conda.enable,
+                config.modules.enable,
+                config.resubmit,
+            ))
             .collect_output(CollectOutputState::default())
             .upload_file(upload_file)
+            .workspace(WorkspaceState::new(
+                &config.save_path,
+                config.workspace.clone(),
+            ))
+            .log_tailer(log_tailer)
+            .software_cache(SoftwareCacheState::new(
+                config.software_cache.clone(),
+                config.deployers(),
+            ))
+            .deploy_log(deploy_log)
             .build();
 
         Ok(container)
@@ -0,0 +1,169 @@
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use domain::model::{
+    entity::{job::JobState, task::deploy_software::DeployerType, Job},
+    vo::job::ScriptInfo,
+};
+use serde::{Deserialize, Deserializer, Serialize};
+use service::prelude::JobContext;
+use tokio::fs;
+use uuid::Uuid;
+
+use super::Container;
+use crate::infrastructure::service::{
+    download_file::DownloadFileService, upload_file::UploadFileService,
+};
+
+/// A job being watched when the agent shut down
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JobRecord {
+    task_id: Uuid,
+    job_id: String,
+    state: JobState,
+    /// Missing in the records saved by older agents
+    #[serde(default)]
+    node_id: Option<String>,
+    /// The deployer and hash of the software used by the job,
+    /// missing if unknown and `null` if the job uses none
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_some"
+    )]
+    software: Option<Option<(DeployerType, String)>>,
+    /// The script of the job for resubmitting it, missing in the records saved by older agents
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    script: Option<ScriptInfo>,
+    /// The times the job was resubmitted
+    #[serde(default)]
+    attempts: u32,
+}
+
+/// Tell a `null` field from a missing one
+fn deserialize_some<'de, T, D>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// When the software was last used, kept across restarts for evicting software
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SoftwareUseRecord {
+    deployer: DeployerType,
+    hash: String,
+    /// Seconds since the Unix epoch
+    last_used: u64,
+}
+
+impl Container {
+    /// Stop all file transmissions, saving their progress
+    pub fn suspend_transfers(&self) {
+        DownloadFileService::inj_ref(self).suspend_all();
+        UploadFileService::inj_ref(self).suspend_all();
+    }
+
+    /// Save the jobs being watched, so that they can be watched again after restarting
+    pub async fn save_jobs(&self, path: &Path) -> anyhow::Result<()> {
+        let records: Vec<_> = self
+            .job
+            .jobs()
+            .into_iter()
+            .map(|(task_id, job)| {
+                let context = self.job.context(task_id);
+                let (script, attempts) = context.script.unzip();
+                JobRecord {
+                    task_id,
+                    job_id: job.id.to_string(),
+                    state: job.state,
+                    node_id: context.node_id,
+                    software: context.software,
+                    script,
+                    attempts: attempts.unwrap_or_default(),
+                }
+            })
+            .collect();
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        fs::write(path, serde_json::to_vec(&records)?).await?;
+        tracing::info!("Saved {} jobs to {}", records.len(), path.display());
+        Ok(())
+    }
+
+    /// Watch the jobs saved at last shutdown again
+    pub async fn load_jobs(&self, path: &Path) -> anyhow::Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let records: Vec<JobRecord> = serde_json::from_slice(&fs::read(path).await?)?;
+        tracing::info!("Loaded {} jobs from {}", records.len(), path.display());
+        self.job.restore(records.into_iter().map(|record| {
+            let job = Job {
+                id: record.job_id.into(),
+                state: record.state,
+                ..Default::default()
+            };
+            let context = JobContext {
+                node_id: record.node_id,
+                software: record.software,
+                script: record.script.map(|script| (script, record.attempts)),
+            };
+            (record.task_id, job, context)
+        }));
+
+        // Their states will be reported by refreshing, so they shouldn't be loaded twice
+        fs::remove_file(path).await?;
+        Ok(())
+    }
+
+    /// Save when the software was last used, so that idle software is still known after restarting
+    pub async fn save_software_uses(&self, path: &Path) -> anyhow::Result<()> {
+        let records: Vec<_> = self
+            .job
+            .software_uses()
+            .into_iter()
+            .map(|(deployer, hash, time)| SoftwareUseRecord {
+                deployer,
+                hash,
+                last_used: time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            })
+            .collect();
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        fs::write(path, serde_json::to_vec(&records)?).await?;
+        tracing::info!(
+            "Saved {} software uses to {}",
+            records.len(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Load the software uses saved at last shutdown
+    pub async fn load_software_uses(&self, path: &Path) -> anyhow::Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+
+        let records: Vec<SoftwareUseRecord> = serde_json::from_slice(&fs::read(path).await?)?;
+        tracing::info!(
+            "Loaded {} software uses from {}",
+            records.len(),
+            path.display()
+        );
+        self.job.restore_software_uses(records.into_iter().map(|record| {
+            let time = UNIX_EPOCH + Duration::from_secs(record.last_used);
+            (record.deployer, record.hash, time)
+        }));
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+use std::future::Future;
+use std::time::Instant;
+
+use tracing::Instrument;
+
+use prometheus::{
+    Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts,
+    Registry, TextEncoder,
+};
+
+/// Metrics of the agent, exposed in the Prometheus text format
+pub struct Metrics {
+    registry: Registry,
+    /// Status reports of tasks, by type and status
+    pub task_reports: IntCounterVec,
+    /// Tasks not ended yet, by type and last status. Refreshed when gathering.
+    pub active_tasks: IntGaugeVec,
+    /// Kafka messages, by result
+    pub kafka_messages: IntCounterVec,
+    /// Requests retried after transient failures
+    pub http_retries: IntCounter,
+    /// Token refreshes, by result
+    pub token_refreshes: IntCounterVec,
+    /// Bytes transmitted, by direction
+    pub transfer_bytes: IntCounterVec,
+    /// Average bytes per second of each transmission. Refreshed when gathering.
+    pub transfer_throughput: GaugeVec,
+    /// Latency of scheduler commands, by command
+    pub scheduler_latency: HistogramVec,
+    /// Failed scheduler commands, by command
+    pub scheduler_errors: IntCounterVec,
+    /// The latest resources stat, by kind and resource
+    pub resources: IntGaugeVec,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("agent".to_owned()), None)?;
+
+        let task_reports = IntCounterVec::new(
+            Opts::new("task_reports_total", "Status reports of tasks"),
+            &["type", "status"],
+        )?;
+        let active_tasks = IntGaugeVec::new(
+            Opts::new("active_tasks", "Tasks not ended yet"),
+            &["type", "status"],
+        )?;
+        let kafka_messages = IntCounterVec::new(
+            Opts::new("kafka_messages_total", "Kafka messages consumed or failed"),
+            &["result"],
+        )?;
+        let http_retries = IntCounter::new(
+            "http_retries_total",
+            "Requests retried after transient failures",
+        )?;
+        let token_refreshes = IntCounterVec::new(
+            Opts::new("token_refreshes_total", "Access token refreshes"),
+            &["result"],
+        )?;
+        let transfer_bytes = IntCounterVec::new(
+            Opts::new("transfer_bytes_total", "Bytes of files transmitted"),
+            &["direction"],
+        )?;
+        let transfer_throughput = GaugeVec::new(
+            Opts::new(
+                "transfer_throughput_bytes",
+                "Average bytes per second of running transmissions",
+            ),
+            &["direction", "task_id"],
+        )?;
+        let scheduler_latency = HistogramVec::new(
+            HistogramOpts::new("scheduler_command_seconds", "Latency of scheduler commands"),
+            &["command"],
+        )?;
+        let scheduler_errors = IntCounterVec::new(
+            Opts::new(
+                "scheduler_command_errors_total",
+                "Failed scheduler commands",
+            ),
+            &["command"],
+        )?;
+        let resources = IntGaugeVec::new(
+            Opts::new("resources", "The latest stat of resources"),
+            &["kind", "resource"],
+        )?;
+
+        registry.register(Box::new(task_reports.clone()))?;
+        registry.register(Box::new(active_tasks.clone()))?;
+        registry.register(Box::new(kafka_messages.clone()))?;
+        registry.register(Box::new(http_retries.clone()))?;
+        registry.register(Box::new(token_refreshes.clone()))?;
+        registry.register(Box::new(transfer_bytes.clone()))?;
+        registry.register(Box::new(transfer_throughput.clone()))?;
+        registry.register(Box::new(scheduler_latency.clone()))?;
+        registry.register(Box::new(scheduler_errors.clone()))?;
+        registry.register(Box::new(resources.clone()))?;
+
+        Ok(Self {
+            registry,
+            task_reports,
+            active_tasks,
+            kafka_messages,
+            http_retries,
+            token_refreshes,
+            transfer_bytes,
+            transfer_throughput,
+            scheduler_latency,
+            scheduler_errors,
+            resources,
+        })
+    }
+
+    /// Run the scheduler command in a span, recording its latency and failure
+    pub async fn scheduler_command<T>(
+        &self,
+        command: &str,
+        fut: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        let start = Instant::now();
+        let result = fut.instrument(tracing::info_span!("scheduler", command)).await;
+        self.scheduler_latency
+            .with_label_values(&[command])
+            .observe(start.elapsed().as_secs_f64());
+        if result.is_err() {
+            self.scheduler_errors.with_label_values(&[command]).inc();
+        }
+        result
+    }
+
+    pub fn record_resources<'a>(
+        &self,
+        kind: &str,
+        values: impl IntoIterator<Item = (&'a str, u64)>,
+    ) {
+        for (resource, value) in values {
+            self.resources.with_label_values(&[kind, resource]).set(value as i64);
+        }
+    }
+
+    /// Encode all metrics in the text format
+    pub fn encode(&self) -> prometheus::Result<String> {
+        let mut buf = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buf)?;
+        // The text encoder only writes UTF-8
+        Ok(String::from_utf8(buf).unwrap())
+    }
+}
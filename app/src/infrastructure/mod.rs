@@ -1,4 +1,6 @@
 pub mod command;
 pub mod http;
 pub mod ioc;
+pub mod metrics;
 pub mod service;
+pub mod trace;
@@ -0,0 +1,126 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use dep_inj::DepInj;
+use domain::service::DeployLogStore;
+use reqwest_middleware::ClientWithMiddleware;
+use url::Url;
+use uuid::Uuid;
+
+use crate::config::LogTailConfig;
+use crate::dto::{LogStream, TaskLog};
+use crate::infrastructure::http::header::TASK_ID;
+
+/// How long to keep a log since it's saved
+const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// Max number of logs kept, the oldest ones are removed when exceeded
+const MAX_LOGS: usize = 100;
+
+/// Keeps the full output of failed software deployments under the save path,
+/// and sends it to the backend as a log of the task
+#[derive(DepInj)]
+#[target(DeployLog)]
+pub struct DeployLogState {
+    dir: PathBuf,
+    url: Url,
+    client: Arc<ClientWithMiddleware>,
+    chunk_size: usize,
+}
+
+impl DeployLogState {
+    pub fn new(
+        save_path: &str,
+        base_url: &Url,
+        client: Arc<ClientWithMiddleware>,
+        config: &LogTailConfig,
+    ) -> Self {
+        Self {
+            dir: Self::dir(save_path),
+            url: base_url.join("workflow-engine/ReceiveTaskLog").unwrap(),
+            client,
+            chunk_size: (config.chunk_size.0 as usize).max(4),
+        }
+    }
+
+    pub fn dir(save_path: impl AsRef<Path>) -> PathBuf {
+        save_path.as_ref().join(".deploy-logs")
+    }
+}
+
+#[async_trait::async_trait]
+impl<Deps> DeployLogStore for DeployLog<Deps>
+where
+    Deps: AsRef<DeployLogState> + Send + Sync,
+{
+    async fn save_deploy_log(&self, id: Uuid, log: &str) -> anyhow::Result<String> {
+        tokio::fs::create_dir_all(&self.dir).await?;
+        let path = self.dir.join(format!("{id}.log"));
+        tokio::fs::write(&path, log).await?;
+        if let Err(e) = self.prune().await {
+            tracing::warn!("Failed to remove old deploy logs: {e}");
+        }
+
+        let path = path.to_string_lossy();
+        match self.send(id, log).await {
+            Ok(()) => Ok(format!(
+                "sent as the `deploy` log of the task, also saved at {path} on the agent"
+            )),
+            Err(e) => {
+                tracing::warn!(task_id = %id, "Failed to send deploy log: {e:#}");
+                Ok(format!("saved at {path} on the agent"))
+            }
+        }
+    }
+}
+
+impl<Deps> DeployLog<Deps>
+where
+    Deps: AsRef<DeployLogState> + Send + Sync,
+{
+    /// Send the log in chunks, each with its byte offset as the outputs of jobs
+    async fn send(&self, id: Uuid, log: &str) -> anyhow::Result<()> {
+        let mut offset = 0;
+        while offset < log.len() {
+            let mut end = (offset + self.chunk_size).min(log.len());
+            while !log.is_char_boundary(end) {
+                end -= 1;
+            }
+            self.client
+                .post(self.url.clone())
+                .header(TASK_ID, id.to_string())
+                .json(&TaskLog {
+                    id,
+                    stream: LogStream::Deploy,
+                    offset: offset as u64,
+                    content: &log[offset..end],
+                })
+                .send()
+                .await?
+                .error_for_status()?;
+            offset = end;
+        }
+        Ok(())
+    }
+
+    /// Remove the logs beyond the max age or number
+    async fn prune(&self) -> std::io::Result<()> {
+        let mut logs = vec![];
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let modified = entry.metadata().await?.modified()?;
+            logs.push((modified, entry.path()));
+        }
+        // The newest ones first
+        logs.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+
+        let now = SystemTime::now();
+        for (i, (modified, path)) in logs.into_iter().enumerate() {
+            let age = now.duration_since(modified).unwrap_or_default();
+            if i >= MAX_LOGS || age >= MAX_AGE {
+                tokio::fs::remove_file(path).await?;
+            }
+        }
+        Ok(())
+    }
+}
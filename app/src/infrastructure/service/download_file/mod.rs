@@ -1,7 +1,7 @@
 mod supervisor;
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::Context;
@@ -14,8 +14,9 @@ use domain::{
     service::{TaskService, TaskStatusReporter},
 };
 use reqwest_middleware::ClientWithMiddleware;
+use serde::{Deserialize, Serialize};
 use tokio::fs;
-use tokio::fs::File;
+use tokio::fs::{File, OpenOptions};
 use tokio::sync::Mutex;
 use tokio_util::sync::CancellationToken;
 use typed_builder::TypedBuilder;
@@ -23,9 +24,12 @@ use url::Url;
 use uuid::Uuid;
 
 use self::supervisor::DownloadFileSupervisor;
+use super::transfer_limit::{TransferEnd, TransferLimit};
+use crate::dto::TransferProgress;
 use crate::infrastructure::{
     command::{MaybeSsh, Scp},
     http::header::TASK_ID,
+    metrics::Metrics,
 };
 
 #[derive(TypedBuilder)]
@@ -35,6 +39,8 @@ pub struct RawDownloadFileService {
     block_size: u64,
     http_client: ClientWithMiddleware,
     download_client: ClientWithMiddleware,
+    transfer_limit: Arc<TransferLimit>,
+    metrics: Arc<Metrics>,
 }
 
 #[derive(DepInj)]
@@ -43,6 +49,7 @@ pub struct DownloadFileState {
     id2supervisor: Mutex<HashMap<Uuid, Arc<DownloadFileSupervisor>>>,
     save_dir: String,
     download_url: Url,
+    suspend: CancellationToken,
     inner: Arc<DownloadFileServiceInner>,
 }
 
@@ -50,6 +57,17 @@ pub struct DownloadFileServiceInner {
     block_size: u64,
     http_client: ClientWithMiddleware,
     download_client: ClientWithMiddleware,
+    transfer_limit: Arc<TransferLimit>,
+    metrics: Arc<Metrics>,
+}
+
+/// Progress of a suspended download, saved next to the file
+#[derive(Serialize, Deserialize)]
+struct Checkpoint {
+    file_id: Uuid,
+    file_size: u64,
+    block_size: u64,
+    blocks: Vec<u64>,
 }
 
 impl From<RawDownloadFileService> for DownloadFileState {
@@ -60,16 +78,21 @@ impl From<RawDownloadFileService> for DownloadFileState {
             block_size,
             http_client,
             download_client,
+            transfer_limit,
+            metrics,
         } = raw;
 
         Self {
             id2supervisor: Mutex::default(),
             save_dir,
             download_url: base_url.join("file-storage/RangelyDownloadFile/").unwrap(),
+            suspend: CancellationToken::new(),
             inner: Arc::new(DownloadFileServiceInner {
                 block_size,
                 http_client,
                 download_client,
+                transfer_limit,
+                metrics,
             }),
         }
     }
@@ -91,8 +114,17 @@ where
 
         match result {
             Ok("cancel") => Ok(()),
+            Ok("suspend") => {
+                self.prj_ref()
+                    .report_msg(
+                        id,
+                        TaskStatus::Paused,
+                        "Agent is shutting down, the progress is saved",
+                    )
+                    .await
+            }
             Ok(_) => self.prj_ref().report(id, TaskStatus::Completed).await,
-            Err(e) => self.prj_ref().report_msg(id, TaskStatus::Failed, &e.to_string()).await,
+            Err(e) => Err(e),
         }
     }
 
@@ -136,6 +168,27 @@ where
     }
 }
 
+impl<Deps> DownloadFileService<Deps>
+where
+    Deps: AsRef<DownloadFileState>,
+{
+    /// Stop all downloads and save their progress. Downloads started later are stopped at once.
+    pub fn suspend_all(&self) {
+        self.suspend.cancel();
+    }
+
+    /// Progress of the download, absent if it isn't transmitting in blocks
+    pub async fn progress(&self, id: Uuid) -> Option<TransferProgress> {
+        self.id2supervisor.lock().await.get(&id).map(|supervisor| supervisor.progress())
+    }
+
+    /// Average bytes per second of the running downloads
+    pub async fn throughputs(&self) -> Vec<(Uuid, f64)> {
+        let map = self.id2supervisor.lock().await;
+        map.iter().map(|(id, supervisor)| (*id, supervisor.throughput())).collect()
+    }
+}
+
 impl<Deps> DownloadFileService<Deps>
 where
     Deps:
@@ -204,21 +257,65 @@ where
                 .await?;
             fs::write(&file_pos, &bytes).await?;
         } else {
-            let file = File::create(&file_pos).await?;
-            file.set_len(file_size).await?;
-            let block_count = file_size.div_ceil(self.inner.block_size);
+            let block_size = self.inner.block_size;
+            let block_count = file_size.div_ceil(block_size);
+            let checkpoint_pos = checkpoint_path(&file_pos);
+            let checkpoint = read_checkpoint(&checkpoint_pos).await.filter(|checkpoint| {
+                checkpoint.file_id == file_id
+                    && checkpoint.file_size == file_size
+                    && checkpoint.block_size == block_size
+                    && checkpoint.blocks.len() as u64 <= block_count
+                    && checkpoint.blocks.iter().all(|&i| i < block_count)
+            });
+
+            let (file, blocks) = match checkpoint {
+                Some(checkpoint) if file_pos.exists() => {
+                    tracing::info!(
+                        %file_id,
+                        "Continue downloading from checkpoint, {} blocks left",
+                        checkpoint.blocks.len()
+                    );
+                    let file = OpenOptions::new().write(true).open(&file_pos).await?;
+                    (file, checkpoint.blocks)
+                }
+                _ => {
+                    let file = File::create(&file_pos).await?;
+                    file.set_len(file_size).await?;
+                    (file, (0..block_count).collect())
+                }
+            };
+
             let supervisor = Arc::new(DownloadFileSupervisor::new(
                 &self.inner,
                 task.id,
                 file,
                 file_size,
                 block_count,
+                blocks,
                 url,
+                self.suspend.clone(),
             ));
-            if supervisor.run().await {
-                // It was canceled
-                let _ = fs::remove_file(&file_pos).await;
-                return Ok("cancel");
+            self.id2supervisor.lock().await.insert(task.id, supervisor.clone());
+
+            match supervisor.clone().run().await {
+                TransferEnd::Finished => {
+                    let _ = fs::remove_file(&checkpoint_pos).await;
+                }
+                TransferEnd::Cancelled => {
+                    let _ = fs::remove_file(&file_pos).await;
+                    let _ = fs::remove_file(&checkpoint_pos).await;
+                    return Ok("cancel");
+                }
+                TransferEnd::Suspended => {
+                    let checkpoint = Checkpoint {
+                        file_id,
+                        file_size,
+                        block_size,
+                        blocks: supervisor.remaining_blocks(),
+                    };
+                    fs::write(&checkpoint_pos, serde_json::to_vec(&checkpoint)?).await?;
+                    return Ok("suspend");
+                }
             }
         }
         tracing::debug!(%file_id, "File download finished");
@@ -243,3 +340,14 @@ where
         Ok("")
     }
 }
+
+fn checkpoint_path(file_pos: &Path) -> PathBuf {
+    let mut path = file_pos.as_os_str().to_owned();
+    path.push(".checkpoint");
+    PathBuf::from(path)
+}
+
+async fn read_checkpoint(path: &Path) -> Option<Checkpoint> {
+    let content = fs::read(path).await.ok()?;
+    serde_json::from_slice(&content).ok()
+}
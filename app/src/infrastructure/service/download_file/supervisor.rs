@@ -1,21 +1,25 @@
 use std::io::SeekFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Weak};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crossbeam_queue::ArrayQueue;
 use infrastructure::sync::PauseToken;
 use tokio::fs::File;
 use tokio::io::{AsyncSeekExt, AsyncWriteExt};
 use tokio::sync::{Mutex, OwnedSemaphorePermit, Semaphore};
-use tokio::time::sleep;
+use tokio::time::{sleep, timeout};
 use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 use url::Url;
 use uuid::Uuid;
 
 use super::DownloadFileServiceInner;
-use crate::infrastructure::http::header::TASK_ID;
+use crate::dto::TransferProgress;
+use crate::infrastructure::{http::header::TASK_ID, service::transfer_limit::TransferEnd};
 
-const MAX_WORKER_COUNT: usize = 16;
+/// How long to wait for the running workers when suspending
+const SUSPEND_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct DownloadFileSupervisor {
     // Used in request
@@ -24,12 +28,18 @@ pub struct DownloadFileSupervisor {
     file: Mutex<File>,
     file_size: u64,
     index_queue: ArrayQueue<u64>,
+    block_count: u64,
+    finished_blocks: AtomicU64,
+    transferred_bytes: AtomicU64,
+    started_at: Instant,
     last_index: u64,
     // status control resources
+    worker_count: usize,
     start_guard: Arc<Semaphore>,
     pub(super) pause_token: PauseToken,
     pub(super) cancel_workers: Mutex<CancellationToken>,
     pub(super) cancel_download: CancellationToken,
+    suspend: CancellationToken,
     // Net
     download_url: Url,
     // Tools
@@ -48,8 +58,8 @@ impl DownloadFileSupervisor {
     ///
     /// # return
     ///
-    /// The routine is cancelled or not.
-    pub async fn run(self: Arc<Self>) -> bool {
+    /// How the routine ends.
+    pub async fn run(self: Arc<Self>) -> TransferEnd {
         let task_id = self.task_id;
         loop {
             tokio::select! {
@@ -61,7 +71,8 @@ impl DownloadFileSupervisor {
                             cancel_token: self.cancel_workers.lock().await.clone(),
                             supervisor: Arc::downgrade(&self),
                         };
-                        tokio::spawn(async move {
+                        let span = tracing::info_span!("download_block", block_index);
+                        let routine = async move {
                             if let Err(e) = worker.start().await {
                                 tracing::error!(
                                     %task_id,
@@ -69,48 +80,97 @@ impl DownloadFileSupervisor {
                                     "Download part failed: {e}"
                                 );
                             }
-                        });
-                    } else if self.start_guard.available_permits() + 1 < MAX_WORKER_COUNT {
-                        // Plus the just acquired one is less than 16,
+                        };
+                        tokio::spawn(routine.instrument(span));
+                    } else if self.start_guard.available_permits() + 1 < self.worker_count {
+                        // Plus the just acquired one is less than the total,
                         // means there are still some workers are running.
                         // Wait for a moment and then try again.
                         sleep(Duration::from_secs(1)).await;
                     } else {
-                        break false;
+                        break TransferEnd::Finished;
                     };
                 }
                 _ = self.cancel_download.cancelled() => {
                     self.cancel_workers.lock().await.cancel();
-                    break true;
+                    break TransferEnd::Cancelled;
+                }
+                _ = self.suspend.cancelled() => {
+                    self.stop_workers().await;
+                    break TransferEnd::Suspended;
                 }
             }
         }
     }
 
+    /// Wait for the running workers to finish their blocks,
+    /// and cancel them if it takes too long.
+    async fn stop_workers(&self) {
+        let all = self.worker_count as u32;
+        if timeout(SUSPEND_TIMEOUT, self.start_guard.acquire_many(all)).await.is_err() {
+            self.cancel_workers.lock().await.cancel();
+            // The semaphore is never closed
+            let _ = self.start_guard.acquire_many(all).await;
+        }
+    }
+
+    /// Take the indexes of blocks not downloaded yet
+    pub fn remaining_blocks(&self) -> Vec<u64> {
+        let mut blocks: Vec<u64> = std::iter::from_fn(|| self.index_queue.pop()).collect();
+        blocks.sort_unstable();
+        blocks
+    }
+
+    pub fn progress(&self) -> TransferProgress {
+        TransferProgress {
+            total_blocks: self.block_count,
+            finished_blocks: self.finished_blocks.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Average bytes per second since started
+    pub fn throughput(&self) -> f64 {
+        let bytes = self.transferred_bytes.load(Ordering::Relaxed);
+        bytes as f64 / self.started_at.elapsed().as_secs_f64().max(1.0)
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         sv: &Arc<DownloadFileServiceInner>,
         task_id: Uuid,
         file: File,
         file_size: u64,
         block_count: u64,
+        blocks: Vec<u64>,
         download_url: Url,
+        suspend: CancellationToken,
     ) -> Self {
         let index_queue = ArrayQueue::new(block_count as usize);
-        for i in 0..block_count {
-            // safe because of reserved capacity
+        for i in blocks {
+            // safe because the indexes are less than block count
             index_queue.push(i).unwrap();
         }
 
+        let worker_count = sv.transfer_limit.workers_per_file;
+        // Blocks not in the queue are downloaded before suspending
+        let finished_blocks = AtomicU64::new(block_count - index_queue.len() as u64);
+
         Self {
             task_id,
             file: Mutex::new(file),
             file_size,
             index_queue,
+            block_count,
+            finished_blocks,
+            transferred_bytes: AtomicU64::new(0),
+            started_at: Instant::now(),
             last_index: block_count - 1,
-            start_guard: Arc::new(Semaphore::new(MAX_WORKER_COUNT)),
+            worker_count,
+            start_guard: Arc::new(Semaphore::new(worker_count)),
             pause_token: PauseToken::default(),
             cancel_workers: Mutex::default(),
             cancel_download: CancellationToken::new(),
+            suspend,
             download_url,
             service: Arc::downgrade(sv),
         }
@@ -144,6 +204,8 @@ impl DownloadFileWorker {
             start + sv.block_size - 1
         };
 
+        let _permit = sv.transfer_limit.acquire_worker().await;
+        sv.transfer_limit.consume(end - start + 1).await;
         let bytes = sv
             .download_client
             .get(supervisor.download_url.clone())
@@ -159,6 +221,12 @@ impl DownloadFileWorker {
         file.seek(SeekFrom::Start(start)).await?;
         file.write_all(&bytes).await?;
         file.flush().await?;
+        supervisor.finished_blocks.fetch_add(1, Ordering::Relaxed);
+        supervisor.transferred_bytes.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        sv.metrics
+            .transfer_bytes
+            .with_label_values(&["download"])
+            .inc_by(bytes.len() as u64);
 
         Ok(())
     }
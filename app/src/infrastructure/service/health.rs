@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use bytesize::ByteSize;
+use dep_inj::DepInj;
+use tokio::fs;
+use url::Url;
+
+use crate::config::AgentConfig;
+use crate::infrastructure::command::MaybeSsh;
+
+/// Timeout of requesting the backend server
+const REQ_TIMEOUT: Duration = Duration::from_secs(10);
+/// File written to check whether the save path is writable
+const PROBE_FILE: &str = ".readyz";
+/// Rounds a background loop can miss before it's regarded as stalled
+const MISSED_BEATS: u32 = 3;
+/// Extra time allowed for slow rounds
+const STALL_GRACE: Duration = Duration::from_secs(300);
+
+/// Checks whether the agent and the services it depends on work
+#[derive(DepInj)]
+#[target(HealthCheck)]
+pub struct HealthState {
+    server: Url,
+    save_dir: PathBuf,
+    min_free_space: u64,
+    client: reqwest::Client,
+    kafka_subscribed: AtomicBool,
+    /// When each background loop last started a round, and the interval between its rounds
+    heartbeats: Mutex<HashMap<&'static str, (Instant, Duration)>>,
+}
+
+impl HealthState {
+    pub fn new(config: &AgentConfig) -> Self {
+        Self {
+            server: config.server.clone(),
+            save_dir: PathBuf::from(&config.save_path),
+            min_free_space: config.health.min_free_space.0,
+            client: reqwest::Client::builder().timeout(REQ_TIMEOUT).build().unwrap(),
+            kafka_subscribed: AtomicBool::new(false),
+            heartbeats: Mutex::default(),
+        }
+    }
+
+    /// Set by the message queue when it's assigned partitions, consumes or fails to consume
+    pub fn set_kafka_subscribed(&self, subscribed: bool) {
+        self.kafka_subscribed.store(subscribed, Ordering::Relaxed);
+    }
+
+    /// Set by the background loop at the start of each round
+    pub fn beat(&self, name: &'static str, interval: Duration) {
+        self.heartbeats.lock().unwrap().insert(name, (Instant::now(), interval));
+    }
+}
+
+impl<Deps> HealthCheck<Deps>
+where
+    Deps: AsRef<HealthState> + MaybeSsh + Send + Sync,
+{
+    /// Alive unless some background loop has stopped starting new rounds
+    pub fn liveness(&self) -> anyhow::Result<()> {
+        let mut stalled: Vec<_> = self
+            .heartbeats
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, (last, interval))| last.elapsed() > *interval * MISSED_BEATS + STALL_GRACE)
+            .map(|(name, _)| *name)
+            .collect();
+        stalled.sort_unstable();
+        anyhow::ensure!(
+            stalled.is_empty(),
+            "Background loops stalled: {}",
+            stalled.join(", ")
+        );
+        Ok(())
+    }
+
+    pub fn kafka(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.kafka_subscribed.load(Ordering::Relaxed),
+            "Kafka consumer isn't assigned partitions or failed to consume"
+        );
+        Ok(())
+    }
+
+    /// Any response means the server is reachable
+    pub async fn server(&self) -> anyhow::Result<()> {
+        self.client
+            .head(self.server.clone())
+            .send()
+            .await
+            .with_context(|| format!("Cannot reach {}", self.server))?;
+        Ok(())
+    }
+
+    pub async fn ssh(&self) -> anyhow::Result<()> {
+        if !self.prj_ref().is_ssh() {
+            return Ok(());
+        }
+        self.command("true", &[]).await.context("SSH proxy doesn't connect")
+    }
+
+    /// Run the command, through ssh if configured
+    pub async fn command(&self, program: &str, args: &[&str]) -> anyhow::Result<()> {
+        let output = self.prj_ref().command(program).args(args).output().await?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "`{program}` exited with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    pub async fn save_path(&self) -> anyhow::Result<()> {
+        let probe = self.save_dir.join(PROBE_FILE);
+        fs::create_dir_all(&self.save_dir).await?;
+        fs::write(&probe, b"").await.context("Save path isn't writable")?;
+        fs::remove_file(&probe).await?;
+
+        let stat = rustix::fs::statvfs(&self.save_dir)?;
+        let free = stat.f_bavail * stat.f_frsize;
+        anyhow::ensure!(
+            free >= self.min_free_space,
+            "Free space of save path is {}, less than {}",
+            ByteSize(free),
+            ByteSize(self.min_free_space)
+        );
+        Ok(())
+    }
+}
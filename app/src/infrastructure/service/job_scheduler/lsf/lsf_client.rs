@@ -1,11 +1,15 @@
-use std::{path::PathBuf, sync::Arc};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
 
 use anyhow::Context;
 use dep_inj::DepInj;
 use domain::{
     model::{
-        entity::{job::JobState, task::execute_usecase::StdInKind, Job},
-        vo::job::ScriptInfo,
+        entity::{
+            job::{FailureReason, JobState},
+            task::execute_usecase::StdInKind,
+            Job,
+        },
+        vo::job::{QueueEstimate, ScriptInfo},
     },
     service::JobScheduler,
 };
@@ -14,7 +18,7 @@ use tokio::{fs, process::Command};
 
 use crate::infrastructure::{
     command::{MaybeSsh, Scp},
-    service::job_scheduler::{LsfJob, LsfJobs},
+    service::job_scheduler::{failure_reason, LsfJob, LsfJobs, LsfPendingJobs},
 };
 
 #[derive(DepInj)]
@@ -140,6 +144,19 @@ where
     async fn continue_job(&self, _job_id: &str) -> anyhow::Result<()> {
         anyhow::bail!("unimplemented!")
     }
+
+    async fn queue_estimates(
+        &self,
+        job_ids: &[String],
+    ) -> anyhow::Result<HashMap<String, QueueEstimate>> {
+        let out = self.prj_ref().command("bjobs").args(LsfPendingJobs::ARGS).output().await?;
+        // bjobs fails when there is no pending job
+        if !out.status.success() {
+            return Ok(HashMap::new());
+        }
+        let jobs = LsfPendingJobs::new(&out.stdout)?;
+        Ok(job_ids.iter().filter_map(|id| Some((id.clone(), jobs.estimate(id)?))).collect())
+    }
 }
 
 impl<Deps> LsfClient<Deps>
@@ -153,22 +170,17 @@ where
         }
         let result = LsfJobs::new(&out.stdout)?;
 
-        Ok(result
-            .jobs
-            .into_iter()
-            .map(|item| Job {
+        let mut jobs = Vec::with_capacity(result.jobs.len());
+        for item in result.jobs {
+            jobs.push(Job {
+                state: self.job_state(&item).await,
                 id: Arc::from(item.id),
                 name: item.job_name,
                 owner: item.user,
-                state: match item.state.as_str() {
-                    "EXIT" => JobState::Failed,
-                    "DONE" => JobState::Completed,
-                    "RUN|EXITING" => JobState::Running,
-                    _ => JobState::Unknown,
-                },
                 ..Default::default()
-            })
-            .collect::<Vec<_>>())
+            });
+        }
+        Ok(jobs)
     }
 
     async fn get_lsf_job(&self, id: &str) -> anyhow::Result<Job> {
@@ -179,18 +191,32 @@ where
         let result = LsfJobs::new(&out.stdout)?;
         let item = result.jobs.into_iter().next().context("Job not found")?;
         Ok(Job {
+            state: self.job_state(&item).await,
             id: Arc::from(item.id),
             name: item.job_name,
             owner: item.user,
-            state: match item.state.as_str() {
-                "EXIT" => JobState::Failed,
-                "DONE" => JobState::Completed,
-                "RUN|EXITING" => JobState::Running,
-                _ => JobState::Unknown,
-            },
             ..Default::default()
         })
     }
+
+    async fn job_state(&self, item: &LsfJob) -> JobState {
+        match item.state.as_str() {
+            "EXIT" => JobState::Failed(self.failure_reason(&item.id).await),
+            "DONE" => JobState::Completed,
+            "RUN|EXITING" => JobState::Running,
+            _ => JobState::Unknown,
+        }
+    }
+
+    /// The reason of an exited job, taken as its own error if the details are unavailable
+    async fn failure_reason(&self, id: &str) -> FailureReason {
+        match self.prj_ref().command("bjobs").args(["-l", id]).output().await {
+            Ok(out) if out.status.success() => {
+                failure_reason(&String::from_utf8_lossy(&out.stdout))
+            }
+            _ => FailureReason::Application,
+        }
+    }
 }
 
 impl<Deps> LsfClient<Deps>
@@ -208,12 +234,26 @@ where
 
         let env_string = env.join("\n");
         // let touch = format!("echo -n \"{}\" > $PBS_O_WORKDIR/.co.sig", script_info.id);
+        let command = format!("{} {}", script_info.name, script_info.arguments.join(" "));
+        let command = match &script_info.container {
+            Some(container) => container.wrap(&command, "$PWD", &script_info.environments),
+            None => command,
+        };
+        let mut limits = String::new();
+        if let Some(requirements) = &script_info.requirements {
+            // `-W` takes minutes
+            if let Some(wall_time) = requirements.max_wall_time {
+                limits += &format!("-W {} ", wall_time.div_ceil(60));
+            }
+            if let Some(memory) = requirements.memory {
+                limits += &format!("-M {memory}MB ");
+            }
+        }
         let script = format!(
-            "bsub -q {} -o {base_path}/{id}/STDOUT -host_stack 1024 -share_size 15000 -cgsp 64 {} {}",
+            "bsub -q {} -o {base_path}/{id}/STDOUT {limits}-host_stack 1024 -share_size 15000 -cgsp 64 {command}",
             self.queue,
-            script_info.name,
-            script_info.arguments.join(" ")
         );
+        let load_software = &script_info.load_software;
         let script = match script_info.std_in {
             Some(StdInKind::Text { text }) => {
                 format!("{script} << EOF\n{text}\nEOF")
@@ -228,6 +268,7 @@ where
             {header}
             {env_string}
             {include_env}
+            {load_software}
             {script}
         "#}
     }
@@ -1,4 +1,5 @@
 use anyhow::Context;
+use domain::model::{entity::job::FailureReason, vo::job::QueueEstimate};
 use regex::Regex;
 use serde::Deserialize;
 use std::io::BufRead;
@@ -33,6 +34,21 @@ impl LsfJob {
     }
 }
 
+/// Tell the failure reason of an exited job from the termination reason in `bjobs -l`
+pub fn failure_reason(detail: &str) -> FailureReason {
+    // Long lines are wrapped with an indent of 21 spaces
+    let detail = detail.replace(&format!("\n{}", " ".repeat(21)), "");
+    let reason = detail
+        .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .find(|word| word.starts_with("TERM_"));
+    match reason {
+        Some("TERM_RUNLIMIT") => FailureReason::Timeout,
+        Some("TERM_MEMLIMIT") => FailureReason::OutOfMemory,
+        Some("TERM_HOST" | "TERM_LOAD") => FailureReason::NodeFail,
+        _ => FailureReason::Application,
+    }
+}
+
 impl LsfJobs {
     #[inline]
     pub fn new(s: &[u8]) -> anyhow::Result<Self> {
@@ -59,11 +75,72 @@ impl LsfJobs {
     }
 }
 
+/// Pending jobs with their reasons, listed by `bjobs -p`
+#[derive(Debug)]
+pub struct LsfPendingJobs {
+    pub jobs: Vec<LsfPendingJob>,
+}
+
+#[derive(Debug, Default)]
+pub struct LsfPendingJob {
+    pub id: String,
+    pub queue: String,
+    pub reasons: Vec<String>,
+}
+
+impl LsfPendingJobs {
+    pub const ARGS: &'static [&'static str] = &["-p", "-u", "all"];
+
+    pub fn new(s: &[u8]) -> anyhow::Result<Self> {
+        let s = String::from_utf8_lossy(s);
+        let mut lines = s.lines();
+        let header: Vec<&str> = lines.next().unwrap_or_default().split_whitespace().collect();
+        let queue = header.iter().position(|&h| h == "QUEUE").context("QUEUE not found")?;
+
+        let mut jobs: Vec<LsfPendingJob> = Vec::new();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            // Reasons are indented under their job
+            if line.starts_with(char::is_whitespace) {
+                if let Some(job) = jobs.last_mut() {
+                    job.reasons.push(line.trim().trim_end_matches(';').to_string());
+                }
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            jobs.push(LsfPendingJob {
+                id: fields[0].to_string(),
+                queue: fields.get(queue).context("QUEUE not found")?.to_string(),
+                reasons: Vec::new(),
+            });
+        }
+        Ok(Self { jobs })
+    }
+
+    /// The estimate of the job, positioned among the pending jobs of its queue.
+    /// LSF doesn't estimate the start time without plan-based scheduling.
+    pub fn estimate(&self, id: &str) -> Option<QueueEstimate> {
+        let job = self.jobs.iter().find(|job| job.id == id)?;
+        let position = self
+            .jobs
+            .iter()
+            .filter(|other| other.queue == job.queue)
+            .position(|other| other.id == id)
+            .map(|i| i + 1);
+        Some(QueueEstimate {
+            start_time: None,
+            reason: (!job.reasons.is_empty()).then(|| job.reasons.join("; ")),
+            position,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::infrastructure::service::job_scheduler::LsfJob;
 
-    use super::LsfJobs;
+    use domain::model::entity::job::FailureReason;
+
+    use super::{failure_reason, LsfJobs, LsfPendingJobs};
     use indoc::indoc;
 
     #[test]
@@ -84,4 +161,52 @@ mod tests {
         let id = LsfJob::parse_job_id(sub_std_out).unwrap();
         assert_eq!("3407845", id);
     }
+
+    #[test]
+    fn pending_job_estimate() {
+        let out = indoc! {"
+            JOBID   USER    STAT  QUEUE      FROM_HOST   JOB_NAME   SUBMIT_TIME
+            3402271 user1   PEND  q_share    sn01        job1       Dec 26 14:50
+             New job is waiting for scheduling: 1 host;
+            3402272 user2   PEND  q_share    sn01        job2       Dec 26 14:51
+             Job's requirements for reserving resource (mem) not satisfied: 2 hosts;
+             The user has reached the job slot limit;
+        "};
+        let jobs = LsfPendingJobs::new(out.as_bytes()).unwrap();
+        let estimate = jobs.estimate("3402272").unwrap();
+        assert_eq!(estimate.position, Some(2));
+        assert_eq!(
+            estimate.reason.as_deref(),
+            Some(
+                "Job's requirements for reserving resource (mem) not satisfied: 2 hosts; \
+                 The user has reached the job slot limit"
+            )
+        );
+        assert!(jobs.estimate("3402273").is_none());
+    }
+
+    #[test]
+    fn exit_failure_reason() {
+        let detail = indoc! {"
+            Job <3402265>, Job Name <07bc9b07>, User <suanwang>, Project <default>, Status
+                                 <EXIT>, Queue <q_share>, Command <vasp_std>
+            Tue Dec 26 14:50:12: Submitted from host <sn01>, CWD <$HOME>;
+            Tue Dec 26 16:50:14: Exited with exit code 140. The CPU time used is 7200.0 se
+                                 conds.
+            Tue Dec 26 16:50:14: Completed <exit>; TERM_RUNLIMIT: job killed after reachin
+                                 g LSF run time limit.
+        "};
+        assert_eq!(failure_reason(detail), FailureReason::Timeout);
+
+        let wrapped = "Completed <exit>; TERM_MEMLI\n                     MIT: job killed";
+        assert_eq!(failure_reason(wrapped), FailureReason::OutOfMemory);
+        assert_eq!(
+            failure_reason("Completed <exit>; TERM_HOST: job killed"),
+            FailureReason::NodeFail
+        );
+        assert_eq!(
+            failure_reason("Exited with exit code 1."),
+            FailureReason::Application
+        );
+    }
 }
@@ -3,3 +3,17 @@ mod slurm;
 mod lsf;
 
 pub use self::{pbs::*, slurm::*,lsf::*};
+
+/// Seconds as `HH:MM:SS`, accepted by the time limits of all the schedulers
+fn format_duration(duration: usize) -> String {
+    let hours = duration / 3600;
+    let minutes = duration % 3600 / 60;
+    let seconds = duration % 3600 % 60;
+
+    format!(
+        "{}:{}:{}",
+        format_args!("{hours:0>2}"),
+        format_args!("{minutes:0>2}"),
+        format_args!("{seconds:0>2}")
+    )
+}
@@ -1,3 +1,5 @@
+use chrono::NaiveDateTime;
+use domain::model::vo::job::QueueEstimate;
 use serde::*;
 use std::collections::HashMap;
 
@@ -97,3 +99,109 @@ pub struct ResourceList {
     pub place: String,
     pub select: String,
 }
+
+/// Queued jobs listed by `qstat -i -f -F json`
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct PBSQueuedJobs {
+    #[serde(rename = "Jobs", default)]
+    pub jobs: HashMap<String, PBSQueuedJob>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct PBSQueuedJob {
+    pub queue: String,
+    #[serde(rename = "Priority", default)]
+    pub priority: i64,
+    #[serde(default)]
+    pub qtime: String,
+    pub comment: Option<String>,
+    pub estimated: Option<Estimated>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Deserialize)]
+pub struct Estimated {
+    pub start_time: Option<String>,
+}
+
+impl PBSQueuedJobs {
+    pub const ARGS: &'static [&'static str] = &["-i", "-f", "-F", "json"];
+
+    /// The estimate of the job, positioned by priority and queued time among the jobs of its queue
+    pub fn estimate(&self, id: &str) -> Option<QueueEstimate> {
+        let (id, job) = self.jobs.iter().find(|(key, _)| same_job(key, id))?;
+        let mut queue: Vec<_> =
+            self.jobs.iter().filter(|(_, other)| other.queue == job.queue).collect();
+        queue.sort_by_key(|(key, other)| (-other.priority, parse_time(&other.qtime), key.as_str()));
+        let position = queue.iter().position(|(key, _)| *key == id).map(|i| i + 1);
+
+        Some(QueueEstimate {
+            start_time: job
+                .estimated
+                .as_ref()
+                .and_then(|estimated| estimated.start_time.as_deref())
+                .map(|time| {
+                    parse_time(time).map_or_else(
+                        || time.to_owned(),
+                        |t| t.format("%Y-%m-%d %H:%M:%S").to_string(),
+                    )
+                }),
+            reason: job
+                .comment
+                .as_deref()
+                .map(|comment| comment.trim_start_matches("Not Running:").trim().to_owned())
+                .filter(|reason| !reason.is_empty()),
+            position,
+        })
+    }
+}
+
+/// Whether the job ids are the same, with or without the server suffix like `1234.pbs01`
+fn same_job(a: &str, b: &str) -> bool {
+    a == b || a.split('.').next() == b.split('.').next()
+}
+
+fn parse_time(time: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(time, "%a %b %d %T %Y").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use domain::model::vo::job::QueueEstimate;
+
+    use super::PBSQueuedJobs;
+
+    #[test]
+    fn queued_job_estimate() {
+        let s = r#"{
+            "Jobs": {
+                "1001.pbs01": {
+                    "queue": "workq",
+                    "Priority": 0,
+                    "qtime": "Sun Oct 18 09:00:00 2026",
+                    "comment": "Not Running: Insufficient amount of resource: ncpus",
+                    "estimated": { "start_time": "Sun Oct 18 12:00:00 2026" }
+                },
+                "1002.pbs01": {
+                    "queue": "workq",
+                    "Priority": 10,
+                    "qtime": "Sun Oct 18 10:00:00 2026"
+                },
+                "1003.pbs01": {
+                    "queue": "gpu",
+                    "qtime": "Sun Oct 18 08:00:00 2026"
+                }
+            }
+        }"#;
+        let jobs: PBSQueuedJobs = serde_json::from_str(s).unwrap();
+        assert_eq!(
+            jobs.estimate("1001"),
+            Some(QueueEstimate {
+                start_time: Some("2026-10-18 12:00:00".to_owned()),
+                reason: Some("Insufficient amount of resource: ncpus".to_owned()),
+                position: Some(2),
+            })
+        );
+        assert_eq!(jobs.estimate("1002.pbs01").unwrap().position, Some(1));
+        assert_eq!(jobs.estimate("1004"), None);
+    }
+}
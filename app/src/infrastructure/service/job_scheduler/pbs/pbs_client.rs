@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::io;
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -8,11 +9,11 @@ use dep_inj::DepInj;
 use domain::{
     model::{
         entity::{
-            job::{JobResources, JobState},
+            job::{FailureReason, JobResources, JobState},
             task::execute_usecase::StdInKind,
             Job,
         },
-        vo::job::ScriptInfo,
+        vo::job::{QueueEstimate, ScriptInfo},
     },
     service::JobScheduler,
 };
@@ -20,7 +21,8 @@ use indoc::formatdoc;
 use tokio::{fs, process::Command};
 use walkdir::WalkDir;
 
-use super::PBSJobs;
+use super::super::format_duration;
+use super::{PBSJobs, PBSQueuedJobs};
 use crate::infrastructure::command::{MaybeSsh, Scp};
 
 #[derive(DepInj)]
@@ -157,6 +159,21 @@ where
         }
         Ok(())
     }
+
+    async fn queue_estimates(
+        &self,
+        job_ids: &[String],
+    ) -> anyhow::Result<HashMap<String, QueueEstimate>> {
+        let out = self.prj_ref().command("qstat").args(PBSQueuedJobs::ARGS).output().await?;
+        if !out.status.success() {
+            anyhow::bail!(
+                "Exit Status not 0 for queue_estimates. real: {}",
+                out.status
+            )
+        }
+        let jobs: PBSQueuedJobs = serde_json::from_slice(&out.stdout)?;
+        Ok(job_ids.iter().filter_map(|id| Some((id.clone(), jobs.estimate(id)?))).collect())
+    }
 }
 
 impl<Deps> PbsClient<Deps>
@@ -180,14 +197,14 @@ where
                     "R" => JobState::Running,
                     "E" => {
                         if item.exit_status != 0 || item.exit_status != 254 {
-                            JobState::Failed
+                            JobState::Failed(failure_reason(item.exit_status))
                         } else {
                             JobState::Completing
                         }
                     }
                     "F" => {
                         if item.exit_status != 0 || item.exit_status != 254 {
-                            JobState::Failed
+                            JobState::Failed(failure_reason(item.exit_status))
                         } else {
                             JobState::Completed
                         }
@@ -264,14 +281,14 @@ where
                         "R" => JobState::Running,
                         "E" => {
                             if temp.exit_status_code != 0 || temp.exit_status_code != 254 {
-                                JobState::Failed
+                                JobState::Failed(failure_reason(temp.exit_status_code))
                             } else {
                                 JobState::Completing
                             }
                         }
                         "F" => {
                             if temp.exit_status_code != 0 || temp.exit_status_code != 254 {
-                                JobState::Failed
+                                JobState::Failed(failure_reason(temp.exit_status_code))
                             } else {
                                 JobState::Completed
                             }
@@ -308,10 +325,10 @@ where
                     let value = line.replace("stime = ", "");
                     temp.resource_used.start_time = parse_time(&value);
                 } else if line.starts_with("mtime = ") {
-                    if temp.state == JobState::Failed
-                        || temp.state == JobState::Completed
-                        || temp.state == JobState::Completing
-                    {
+                    if matches!(
+                        temp.state,
+                        JobState::Failed(_) | JobState::Completed | JobState::Completing
+                    ) {
                         let value = line.replace("mtime = ", "");
                         temp.resource_used.end_time = parse_time(&value);
                     }
@@ -349,14 +366,14 @@ where
                 "R" => JobState::Running,
                 "E" => {
                     if item.exit_status != 0 && item.exit_status != 254 {
-                        JobState::Failed
+                        JobState::Failed(failure_reason(item.exit_status))
                     } else {
                         JobState::Completing
                     }
                 }
                 "F" => {
                     if item.exit_status != 0 && item.exit_status != 254 {
-                        JobState::Failed
+                        JobState::Failed(failure_reason(item.exit_status))
                     } else {
                         JobState::Completed
                     }
@@ -422,14 +439,14 @@ where
                         "R" => JobState::Running,
                         "E" => {
                             if temp.exit_status_code != 0 && temp.exit_status_code != 254 {
-                                JobState::Failed
+                                JobState::Failed(failure_reason(temp.exit_status_code))
                             } else {
                                 JobState::Completing
                             }
                         }
                         "F" => {
                             if temp.exit_status_code != 0 && temp.exit_status_code != 254 {
-                                JobState::Failed
+                                JobState::Failed(failure_reason(temp.exit_status_code))
                             } else {
                                 JobState::Completed
                             }
@@ -465,10 +482,10 @@ where
                     let value = line.replace("stime = ", "");
                     temp.resource_used.start_time = parse_time(&value);
                 } else if line.starts_with("mtime = ") {
-                    if temp.state == JobState::Failed
-                        || temp.state == JobState::Completed
-                        || temp.state == JobState::Completing
-                    {
+                    if matches!(
+                        temp.state,
+                        JobState::Failed(_) | JobState::Completed | JobState::Completing
+                    ) {
                         let value = line.replace("mtime = ", "");
                         temp.resource_used.end_time = parse_time(&value);
                     }
@@ -502,6 +519,10 @@ impl<Deps> PbsClient<Deps> {
         let env_string = env.join("\n");
         let touch = format!("echo -n \"{}\" > $PBS_O_WORKDIR/.co.sig", script_info.id);
         let script = format!("{} {}", script_info.name, script_info.arguments.join(" "));
+        let script = match &script_info.container {
+            Some(container) => container.wrap(&script, "$PBS_O_WORKDIR", &script_info.environments),
+            None => script,
+        };
         let script = match script_info.std_in {
             Some(StdInKind::Text { text }) => {
                 format!("{script} << EOF\n{text}\nEOF")
@@ -542,6 +563,11 @@ impl<Deps> PbsClient<Deps> {
                     None => String::default(),
                 }
                 .as_str();
+                header += match x.memory {
+                    Some(x) => format!("#PBS -l mem={x}mb\n"),
+                    None => String::default(),
+                }
+                .as_str();
                 header
             }
         };
@@ -563,6 +589,19 @@ impl<Deps> PbsClient<Deps> {
     }
 }
 
+/// Tell the failure reason from the special exit status set by PBS
+fn failure_reason(exit_status: i32) -> FailureReason {
+    match exit_status {
+        // JOB_EXEC_RETRY: the job failed to start on the node
+        -3 => FailureReason::NodeFail,
+        // JOB_EXEC_KILL_MEM
+        -27 => FailureReason::OutOfMemory,
+        // JOB_EXEC_KILL_WALLTIME
+        -29 => FailureReason::Timeout,
+        _ => FailureReason::Application,
+    }
+}
+
 fn parse_time(time: &str) -> i64 {
     time.ne("UNKNOWN")
         .then(|| {
@@ -593,19 +632,6 @@ fn parse_duration(duration: &str) -> u64 {
     second
 }
 
-fn format_duration(duration: usize) -> String {
-    let hours = duration / 3600;
-    let minutes = duration % 3600 / 60;
-    let seconds = duration % 3600 % 60;
-
-    format!(
-        "{}:{}:{}",
-        format_args!("{hours:0>2}"),
-        format_args!("{minutes:0>2}"),
-        format_args!("{seconds:0>2}")
-    )
-}
-
 fn parse_memory(memory: &str) -> u64 {
     let unit = memory.trim_start_matches(char::is_numeric);
     let size = memory.trim_end_matches(char::is_alphabetic).parse().unwrap_or(0u64);
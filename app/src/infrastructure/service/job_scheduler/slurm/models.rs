@@ -1,3 +1,4 @@
+use domain::model::vo::job::QueueEstimate;
 use serde::*;
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -32,6 +33,59 @@ pub struct SlurmJob {
     pub nnodes: u64,
 }
 
+/// A pending job listed by `squeue --start`
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct SlurmPendingJob {
+    pub job_id: String,
+    pub partition: String,
+    pub start_time: String,
+    pub reason: String,
+}
+
+impl SlurmPendingJob {
+    /// Pending jobs sorted by priority
+    pub const ARGS: &'static [&'static str] = &[
+        "--start",
+        "-h",
+        "-t",
+        "PD",
+        "--sort=-p,i",
+        "-o",
+        "'%i|%P|%S|%r'",
+    ];
+
+    pub fn parse_all(s: &[u8]) -> Vec<Self> {
+        String::from_utf8_lossy(s)
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.trim().trim_matches('\'').split('|');
+                Some(Self {
+                    job_id: fields.next()?.to_owned(),
+                    partition: fields.next()?.to_owned(),
+                    start_time: fields.next()?.to_owned(),
+                    reason: fields.next()?.to_owned(),
+                })
+            })
+            .collect()
+    }
+
+    /// The estimate of the job, positioned among the pending jobs of its partition
+    pub fn estimate(jobs: &[Self], id: &str) -> Option<QueueEstimate> {
+        let job = jobs.iter().find(|job| job.job_id == id)?;
+        let position = jobs
+            .iter()
+            .filter(|other| other.partition == job.partition)
+            .position(|other| other.job_id == id)
+            .map(|i| i + 1);
+        Some(QueueEstimate {
+            start_time: (!matches!(job.start_time.as_str(), "N/A" | "Unknown" | ""))
+                .then(|| job.start_time.replace('T', " ")),
+            reason: (!matches!(job.reason.as_str(), "None" | "")).then(|| job.reason.clone()),
+            position,
+        })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -52,4 +106,27 @@ mod tests {
             let _record: SlurmJob = record.unwrap();
         }
     }
+
+    #[test]
+    fn pending_job_estimate() {
+        let x = indoc! {"
+            '1003|gpu|2026-10-18T12:00:00|Resources'
+            '1001|cpu|N/A|Priority'
+            '1002|cpu|2026-10-19T08:30:00|QOSMaxJobsPerUser'
+        "};
+        let jobs = SlurmPendingJob::parse_all(x.as_bytes());
+        assert_eq!(
+            SlurmPendingJob::estimate(&jobs, "1002"),
+            Some(QueueEstimate {
+                start_time: Some("2026-10-19 08:30:00".to_owned()),
+                reason: Some("QOSMaxJobsPerUser".to_owned()),
+                position: Some(2),
+            })
+        );
+        assert_eq!(
+            SlurmPendingJob::estimate(&jobs, "1001").unwrap().start_time,
+            None
+        );
+        assert_eq!(SlurmPendingJob::estimate(&jobs, "1004"), None);
+    }
 }
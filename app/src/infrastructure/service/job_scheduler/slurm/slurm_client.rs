@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
 
@@ -5,18 +6,19 @@ use dep_inj::DepInj;
 use domain::{
     model::{
         entity::{
-            job::{JobResources, JobState},
+            job::{FailureReason, JobResources, JobState},
             task::execute_usecase::StdInKind,
             Job,
         },
-        vo::job::ScriptInfo,
+        vo::job::{QueueEstimate, ScriptInfo},
     },
     service::JobScheduler,
 };
 use indoc::formatdoc;
 use tokio::process::Command;
 
-use super::SlurmJob;
+use super::super::format_duration;
+use super::{SlurmJob, SlurmPendingJob};
 use crate::infrastructure::command::{MaybeSsh, Scp};
 
 #[derive(DepInj)]
@@ -68,16 +70,7 @@ where
                 id: Arc::from(record.job_id),
                 name: record.job_name,
                 owner: record.user,
-                state: match record.state.as_str() {
-                    "BOOT_FAIL" | "FAILED" | "NODE_FAIL" | "OUT_OF_MEMORY" | "TIMEOUT"
-                    | "DEADLINE" => JobState::Failed,
-                    "CANCELLED" => JobState::Suspended,
-                    "COMPLETED" => JobState::Completed,
-                    "PENDING" => JobState::Queuing,
-                    "COMPLETING" => JobState::Completing,
-                    "RUNNING" => JobState::Running,
-                    _ => JobState::Unknown,
-                },
+                state: parse_state(&record.state),
                 exit_status_code: record.exit_code.split(':').next().unwrap_or("0").parse()?,
                 error_output: tokio::fs::read_to_string(format!("{}/STDERR", record.work_dir))
                     .await
@@ -122,16 +115,7 @@ where
                 id: Arc::from(record.job_id),
                 name: record.job_name,
                 owner: record.user,
-                state: match record.state.as_str() {
-                    "BOOT_FAIL" | "FAILED" | "NODE_FAIL" | "OUT_OF_MEMORY" | "TIMEOUT"
-                    | "DEADLINE" => JobState::Failed,
-                    "CANCELLED" => JobState::Suspended,
-                    "COMPLETED" => JobState::Completed,
-                    "PENDING" => JobState::Queuing,
-                    "COMPLETING" => JobState::Completing,
-                    "RUNNING" => JobState::Running,
-                    _ => JobState::Unknown,
-                },
+                state: parse_state(&record.state),
                 exit_status_code: record.exit_code.split(':').next().unwrap_or("0").parse()?,
                 error_output: tokio::fs::read_to_string(format!("{}/STDERR", record.work_dir))
                     .await
@@ -288,6 +272,24 @@ where
         }
         Ok(())
     }
+
+    async fn queue_estimates(
+        &self,
+        job_ids: &[String],
+    ) -> anyhow::Result<HashMap<String, QueueEstimate>> {
+        let out = self.prj_ref().command("squeue").args(SlurmPendingJob::ARGS).output().await?;
+        if !out.status.success() {
+            anyhow::bail!(
+                "Exit Status not 0 for queue_estimates. real: {}",
+                out.status
+            )
+        }
+        let jobs = SlurmPendingJob::parse_all(&out.stdout);
+        Ok(job_ids
+            .iter()
+            .filter_map(|id| Some((id.clone(), SlurmPendingJob::estimate(&jobs, id)?)))
+            .collect())
+    }
 }
 
 impl<Deps> SlurmClient<Deps>
@@ -305,6 +307,12 @@ where
         let env_string = env.join("\n");
         let touch = format!("echo -n \"{}\" > $SLURM_SUBMIT_DIR/.co.sig", parent_id);
         let script = format!("{} {}", script_info.name, script_info.arguments.join(" "));
+        let script = match &script_info.container {
+            Some(container) => {
+                container.wrap(&script, "$SLURM_SUBMIT_DIR", &script_info.environments)
+            }
+            None => script,
+        };
         let script = match script_info.std_in {
             Some(StdInKind::Text { text }) => {
                 format!("{script} << EOF\n{text}\nEOF")
@@ -345,12 +353,18 @@ where
                 }
                 .as_str();
                 header += match x.max_wall_time {
-                    Some(x) => format!("#SBATCH --time={}\n", x),
+                    // Slurm takes a bare number as minutes
+                    Some(x) => format!("#SBATCH --time={}\n", format_duration(x)),
                     None => String::default(),
                 }
                 .as_str();
                 header += match x.max_cpu_time {
-                    Some(x) => format!("#SBATCH --time={}\n", x / cores),
+                    Some(x) => format!("#SBATCH --time={}\n", format_duration(x / cores)),
+                    None => String::default(),
+                }
+                .as_str();
+                header += match x.memory {
+                    Some(x) => format!("#SBATCH --mem={x}M\n"),
                     None => String::default(),
                 }
                 .as_str();
@@ -375,6 +389,21 @@ where
     }
 }
 
+fn parse_state(state: &str) -> JobState {
+    match state {
+        "BOOT_FAIL" | "NODE_FAIL" => JobState::Failed(FailureReason::NodeFail),
+        "OUT_OF_MEMORY" => JobState::Failed(FailureReason::OutOfMemory),
+        "TIMEOUT" => JobState::Failed(FailureReason::Timeout),
+        "FAILED" | "DEADLINE" => JobState::Failed(FailureReason::Application),
+        "CANCELLED" => JobState::Suspended,
+        "COMPLETED" => JobState::Completed,
+        "PENDING" => JobState::Queuing,
+        "COMPLETING" => JobState::Completing,
+        "RUNNING" => JobState::Running,
+        _ => JobState::Unknown,
+    }
+}
+
 fn parse_time(time: &str) -> i64 {
     if time.eq("UNKNOWN") {
         return 0;
@@ -3,21 +3,28 @@ use reqwest_middleware::ClientWithMiddleware;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+/// The scope requesting an offline token
+pub const OFFLINE_ACCESS: &str = "offline_access";
+
 #[derive(Debug, Deserialize)]
 pub struct GrantInfo {
     pub access_token: String,
+    /// Absent in the client credentials grant
+    #[serde(default)]
     pub refresh_token: String,
 }
 
-pub async fn login(client: &Client, url: Url, client_id: &str) -> reqwest::Result<LoginInfo> {
-    client
-        .post(url)
-        .form(&[("client_id", client_id)])
-        .send()
-        .await?
-        .error_for_status()?
-        .json()
-        .await
+pub async fn login(
+    client: &Client,
+    url: Url,
+    client_id: &str,
+    scope: Option<&str>,
+) -> reqwest::Result<LoginInfo> {
+    let mut params = vec![("client_id", client_id)];
+    if let Some(scope) = scope {
+        params.push(("scope", scope));
+    }
+    client.post(url).form(&params).send().await?.error_for_status()?.json().await
 }
 
 pub fn grant_request(
@@ -54,6 +61,27 @@ pub async fn refresh_token(
         .map_err(|e| e.into())
 }
 
+pub async fn client_credentials(
+    client: &ClientWithMiddleware,
+    url: impl IntoUrl,
+    client_id: &str,
+    client_secret: &str,
+) -> reqwest_middleware::Result<GrantInfo> {
+    client
+        .post(url)
+        .form(&ClientCredentialsParams {
+            grant_type: "client_credentials",
+            client_id,
+            client_secret,
+        })
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+        .map_err(|e| e.into())
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LoginInfo {
     pub device_code: String,
@@ -75,3 +103,10 @@ struct RefreshTokenParams<'a> {
     client_id: &'a str,
     refresh_token: &'a str,
 }
+
+#[derive(Debug, Serialize)]
+struct ClientCredentialsParams<'a> {
+    grant_type: &'static str,
+    client_id: &'a str,
+    client_secret: &'a str,
+}
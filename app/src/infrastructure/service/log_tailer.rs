@@ -0,0 +1,195 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use dep_inj::DepInj;
+use reqwest_middleware::ClientWithMiddleware;
+use service::job::JobServiceState;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use url::Url;
+use uuid::Uuid;
+
+use crate::config::LogTailConfig;
+use crate::dto::{LogStream, TaskLog};
+use crate::infrastructure::command::{MaybeSsh, SshConfig};
+use crate::infrastructure::http::header::TASK_ID;
+
+/// Streams STDOUT and STDERR of running jobs to the backend in chunks,
+/// each with its byte offset so that the backend can resume or dedupe.
+#[derive(DepInj)]
+#[target(LogTailer)]
+pub struct LogTailerState {
+    save_dir: PathBuf,
+    url: Url,
+    client: Arc<ClientWithMiddleware>,
+    chunk_size: u64,
+    /// Bytes of each output sent
+    offsets: Mutex<HashMap<(Uuid, LogStream), u64>>,
+    /// The running jobs tailed last time, with their nodes
+    tailed: Mutex<HashMap<Uuid, String>>,
+}
+
+impl LogTailerState {
+    pub fn new(
+        save_dir: &str,
+        base_url: &Url,
+        client: Arc<ClientWithMiddleware>,
+        config: &LogTailConfig,
+    ) -> Self {
+        Self {
+            save_dir: PathBuf::from(save_dir),
+            url: base_url.join("workflow-engine/ReceiveTaskLog").unwrap(),
+            client,
+            chunk_size: config.chunk_size.0.max(1),
+            offsets: Mutex::default(),
+            tailed: Mutex::default(),
+        }
+    }
+}
+
+impl<Deps> LogTailer<Deps>
+where
+    Deps: AsRef<LogTailerState>
+        + AsRef<JobServiceState>
+        + AsRef<Option<SshConfig>>
+        + MaybeSsh
+        + Send
+        + Sync,
+{
+    /// Send the new outputs of the running jobs,
+    /// and the rest of the outputs of the jobs no longer running before forgetting them
+    pub async fn tail_all(&self) {
+        let running = AsRef::<JobServiceState>::as_ref(self.prj_ref()).running();
+        let ended: Vec<_> = {
+            let mut tailed = self.tailed.lock().unwrap();
+            let ended = tailed
+                .drain()
+                .filter(|(id, _)| !running.iter().any(|(running, _)| running == id))
+                .collect();
+            tailed.extend(running.iter().cloned());
+            ended
+        };
+
+        for (id, node) in running {
+            for stream in LogStream::ALL {
+                if let Err(e) = self.tail(id, &node, stream).await {
+                    tracing::warn!(task_id = %id, "Failed to tail {}: {e:#}", stream.file_name());
+                }
+            }
+        }
+
+        for (id, node) in ended {
+            for stream in LogStream::ALL {
+                if let Err(e) = self.tail_to_end(id, &node, stream).await {
+                    tracing::warn!(task_id = %id, "Failed to tail {}: {e:#}", stream.file_name());
+                }
+            }
+            self.offsets.lock().unwrap().retain(|(task_id, _), _| *task_id != id);
+        }
+    }
+
+    /// Send the output up to its end, which no longer grows
+    async fn tail_to_end(&self, id: Uuid, node: &str, stream: LogStream) -> anyhow::Result<()> {
+        while self.tail(id, node, stream).await? > 0 {}
+        Ok(())
+    }
+
+    /// Send a chunk of the output from the offset sent.
+    ///
+    /// # return
+    ///
+    /// How many bytes are sent.
+    async fn tail(&self, id: Uuid, node: &str, stream: LogStream) -> anyhow::Result<usize> {
+        let offset = self.offsets.lock().unwrap().get(&(id, stream)).copied().unwrap_or_default();
+        let chunk = self.read(node, stream, offset).await?;
+        let (content, len) = decode(&chunk);
+        if len == 0 {
+            return Ok(0);
+        }
+
+        self.client
+            .post(self.url.clone())
+            .header(TASK_ID, id.to_string())
+            .json(&TaskLog {
+                id,
+                stream,
+                offset,
+                content: &content,
+            })
+            .send()
+            .await?
+            .error_for_status()?;
+        self.offsets.lock().unwrap().insert((id, stream), offset + len as u64);
+        Ok(len)
+    }
+
+    /// Read at most a chunk from the offset, nothing if the output isn't created yet
+    async fn read(&self, node: &str, stream: LogStream, offset: u64) -> anyhow::Result<Vec<u8>> {
+        let Some(ssh) = AsRef::<Option<SshConfig>>::as_ref(self.prj_ref()) else {
+            let path = self.save_dir.join(node).join(stream.file_name());
+            let mut file = match File::open(&path).await {
+                Ok(file) => file,
+                Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+                Err(e) => return Err(e.into()),
+            };
+            file.seek(SeekFrom::Start(offset)).await?;
+            let mut chunk = Vec::new();
+            file.take(self.chunk_size).read_to_end(&mut chunk).await?;
+            return Ok(chunk);
+        };
+
+        let file = stream.file_name();
+        let path = format!("{}/{}/{node}/{file}", ssh.home_dir, ssh.save_dir);
+        let output = self
+            .prj_ref()
+            .command("dd")
+            .arg(format!("if={path}"))
+            .args(["iflag=skip_bytes,count_bytes", "status=none"])
+            .arg(format!("skip={offset}"))
+            .arg(format!("count={}", self.chunk_size))
+            .output()
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.contains("No such file") {
+                return Ok(Vec::new());
+            }
+            anyhow::bail!("Failed to read {path}: {stderr}");
+        }
+        Ok(output.stdout)
+    }
+}
+
+/// Decode the chunk, leaving a character cut at the end to the next chunk.
+///
+/// # return
+///
+/// The text and how many bytes of the chunk it takes.
+fn decode(chunk: &[u8]) -> (Cow<'_, str>, usize) {
+    match std::str::from_utf8(chunk) {
+        Ok(s) => (Cow::Borrowed(s), chunk.len()),
+        // Incomplete at the end
+        Err(e) if e.error_len().is_none() => {
+            let len = e.valid_up_to();
+            (String::from_utf8_lossy(&chunk[..len]), len)
+        }
+        Err(_) => (String::from_utf8_lossy(chunk), chunk.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode;
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"step 1\n"), ("step 1\n".into(), 7));
+        // `收` is cut after its first 2 bytes
+        let chunk = "loss 0.1 收".as_bytes();
+        assert_eq!(decode(&chunk[..chunk.len() - 1]), ("loss 0.1 ".into(), 9));
+        assert_eq!(decode(b"a\xffb"), ("a\u{fffd}b".into(), 3));
+    }
+}
@@ -1,11 +1,21 @@
+pub mod deploy_log;
 pub mod download_file;
 pub mod file_load;
+pub mod health;
 pub mod job_scheduler;
 pub mod keycloak;
+pub mod log_tailer;
 pub mod resource_stat;
 mod select_task_service;
+pub mod software_cache;
 pub mod software_deployer;
+pub mod task_queue;
+pub mod task_registry;
 pub mod task_status_reporter;
+pub mod token_manager;
+pub mod token_store;
+pub mod transfer_limit;
 pub mod upload_file;
+pub mod workspace;
 
 pub use self::select_task_service::SelectTaskService;
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+/// Resources of each partition and node
+#[derive(Debug, Default, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Inventory {
+    pub partitions: Vec<PartitionInventory>,
+    pub nodes: Vec<NodeInventory>,
+}
+
+/// Resources summed up over the nodes of a partition (Slurm) or queue (PBS, LSF)
+#[derive(Debug, Default, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PartitionInventory {
+    pub name: String,
+    pub node_number: usize,
+    /// Nodes able to run jobs, i.e. not down, drained or offline
+    pub available_node_number: usize,
+    pub core_number: usize,
+    pub allocated_cpu_count: usize,
+    pub memory: u64,
+    pub allocated_memory: u64,
+}
+
+#[derive(Debug, Default, Clone, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInventory {
+    pub name: String,
+    pub partitions: Vec<String>,
+    /// The state reported by the scheduler
+    pub state: String,
+    pub available: bool,
+    pub core_number: usize,
+    pub allocated_cpu_count: usize,
+    pub memory: u64,
+    pub allocated_memory: u64,
+    pub features: Vec<String>,
+    /// Generic resources like GPUs, e.g. `gpu:a100:4`
+    pub gres: Vec<String>,
+}
+
+impl Inventory {
+    /// Sum up the partitions of the nodes.
+    /// A node listed more than once, e.g. once per partition, is merged.
+    pub fn new(nodes: impl IntoIterator<Item = NodeInventory>) -> Self {
+        let mut merged = BTreeMap::<String, NodeInventory>::new();
+        for node in nodes {
+            match merged.get_mut(&node.name) {
+                Some(existing) => {
+                    for partition in node.partitions {
+                        if !existing.partitions.contains(&partition) {
+                            existing.partitions.push(partition);
+                        }
+                    }
+                }
+                None => {
+                    merged.insert(node.name.clone(), node);
+                }
+            }
+        }
+
+        let mut partitions = BTreeMap::<&str, PartitionInventory>::new();
+        for node in merged.values() {
+            for name in &node.partitions {
+                let partition = partitions.entry(name).or_insert_with(|| PartitionInventory {
+                    name: name.clone(),
+                    ..Default::default()
+                });
+                partition.node_number += 1;
+                partition.available_node_number += node.available as usize;
+                partition.core_number += node.core_number;
+                partition.allocated_cpu_count += node.allocated_cpu_count;
+                partition.memory += node.memory;
+                partition.allocated_memory += node.allocated_memory;
+            }
+        }
+
+        Self {
+            partitions: partitions.into_values().collect(),
+            nodes: merged.into_values().collect(),
+        }
+    }
+
+    /// Whether any node appears, disappears or changes its availability
+    pub fn availability_changed(&self, previous: &Self) -> bool {
+        self.nodes.len() != previous.nodes.len()
+            || self
+                .nodes
+                .iter()
+                .zip(&previous.nodes)
+                .any(|(now, before)| now.name != before.name || now.available != before.available)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Inventory, NodeInventory, PartitionInventory};
+
+    fn node(name: &str, partition: &str, available: bool) -> NodeInventory {
+        NodeInventory {
+            name: name.to_owned(),
+            partitions: vec![partition.to_owned()],
+            available,
+            core_number: 64,
+            allocated_cpu_count: 16,
+            memory: 1 << 30,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_inventory_new() {
+        let inventory = Inventory::new([
+            node("n1", "cpu", true),
+            node("n2", "cpu", false),
+            node("n1", "fat", true),
+        ]);
+        assert_eq!(inventory.nodes.len(), 2);
+        assert_eq!(inventory.nodes[0].partitions, ["cpu", "fat"]);
+        assert_eq!(
+            inventory.partitions,
+            [
+                PartitionInventory {
+                    name: "cpu".to_owned(),
+                    node_number: 2,
+                    available_node_number: 1,
+                    core_number: 128,
+                    allocated_cpu_count: 32,
+                    memory: 2 << 30,
+                    allocated_memory: 0,
+                },
+                PartitionInventory {
+                    name: "fat".to_owned(),
+                    node_number: 1,
+                    available_node_number: 1,
+                    core_number: 64,
+                    allocated_cpu_count: 16,
+                    memory: 1 << 30,
+                    allocated_memory: 0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use anyhow::Context;
+
+/// Batch states of the hosts
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hosts {
+    pub hosts: Vec<Host>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Host {
+    pub name: String,
+    pub status: String,
+    /// Slots used by jobs
+    pub njobs: usize,
+}
+
+impl Hosts {
+    pub const ARGS: &'static [&'static str] = &["-w"];
+
+    pub fn new(s: &[u8]) -> anyhow::Result<Self> {
+        let s = String::from_utf8_lossy(s);
+        let hosts = s
+            .lines()
+            .skip(1)
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let fields: Vec<&str> = line.split_whitespace().collect();
+                let [name, status, _, _, njobs, ..] = fields[..] else {
+                    anyhow::bail!("Unexpected bhosts line: {line}");
+                };
+                Ok(Host {
+                    name: name.to_owned(),
+                    status: status.to_owned(),
+                    njobs: njobs.parse().context("Failed to parse NJOBS")?,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self { hosts })
+    }
+}
+
+impl Host {
+    /// Closed hosts are available only when they are full or busy
+    pub fn available(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            "ok" | "closed_Full" | "closed_Excl" | "closed_Busy"
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::Hosts;
+
+    #[test]
+    fn test_hosts() {
+        let s = indoc! {"
+            HOST_NAME          STATUS       JL/U    MAX  NJOBS    RUN  SSUSP  USUSP    RSV
+            hostA              ok              -     40     16     16      0      0      0
+            hostB              unavail         -     40      0      0      0      0      0
+        "};
+        let hosts = Hosts::new(s.as_bytes()).unwrap();
+        assert_eq!(hosts.hosts.len(), 2);
+        assert_eq!(hosts.hosts[0].njobs, 16);
+        assert!(hosts.hosts[0].available());
+        assert!(!hosts.hosts[1].available());
+    }
+}
@@ -0,0 +1,35 @@
+/// States of the unfinished jobs of all users
+#[derive(Debug, PartialEq, Eq)]
+pub struct Status {
+    stats: Vec<String>,
+}
+
+impl Status {
+    pub const ARGS: &'static [&'static str] = &["-u", "all", "-noheader", "-o", "stat"];
+
+    pub fn new(s: &[u8]) -> Self {
+        Self {
+            stats: String::from_utf8_lossy(s).split_whitespace().map(str::to_owned).collect(),
+        }
+    }
+
+    /// Count of queuing and running jobs
+    pub fn qr_count(&self) -> (usize, usize) {
+        self.stats.iter().fold((0, 0), |(q, r), stat| match stat.as_str() {
+            "PEND" | "PSUSP" => (q + 1, r),
+            "RUN" => (q, r + 1),
+            _ => (q, r),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Status;
+
+    #[test]
+    fn test_status_qr_count() {
+        let s = "RUN\nPEND\nRUN\nDONE\nPSUSP\n";
+        assert_eq!(Status::new(s.as_bytes()).qr_count(), (2, 2));
+    }
+}
@@ -0,0 +1,90 @@
+use std::collections::BTreeMap;
+
+/// Hosts of the queues, from the long format of `bqueues`
+#[derive(Debug, PartialEq, Eq)]
+pub struct Queues {
+    pub queues: BTreeMap<String, QueueHosts>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct QueueHosts {
+    /// Any host except the excluded ones
+    pub all: bool,
+    pub hosts: Vec<String>,
+    pub excluded: Vec<String>,
+}
+
+impl Queues {
+    pub const ARGS: &'static [&'static str] = &["-l"];
+
+    pub fn new(s: &[u8]) -> Self {
+        let s = String::from_utf8_lossy(s);
+        let mut queues = BTreeMap::new();
+        let mut current = None;
+        for line in s.lines().map(str::trim) {
+            if let Some(name) = line.strip_prefix("QUEUE:") {
+                let name = name.trim().to_owned();
+                queues.insert(name.clone(), QueueHosts::default());
+                current = Some(name);
+            } else if let Some(hosts) = line.strip_prefix("HOSTS:") {
+                let Some(queue) = current.as_ref().and_then(|name| queues.get_mut(name)) else {
+                    continue;
+                };
+                for host in hosts.split_whitespace() {
+                    // Host groups end with `/`, which aren't expanded
+                    let host = host.split_once('+').map_or(host, |(host, _)| host);
+                    if host.starts_with("all") {
+                        queue.all = true;
+                    } else if let Some(excluded) = host.strip_prefix('~') {
+                        queue.excluded.push(excluded.to_owned());
+                    } else if !host.ends_with('/') {
+                        queue.hosts.push(host.to_owned());
+                    }
+                }
+            }
+        }
+        Self { queues }
+    }
+
+    /// Queues which the host belongs to
+    pub fn of(&self, host: &str) -> Vec<String> {
+        self.queues
+            .iter()
+            .filter(|(_, q)| {
+                (q.all || q.hosts.iter().any(|h| h == host))
+                    && !q.excluded.iter().any(|h| h == host)
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::Queues;
+
+    #[test]
+    fn test_queues() {
+        let s = indoc! {"
+            QUEUE: normal
+              -- For normal low priority jobs, running only if hosts are lightly loaded.
+
+            PRIO NICE STATUS          MAX JL/U JL/P JL/H NJOBS  PEND   RUN SSUSP USUSP  RSV
+             30   20  Open:Active       -    -    -    -     0     0     0     0     0    0
+
+            USERS: all
+            HOSTS:  all ~hostB
+
+            -------------------------------------------------------------------------------
+
+            QUEUE: gpu
+
+            HOSTS:  hostB+2 gpu_group/
+        "};
+        let queues = Queues::new(s.as_bytes());
+        assert_eq!(queues.of("hostA"), ["normal"]);
+        assert_eq!(queues.of("hostB"), ["gpu"]);
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use anyhow::Context;
+
+/// Static resources of the hosts
+#[derive(Debug, PartialEq, Eq)]
+pub struct HostsInfo {
+    pub hosts: HashMap<String, HostInfo>,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct HostInfo {
+    pub ncpus: usize,
+    /// Unit: byte
+    pub maxmem: u64,
+    /// Boolean resources, e.g. `mg` or `gpu`
+    pub resources: Vec<String>,
+}
+
+impl HostsInfo {
+    pub const ARGS: &'static [&'static str] = &["-w"];
+
+    pub fn new(s: &[u8]) -> anyhow::Result<Self> {
+        let s = String::from_utf8_lossy(s);
+        let mut lines = s.lines();
+        let header: Vec<&str> = lines.next().unwrap_or_default().split_whitespace().collect();
+        let column = |name: &str| header.iter().position(|&h| h == name);
+        let (ncpus, maxmem, resources) = column("ncpus")
+            .zip(column("maxmem"))
+            .zip(column("RESOURCES"))
+            .map(|((ncpus, maxmem), resources)| (ncpus, maxmem, resources))
+            .context("Unexpected lshosts header")?;
+
+        let mut hosts = HashMap::new();
+        for line in lines.filter(|line| !line.trim().is_empty()) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            anyhow::ensure!(
+                fields.len() > maxmem.max(ncpus),
+                "Unexpected lshosts line: {line}"
+            );
+            hosts.insert(
+                fields[0].to_owned(),
+                HostInfo {
+                    // `-` if unknown
+                    ncpus: fields[ncpus].parse().unwrap_or_default(),
+                    maxmem: parse_memory(fields[maxmem]).unwrap_or_default(),
+                    resources: fields
+                        .get(resources..)
+                        .unwrap_or_default()
+                        .iter()
+                        .map(|r| r.trim_matches(|c| c == '(' || c == ')'))
+                        .filter(|r| !r.is_empty())
+                        .map(str::to_owned)
+                        .collect(),
+                },
+            );
+        }
+        Ok(Self { hosts })
+    }
+}
+
+/// Parse memory like `257654M` or `251.6G`, in MB without unit
+fn parse_memory(s: &str) -> Option<u64> {
+    let (number, unit) = match s.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => s.split_at(i),
+        None => (s, "M"),
+    };
+    let scale: u64 = match unit {
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        "T" => 1 << 40,
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    Some((number * scale as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+
+    use super::HostsInfo;
+
+    #[test]
+    fn test_hosts_info() {
+        let s = indoc! {"
+            HOST_NAME                       type       model  cpuf ncpus maxmem maxswp server RESOURCES
+            hostA                        X86_64    Intel_EM  60.0    40 257654M  4096M    Yes (mg gpu)
+            hostB                        X86_64    Intel_EM  60.0     -      -      -    Yes ()
+        "};
+        let info = HostsInfo::new(s.as_bytes()).unwrap();
+        let host_a = &info.hosts["hostA"];
+        assert_eq!(host_a.ncpus, 40);
+        assert_eq!(host_a.maxmem, 257654 << 20);
+        assert_eq!(host_a.resources, ["mg", "gpu"]);
+        assert_eq!(info.hosts["hostB"].maxmem, 0);
+    }
+}
@@ -0,0 +1,106 @@
+mod bhosts;
+mod bjobs;
+mod bqueues;
+mod lshosts;
+
+use anyhow::{bail, Context};
+use dep_inj_target::dep_inj_target;
+
+use super::inventory::{Inventory, NodeInventory};
+use super::{SchedulerStat, SchedulerTotalResources, SchedulerUsedResources};
+use crate::infrastructure::command::MaybeSsh;
+
+#[dep_inj_target]
+pub struct Lsf;
+
+#[async_trait::async_trait]
+impl<Deps> SchedulerStat for Lsf<Deps>
+where
+    Deps: MaybeSsh + Send + Sync,
+{
+    async fn total(&self) -> anyhow::Result<SchedulerTotalResources> {
+        let inventory = self.inventory().await?;
+        Ok(SchedulerTotalResources {
+            memory: inventory.nodes.iter().map(|node| node.memory).sum(),
+            core_number: inventory.nodes.iter().map(|node| node.core_number).sum(),
+            node_number: inventory.nodes.len(),
+        })
+    }
+
+    async fn used(&self) -> anyhow::Result<SchedulerUsedResources> {
+        let output = self.run("bhosts", bhosts::Hosts::ARGS).await?;
+        let hosts = bhosts::Hosts::new(&output)?;
+
+        let output = self
+            .prj_ref()
+            .command("bjobs")
+            .args(bjobs::Status::ARGS)
+            .output()
+            .await
+            .context("bjobs")?;
+        // bjobs fails when there is no job
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() && !stderr.contains("No unfinished job found") {
+            bail!(
+                "bjobs terminated with an exception. Exit status: {}, stderr: {stderr}",
+                output.status,
+            );
+        }
+        let (queuing_task_count, running_task_count) =
+            bjobs::Status::new(&output.stdout).qr_count();
+
+        Ok(SchedulerUsedResources {
+            // bhosts doesn't report memory reserved by jobs
+            allocated_memory: 0,
+            allocated_cpu_count: hosts.hosts.iter().map(|host| host.njobs).sum(),
+            queuing_task_count,
+            running_task_count,
+            used_node_count: hosts.hosts.iter().filter(|host| host.njobs > 0).count(),
+        })
+    }
+
+    async fn inventory(&self) -> anyhow::Result<Inventory> {
+        let hosts = bhosts::Hosts::new(&self.run("bhosts", bhosts::Hosts::ARGS).await?)?;
+        let info = lshosts::HostsInfo::new(&self.run("lshosts", lshosts::HostsInfo::ARGS).await?)?;
+        let queues = bqueues::Queues::new(&self.run("bqueues", bqueues::Queues::ARGS).await?);
+
+        Ok(Inventory::new(hosts.hosts.into_iter().map(|host| {
+            let info = info.hosts.get(&host.name);
+            NodeInventory {
+                partitions: queues.of(&host.name),
+                available: host.available(),
+                core_number: info.map(|info| info.ncpus).unwrap_or_default(),
+                allocated_cpu_count: host.njobs,
+                memory: info.map(|info| info.maxmem).unwrap_or_default(),
+                allocated_memory: 0,
+                features: info.map(|info| info.resources.clone()).unwrap_or_default(),
+                gres: Vec::new(),
+                state: host.status,
+                name: host.name,
+            }
+        })))
+    }
+}
+
+impl<Deps> Lsf<Deps>
+where
+    Deps: MaybeSsh + Send + Sync,
+{
+    async fn run(&self, program: &str, args: &[&str]) -> anyhow::Result<Vec<u8>> {
+        let output = self
+            .prj_ref()
+            .command(program)
+            .args(args)
+            .output()
+            .await
+            .context(program.to_owned())?;
+        if !output.status.success() {
+            bail!(
+                "{program} terminated with an exception. Exit status: {}, stderr: {}",
+                output.status,
+                String::from_utf8(output.stderr)?
+            );
+        }
+        Ok(output.stdout)
+    }
+}
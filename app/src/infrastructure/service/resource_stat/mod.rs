@@ -1,3 +1,5 @@
+mod inventory;
+mod lsf;
 mod pbs;
 mod slurm;
 mod storage;
@@ -8,7 +10,8 @@ use serde::Serialize;
 use self::storage::stat;
 use crate::infrastructure::command::MaybeSsh;
 
-pub use self::{pbs::Pbs, slurm::Slurm};
+pub use self::inventory::Inventory;
+pub use self::{lsf::Lsf, pbs::Pbs, slurm::Slurm};
 
 #[async_trait::async_trait]
 pub trait ResourceStat {
@@ -20,6 +23,7 @@ pub trait ResourceStat {
 pub trait SchedulerStat {
     async fn total(&self) -> anyhow::Result<SchedulerTotalResources>;
     async fn used(&self) -> anyhow::Result<SchedulerUsedResources>;
+    async fn inventory(&self) -> anyhow::Result<Inventory>;
 }
 
 /// Total resources counted by scheduler
@@ -47,6 +51,9 @@ pub struct TotalResources {
     core_number: usize,
     storage_capacity: u64,
     node_number: usize,
+    /// Resources of each partition and node, if the scheduler reports them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inventory: Option<Inventory>,
 }
 
 #[derive(Debug, Serialize)]
@@ -58,6 +65,51 @@ pub struct UsedResources {
     queuing_task_count: usize,
     running_task_count: usize,
     used_node_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inventory: Option<Inventory>,
+}
+
+impl TotalResources {
+    /// Values keyed by their names
+    pub fn values(&self) -> [(&'static str, u64); 4] {
+        [
+            ("memory", self.memory),
+            ("core_number", self.core_number as u64),
+            ("storage_capacity", self.storage_capacity),
+            ("node_number", self.node_number as u64),
+        ]
+    }
+
+    /// Whether the number of nodes or their availability changes,
+    /// or any other value changes by more than the ratio
+    pub fn changed(&self, previous: &Self, threshold: f64) -> bool {
+        if self.node_number != previous.node_number {
+            return true;
+        }
+        if let (Some(now), Some(before)) = (&self.inventory, &previous.inventory) {
+            if now.availability_changed(before) {
+                return true;
+            }
+        }
+        self.values()
+            .iter()
+            .zip(previous.values())
+            .any(|(&(_, now), (_, before))| now.abs_diff(before) as f64 > before as f64 * threshold)
+    }
+}
+
+impl UsedResources {
+    /// Values keyed by their names
+    pub fn values(&self) -> [(&'static str, u64); 6] {
+        [
+            ("allocated_memory", self.allocated_memory),
+            ("allocated_cpu_count", self.allocated_cpu_count as u64),
+            ("used_storage", self.used_storage),
+            ("queuing_task_count", self.queuing_task_count as u64),
+            ("running_task_count", self.running_task_count as u64),
+            ("used_node_count", self.used_node_count as u64),
+        ]
+    }
 }
 
 #[dep_inj_target]
@@ -75,12 +127,14 @@ where
             node_number,
         } = self.prj_ref().total().await?;
         let storage_capacity = self.total_storage().await?;
+        let inventory = self.inventory().await;
 
         Ok(TotalResources {
             memory,
             core_number,
             storage_capacity,
             node_number,
+            inventory,
         })
     }
 
@@ -93,6 +147,7 @@ where
             used_node_count,
         } = self.prj_ref().used().await?;
         let used_storage = self.used_storage().await?;
+        let inventory = self.inventory().await;
 
         Ok(UsedResources {
             allocated_memory,
@@ -101,10 +156,25 @@ where
             queuing_task_count,
             running_task_count,
             used_node_count,
+            inventory,
         })
     }
 }
 
+impl<Deps> ResourceStatImpl<Deps>
+where
+    Deps: SchedulerStat + Send + Sync,
+{
+    /// The inventory is optional, so a failure doesn't fail reporting the aggregate values
+    async fn inventory(&self) -> Option<Inventory> {
+        self.prj_ref()
+            .inventory()
+            .await
+            .map_err(|e| tracing::warn!("Failed to stat resource inventory: {e:#}"))
+            .ok()
+    }
+}
+
 impl<Deps> ResourceStatImpl<Deps>
 where
     Deps: MaybeSsh + Send + Sync,
@@ -125,3 +195,27 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::TotalResources;
+
+    fn total(memory: u64, node_number: usize) -> TotalResources {
+        TotalResources {
+            memory,
+            core_number: 64,
+            storage_capacity: 1 << 40,
+            node_number,
+            inventory: None,
+        }
+    }
+
+    #[test]
+    fn test_total_changed() {
+        let before = total(1000, 4);
+        assert!(!total(1000, 4).changed(&before, 0.05));
+        assert!(!total(1040, 4).changed(&before, 0.05));
+        assert!(total(900, 4).changed(&before, 0.05));
+        assert!(total(1000, 3).changed(&before, 0.05));
+    }
+}
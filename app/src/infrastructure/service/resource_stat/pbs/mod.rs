@@ -4,7 +4,8 @@ mod qstat;
 use anyhow::{bail, Context};
 use dep_inj_target::dep_inj_target;
 
-use self::pbsnodes::{NodeAssigned, NodeAvailable};
+use self::pbsnodes::{NodeAssigned, NodeAvailable, NodeInfo};
+use super::inventory::Inventory;
 use super::{SchedulerStat, SchedulerTotalResources, SchedulerUsedResources};
 use crate::infrastructure::command::MaybeSsh;
 
@@ -84,4 +85,23 @@ where
             used_node_count,
         })
     }
+
+    async fn inventory(&self) -> anyhow::Result<Inventory> {
+        let output = self
+            .prj_ref()
+            .command("pbsnodes")
+            .args(pbsnodes::Status::<NodeInfo>::ARGS)
+            .output()
+            .await
+            .context("pbsnodes")?;
+        if !output.status.success() {
+            bail!(
+                "pbsnodes terminated with an exception. Exit status: {}, stderr: {}",
+                output.status,
+                String::from_utf8(output.stderr)?
+            );
+        }
+
+        Ok(pbsnodes::Status::<NodeInfo>::new(&output.stdout)?.inventory())
+    }
 }
@@ -1,8 +1,11 @@
 use bytesize::ByteSize;
 use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::Value;
 use std::collections::HashMap;
 use std::ops::Add;
 
+use crate::infrastructure::service::resource_stat::inventory::{Inventory, NodeInventory};
+
 #[derive(Debug, Deserialize)]
 pub struct Status<Node> {
     nodes: HashMap<String, Node>,
@@ -21,7 +24,26 @@ pub struct NodeAssigned {
     jobs: Option<Vec<String>>,
 }
 
+/// node with its state and resources
+#[derive(Debug, Deserialize)]
+pub struct NodeInfo {
+    state: String,
+    queue: Option<String>,
+    resources_available: CustomResources,
+    #[serde(default)]
+    resources_assigned: Resources,
+}
+
+/// Resources including the custom ones, e.g. `ngpus` or a boolean for a feature
 #[derive(Debug, Deserialize)]
+struct CustomResources {
+    #[serde(flatten)]
+    base: Resources,
+    #[serde(flatten)]
+    custom: HashMap<String, Value>,
+}
+
+#[derive(Debug, Deserialize, Default)]
 struct Resources {
     mem: Option<ByteSize>,
     ncpus: Option<usize>,
@@ -72,6 +94,49 @@ impl Status<NodeAssigned> {
     }
 }
 
+impl Status<NodeInfo> {
+    pub fn inventory(&self) -> Inventory {
+        Inventory::new(self.nodes.iter().map(|(name, node)| {
+            let mut features = Vec::new();
+            let mut gres = Vec::new();
+            for (resource, value) in &node.resources_available.custom {
+                match value {
+                    Value::Bool(true) => features.push(resource.clone()),
+                    Value::String(s) if s.eq_ignore_ascii_case("true") => {
+                        features.push(resource.clone())
+                    }
+                    Value::Number(n) if !matches!(resource.as_str(), "ncpus" | "mem") => {
+                        if let Some(n) = n.as_u64().filter(|&n| n > 0) {
+                            gres.push(format!("{resource}:{n}"));
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            features.sort();
+            gres.sort();
+
+            NodeInventory {
+                name: name.clone(),
+                partitions: node.queue.iter().cloned().collect(),
+                state: node.state.clone(),
+                available: node.state.split(',').all(|state| {
+                    !matches!(
+                        state.trim(),
+                        "down" | "offline" | "state-unknown" | "unknown" | "stale" | "maintenance"
+                    )
+                }),
+                core_number: node.resources_available.base.ncpus.unwrap_or_default(),
+                allocated_cpu_count: node.resources_assigned.ncpus.unwrap_or_default(),
+                memory: node.resources_available.base.mem.unwrap_or_default().0,
+                allocated_memory: node.resources_assigned.mem.unwrap_or_default().0,
+                features,
+                gres,
+            }
+        }))
+    }
+}
+
 impl Add<&Resources> for SumResources {
     type Output = Self;
 
@@ -82,3 +147,50 @@ impl Add<&Resources> for SumResources {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{NodeInfo, Status};
+
+    #[test]
+    fn test_status_inventory() {
+        let s = r#"{
+            "pbs_version": "19.1.3",
+            "nodes": {
+                "node01": {
+                    "state": "job-busy",
+                    "queue": "fat",
+                    "resources_available": {
+                        "arch": "linux",
+                        "host": "node01",
+                        "mem": "196608kb",
+                        "ncpus": 48,
+                        "ngpus": 4,
+                        "nvlink": true
+                    },
+                    "resources_assigned": {
+                        "mem": "98304kb",
+                        "ncpus": 48
+                    }
+                },
+                "node02": {
+                    "state": "down,offline",
+                    "resources_available": {
+                        "mem": "196608kb",
+                        "ncpus": 48
+                    }
+                }
+            }
+        }"#;
+        let inventory = Status::<NodeInfo>::new(s.as_bytes()).unwrap().inventory();
+        let node01 = &inventory.nodes[0];
+        assert_eq!(node01.partitions, ["fat"]);
+        assert!(node01.available);
+        assert_eq!(node01.core_number, 48);
+        assert_eq!(node01.allocated_cpu_count, 48);
+        assert_eq!(node01.features, ["nvlink"]);
+        assert_eq!(node01.gres, ["ngpus:4"]);
+        assert!(!inventory.nodes[1].available);
+        assert_eq!(inventory.partitions.len(), 1);
+    }
+}
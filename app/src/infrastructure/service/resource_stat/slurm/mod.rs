@@ -6,6 +6,7 @@ use anyhow::Context;
 use dep_inj_target::dep_inj_target;
 
 use self::sinfo::{NodeAlloc, NodeTotal};
+use super::inventory::{Inventory, NodeInventory};
 use super::SchedulerStat;
 use super::{SchedulerTotalResources, SchedulerUsedResources};
 use crate::infrastructure::command::MaybeSsh;
@@ -87,4 +88,23 @@ where
             used_node_count: resources.alloc_nodes,
         })
     }
+
+    async fn inventory(&self) -> anyhow::Result<Inventory> {
+        let output = self
+            .prj_ref()
+            .command("sinfo")
+            .args(sinfo::Info::<NodeInventory>::ARGS)
+            .output()
+            .await
+            .context("sinfo")?;
+        if !output.status.success() {
+            bail!(
+                "sinfo inventory terminated with an exception. Exit status: {}, stderr:\n {}",
+                output.status,
+                String::from_utf8(output.stderr)?
+            );
+        }
+
+        Ok(sinfo::Info::<NodeInventory>::new(&output.stdout)?.inventory())
+    }
 }
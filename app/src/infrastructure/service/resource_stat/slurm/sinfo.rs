@@ -3,6 +3,8 @@ use std::ops::Add;
 use anyhow::Context;
 use serde::{Deserialize, Deserializer};
 
+use crate::infrastructure::service::resource_stat::inventory::{Inventory, NodeInventory};
+
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct Info<Node> {
     nodes: Vec<Node>,
@@ -95,6 +97,95 @@ impl Info<NodeAlloc> {
     }
 }
 
+impl Info<NodeInventory> {
+    /// One line per node and partition
+    pub const ARGS: &'static [&'static str] = &["-N", "-h", "-o", "'%n|%P|%T|%C|%m|%e|%f|%G'"];
+
+    pub fn new(s: &[u8]) -> anyhow::Result<Self> {
+        let s = String::from_utf8_lossy(s).replace('\'', "");
+        Ok(Self {
+            nodes: s
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(parse_node)
+                .collect::<anyhow::Result<_>>()?,
+        })
+    }
+
+    #[inline]
+    pub fn inventory(self) -> Inventory {
+        Inventory::new(self.nodes)
+    }
+}
+
+fn parse_node(line: &str) -> anyhow::Result<NodeInventory> {
+    let fields: Vec<&str> = line.trim().split('|').collect();
+    let [name, partition, state, cpus, memory, free_mem, features, gres] = fields[..] else {
+        anyhow::bail!("Unexpected sinfo line: {line}");
+    };
+    let (allocated_cpus, total_cpus) = cpus
+        .split_once('/')
+        .zip(cpus.rsplit_once('/'))
+        .map(|((a, _), (_, t))| (a, t))
+        .context("Failed to parse CPUS(A/I/O/T)")?;
+    let memory: u64 = memory.parse().context("Failed to parse MEMORY")?;
+    // Free memory is `N/A` when the node is down
+    let free_mem = free_mem.parse().unwrap_or(memory);
+
+    Ok(NodeInventory {
+        name: name.to_owned(),
+        partitions: vec![partition.trim_end_matches('*').to_owned()],
+        state: state.to_owned(),
+        available: is_available(state),
+        core_number: total_cpus.parse().context("Failed to parse CPUS(T)")?,
+        allocated_cpu_count: allocated_cpus.parse().context("Failed to parse CPUS(A)")?,
+        memory: memory * 1024 * 1024,
+        allocated_memory: memory.saturating_sub(free_mem) * 1024 * 1024,
+        features: split_list(features),
+        gres: split_list(gres),
+    })
+}
+
+/// Whether the node can run jobs, judging from its long state, e.g. `mixed`, `down*` or `idle+drain`
+fn is_available(state: &str) -> bool {
+    // Flags like `*` (not responding) and `~` (powered off) are appended to the state
+    if state.ends_with(['*', '~', '#', '%', '!']) {
+        return false;
+    }
+    let mut states = state.trim_end_matches(|c: char| !c.is_ascii_alphanumeric()).split('+');
+    matches!(
+        states.next(),
+        Some("idle" | "mixed" | "allocated" | "completing")
+    ) && states.all(|flag| {
+        !["drain", "fail", "maint", "down", "reboot"]
+            .iter()
+            .any(|bad| flag.contains(bad))
+    })
+}
+
+/// Split the comma separated list, ignoring the commas in parentheses like `gpu:4(S:0,1)`
+fn split_list(s: &str) -> Vec<String> {
+    if s == "(null)" || s.is_empty() {
+        return Vec::new();
+    }
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                items.push(s[start..i].to_owned());
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+    items.push(s[start..].to_owned());
+    items
+}
+
 impl Add<&Self> for NodeTotal {
     type Output = Self;
 
@@ -129,6 +220,7 @@ where
 #[cfg(test)]
 mod tests {
     use super::{Info, NodeAlloc, NodeTotal};
+    use crate::infrastructure::service::resource_stat::inventory::NodeInventory;
     use indoc::indoc;
 
     #[test]
@@ -171,6 +263,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_info_inventory() {
+        let s = indoc! {"
+            'foo0042|cpu*|mixed|8/48/0/56|190000|184421|(null)|(null)'
+            'foo0042|fat|mixed|8/48/0/56|190000|184421|(null)|(null)'
+            'foo1145|gpu|idle+drain|0/56/0/56|190000|N/A|a100,nvlink|gpu:a100:4(S:0,1),mps:100'
+        "};
+        let inventory = Info::<NodeInventory>::new(s.as_bytes()).unwrap().inventory();
+        assert_eq!(
+            inventory.nodes,
+            [
+                NodeInventory {
+                    name: "foo0042".to_owned(),
+                    partitions: vec!["cpu".to_owned(), "fat".to_owned()],
+                    state: "mixed".to_owned(),
+                    available: true,
+                    core_number: 56,
+                    allocated_cpu_count: 8,
+                    memory: 199229440000,
+                    allocated_memory: 5850005504,
+                    features: vec![],
+                    gres: vec![],
+                },
+                NodeInventory {
+                    name: "foo1145".to_owned(),
+                    partitions: vec!["gpu".to_owned()],
+                    state: "idle+drain".to_owned(),
+                    available: false,
+                    core_number: 56,
+                    allocated_cpu_count: 0,
+                    memory: 199229440000,
+                    allocated_memory: 0,
+                    features: vec!["a100".to_owned(), "nvlink".to_owned()],
+                    gres: vec!["gpu:a100:4(S:0,1)".to_owned(), "mps:100".to_owned()],
+                },
+            ]
+        );
+        assert_eq!(inventory.partitions.len(), 3);
+        assert_eq!(inventory.partitions[2].available_node_number, 0);
+    }
+
     #[test]
     fn test_info_alloc() {
         let s = indoc! {"
@@ -0,0 +1,184 @@
+use std::time::{Duration, SystemTime};
+
+use dep_inj::DepInj;
+use domain::model::entity::task::deploy_software::DeployerType;
+use domain::service::SelectSoftwareDeployer;
+use service::prelude::JobServiceState;
+
+use crate::config::SoftwareCacheConfig;
+use crate::dto::{EvictedSoftware, EvictionReport, InstalledSoftware};
+
+/// Evicts deployed software no longer used by jobs
+#[derive(DepInj)]
+#[target(SoftwareCache)]
+pub struct SoftwareCacheState {
+    config: SoftwareCacheConfig,
+    /// Deployers installing software themselves, i.e. not site modules
+    deployers: Vec<DeployerType>,
+    /// The last use of software with no recorded use or install,
+    /// i.e. installed by older agents or out of the agent
+    started_at: SystemTime,
+}
+
+impl SoftwareCacheState {
+    pub fn new(config: SoftwareCacheConfig, deployers: Vec<DeployerType>) -> Self {
+        Self {
+            config,
+            deployers: deployers.into_iter().filter(|&d| d != DeployerType::Module).collect(),
+            started_at: SystemTime::now(),
+        }
+    }
+}
+
+struct Candidate {
+    software: InstalledSoftware,
+    idle: Duration,
+    in_use: bool,
+}
+
+impl<Deps> SoftwareCache<Deps>
+where
+    Deps: AsRef<SoftwareCacheState> + AsRef<JobServiceState> + SelectSoftwareDeployer + Send + Sync,
+{
+    /// Uninstall the software according to the eviction rules,
+    /// or only report what would be uninstalled if `dry_run`.
+    ///
+    /// Software used by the watched jobs or other installed software is never uninstalled.
+    pub async fn evict(&self, dry_run: bool) -> anyhow::Result<EvictionReport> {
+        let job: &JobServiceState = self.prj_ref().as_ref();
+        let now = SystemTime::now();
+        let mut report = EvictionReport {
+            dry_run,
+            ..Default::default()
+        };
+        let Some(mut in_use) = job.software_in_use() else {
+            tracing::info!(
+                "Skipped evicting software, as the software of restored jobs is unknown"
+            );
+            return Ok(report);
+        };
+        // e.g. the packages of Spack environments, which are in the Spack store
+        for &deployer in &self.deployers {
+            in_use.extend(self.prj_ref().select(deployer).dependencies().await?);
+        }
+
+        for &deployer in &self.deployers {
+            let installed = self.prj_ref().select(deployer).load_installed().await?;
+            let candidates = installed
+                .into_iter()
+                .filter_map(|options| {
+                    let software = InstalledSoftware::new(deployer, options);
+                    let hash = software.hash.clone()?;
+                    let last_used = job.last_used(deployer, &hash).unwrap_or(self.started_at);
+                    Some(Candidate {
+                        in_use: in_use.contains(&(deployer, hash)),
+                        idle: now.duration_since(last_used).unwrap_or_default(),
+                        software,
+                    })
+                })
+                .collect();
+
+            for candidate in self.choose(candidates) {
+                let evicted = EvictedSoftware {
+                    idle: candidate.idle.as_secs(),
+                    software: candidate.software,
+                };
+                if dry_run {
+                    report.evicted.push(evicted);
+                    continue;
+                }
+                let hash = evicted.software.hash.as_deref().unwrap_or_default();
+                match self.prj_ref().select(deployer).uninstall(hash).await {
+                    Ok(()) => {
+                        tracing::info!(%deployer, %hash, "Evicted software idle for {}s", evicted.idle);
+                        report.evicted.push(evicted);
+                    }
+                    Err(e) => {
+                        tracing::warn!(%deployer, %hash, "Failed to evict software: {e}");
+                        report.failed.push(evicted);
+                    }
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Software idle for too long, then the least recently used beyond the quota
+    fn choose(&self, candidates: Vec<Candidate>) -> Vec<Candidate> {
+        choose(
+            candidates,
+            self.config.max_idle.map(|hours| Duration::from_secs(hours * 60 * 60)),
+            self.config.max_installed,
+        )
+    }
+}
+
+fn choose(
+    mut candidates: Vec<Candidate>,
+    max_idle: Option<Duration>,
+    max_installed: Option<usize>,
+) -> Vec<Candidate> {
+    let mut kept = candidates.len();
+    // The least recently used first
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.idle));
+    candidates
+        .into_iter()
+        .filter(|candidate| !candidate.in_use)
+        .filter(|candidate| {
+            let idle = max_idle.is_some_and(|max_idle| candidate.idle >= max_idle);
+            let over_quota = max_installed.is_some_and(|max_installed| kept > max_installed);
+            if idle || over_quota {
+                kept -= 1;
+            }
+            idle || over_quota
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use domain::model::entity::{task::deploy_software::DeployerType, SoftwareInstallOptions};
+
+    use super::{choose, Candidate};
+    use crate::dto::InstalledSoftware;
+
+    fn candidate(name: &str, idle_hours: u64, in_use: bool) -> Candidate {
+        let options = SoftwareInstallOptions {
+            name: name.to_owned(),
+            hash: Some(name.to_owned()),
+            ..Default::default()
+        };
+        Candidate {
+            software: InstalledSoftware::new(DeployerType::Spack, options),
+            idle: Duration::from_secs(idle_hours * 60 * 60),
+            in_use,
+        }
+    }
+
+    fn names(candidates: Vec<Candidate>) -> Vec<String> {
+        candidates.into_iter().map(|candidate| candidate.software.name).collect()
+    }
+
+    #[test]
+    fn test_choose() {
+        let candidates = || {
+            vec![
+                candidate("fresh", 1, false),
+                candidate("old", 1000, false),
+                candidate("used", 2000, true),
+                candidate("stale", 100, false),
+            ]
+        };
+        let day = Duration::from_secs(24 * 60 * 60);
+        assert_eq!(names(choose(candidates(), Some(30 * day), None)), ["old"]);
+        assert_eq!(names(choose(candidates(), None, Some(2))), ["old", "stale"]);
+        assert_eq!(
+            names(choose(candidates(), Some(day), Some(3))),
+            ["old", "stale"]
+        );
+        assert!(choose(candidates(), None, None).is_empty());
+    }
+}
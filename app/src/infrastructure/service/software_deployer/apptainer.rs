@@ -1,6 +1,14 @@
+use std::time::UNIX_EPOCH;
+
+use anyhow::Context;
 use dep_inj::DepInj;
-use domain::{model::entity::SoftwareInstallOptions, service::SoftwareDeployer};
-use tokio::process::Command;
+use domain::{
+    model::{entity::SoftwareInstallOptions, vo::job::ContainerExec},
+    service::{DeployProgress, SoftwareDeployer},
+};
+
+use super::progress::{apptainer_progress, run_with_progress, GroupCommand, NoProgress};
+use crate::config::{ApptainerExecConfig, ApptainerImageConfig, RegistryCredential};
 
 #[derive(DepInj)]
 #[target(ApptainerDeployer)]
@@ -8,16 +16,117 @@ pub struct ApptainerDeployerState {
     execution_path: String,
     save_path: String,
     apptainer_proxy: Option<String>,
+    fakeroot: bool,
+    credentials: Vec<RegistryCredential>,
+    exec: ApptainerExecConfig,
 }
 
 impl ApptainerDeployerState {
-    pub fn new(execution_path: String, save_path: String, apptainer_proxy: Option<String>) -> Self {
+    pub fn new(
+        execution_path: String,
+        save_path: String,
+        apptainer_proxy: Option<String>,
+        config: &ApptainerImageConfig,
+        exec: &ApptainerExecConfig,
+    ) -> Self {
         Self {
             execution_path,
             save_path,
             apptainer_proxy,
+            fakeroot: config.fakeroot,
+            credentials: config.credentials.clone(),
+            exec: exec.clone(),
+        }
+    }
+}
+
+/// The tag and the `key=value` parameters of an image, see `ImageSource::parameters`
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ImageSpec<'a> {
+    tag: &'a str,
+    source: &'a str,
+    path: Option<&'a str>,
+    definition: Option<&'a str>,
+    digest: Option<&'a str>,
+}
+
+impl<'a> ImageSpec<'a> {
+    fn parse(parameters: &'a [String]) -> Self {
+        let mut spec = Self {
+            tag: parameters.first().map(String::as_str).unwrap_or_default(),
+            source: "docker",
+            ..Default::default()
+        };
+        for parameter in parameters.iter().skip(1) {
+            match parameter.split_once('=') {
+                Some(("source", source)) => spec.source = source,
+                Some(("path", path)) => spec.path = Some(path),
+                Some(("definition", definition)) => spec.definition = Some(definition),
+                Some(("digest", digest)) => spec.digest = Some(digest),
+                _ => tracing::warn!("Unknown image parameter: {parameter}"),
+            }
+        }
+        spec
+    }
+
+    /// The file name of the image, keyed on the content digest if pinned.
+    ///
+    /// Images not from Docker are prefixed with their sources, and archives are keyed on
+    /// their paths and modification times instead of the tags, as they may be replaced.
+    fn file_name(&self) -> String {
+        let key = match (self.digest, self.definition, self.path) {
+            (Some(digest), _, _) => digest.replace(':', "-"),
+            (None, Some(definition), _) => format!("def-{}", short_hash(definition)),
+            (None, None, Some(path)) => {
+                let modified = std::fs::metadata(path)
+                    .and_then(|metadata| metadata.modified())
+                    .map(|time| time.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos())
+                    .unwrap_or_default();
+                short_hash(&format!("{path}\0{modified}"))
+            }
+            (None, None, None) => self.tag.to_owned(),
+        };
+        match self.source {
+            // The names of Docker images are kept for the images pulled before
+            "docker" | "definition" => format!("{key}.sif"),
+            source => format!("{source}-{key}.sif"),
         }
     }
+
+    /// The URI to pull or build from, or `None` for definition files
+    fn uri(&self, name: &str, proxy: Option<&str>) -> anyhow::Result<Option<String>> {
+        let reference = |library: bool| match self.digest {
+            Some(digest) if library => format!(":{}", digest.replace(':', ".")),
+            Some(digest) => format!("@{digest}"),
+            None => format!(":{}", self.tag),
+        };
+        let path = || self.path.context("The path of the image archive is missing");
+        Ok(Some(match self.source {
+            "docker" => match proxy {
+                Some(proxy) => format!("docker://{proxy}/{name}{}", reference(false)),
+                None => format!("docker://{name}{}", reference(false)),
+            },
+            "oras" => format!("oras://{name}{}", reference(false)),
+            // The library addresses digests as `:sha256.<hex>`
+            "library" => format!("library://{name}{}", reference(true)),
+            "docker-archive" => format!("docker-archive:{}", path()?),
+            "oci-archive" => format!("oci-archive:{}", path()?),
+            "definition" => return Ok(None),
+            source => anyhow::bail!("Unknown image source: {source}"),
+        }))
+    }
+}
+
+fn short_hash(content: &str) -> String {
+    blake3::hash(content.as_bytes()).to_hex()[..16].to_owned()
+}
+
+/// The registry of an image name like `registry.example.com/library/ubuntu`
+fn registry_of(name: &str) -> &str {
+    match name.split_once('/') {
+        Some((host, _)) if host.contains(['.', ':']) || host == "localhost" => host,
+        _ => "docker.io",
+    }
 }
 
 #[async_trait::async_trait]
@@ -26,43 +135,77 @@ where
     Deps: AsRef<ApptainerDeployerState> + Send + Sync,
 {
     async fn install(&self, name: &str, parameters: Vec<String>) -> anyhow::Result<String> {
-        let mut path = std::path::PathBuf::new();
-        path.push(self.save_path.as_str());
-        path.push(name);
-        if !path.exists() {
-            tokio::fs::create_dir_all(path).await?;
+        self.install_with_progress(name, parameters, &NoProgress).await
+    }
+
+    async fn install_with_progress(
+        &self,
+        name: &str,
+        parameters: Vec<String>,
+        progress: &dyn DeployProgress,
+    ) -> anyhow::Result<String> {
+        let spec = ImageSpec::parse(&parameters);
+        let dir = format!("{}/{name}", self.save_path);
+        tokio::fs::create_dir_all(&dir).await?;
+        let image = format!("{dir}/{}", spec.file_name());
+
+        let mut command = GroupCommand::local(&self.execution_path);
+        match spec.uri(name, self.apptainer_proxy.as_deref())? {
+            Some(uri) if uri.contains("://") => {
+                command.arg("pull").arg(&image).arg(uri);
+            }
+            Some(archive) => {
+                command.arg("build").arg(&image).arg(archive);
+            }
+            None => {
+                let definition = spec.definition.context("The definition file is missing")?;
+                let definition_path = image.replace(".sif", ".def");
+                tokio::fs::write(&definition_path, definition).await?;
+                command.arg("build");
+                if self.fakeroot {
+                    command.arg("--fakeroot");
+                }
+                command.arg(&image).arg(definition_path);
+            }
         }
-        let parameters = parameters.join("");
-        let docker_url = match &self.apptainer_proxy {
-            Some(x) => format!("docker://{x}/{name}:{parameters}"),
-            None => format!("docker://{name}:{parameters}"),
-        };
-        let output = Command::new(self.execution_path.as_str())
-            .arg("pull")
-            .arg(format!("{}/{name}/{parameters}.sif", self.save_path))
-            .arg(docker_url)
-            .output()
-            .await
-            .map_err(|e| {
-                anyhow::anyhow!("Unable to run apptainer pull because: {}", e.to_string())
-            })?;
-        if !output.status.success() {
-            anyhow::bail!("{}", String::from_utf8_lossy(output.stderr.as_slice()))
+        if let Some(credential) = self.credential(name) {
+            let password = credential.password().await?;
+            command
+                .env("APPTAINER_DOCKER_USERNAME", &credential.username)
+                .env("APPTAINER_DOCKER_PASSWORD", password);
         }
-        let hash = String::from_utf8_lossy(output.stdout.as_slice()).to_string();
-        let hash = hash.trim().rsplit_once('-').unwrap().1;
-        Ok(hash.to_string())
+
+        run_with_progress(&mut command, progress, apptainer_progress)
+            .await
+            .context("Unable to run apptainer to obtain the image")?;
+        Ok(image)
     }
 
+    /// `hash` is the path of the image, see `find_installed_hash`
     async fn uninstall(&self, hash: &str) -> anyhow::Result<()> {
-        let path = format!("{}/{hash}", self.save_path);
-        let path = std::path::Path::new(path.as_str());
+        let path = std::path::Path::new(hash);
+        anyhow::ensure!(
+            path.starts_with(&self.save_path) && hash.ends_with(".sif"),
+            "Not an image under {}: {hash}",
+            self.save_path
+        );
         if path.exists() {
             tokio::fs::remove_file(path).await?;
         }
+        // The definition file the image was built from, if any
+        let definition = path.with_extension("def");
+        if definition.exists() {
+            tokio::fs::remove_file(definition).await?;
+        }
         Ok(())
     }
 
+    /// The image may be left partly written
+    async fn clean_up(&self, name: &str, parameters: Vec<String>) -> anyhow::Result<()> {
+        let spec = ImageSpec::parse(&parameters);
+        self.uninstall(&format!("{}/{name}/{}", self.save_path, spec.file_name())).await
+    }
+
     async fn load_installed(&self) -> anyhow::Result<Vec<SoftwareInstallOptions>> {
         let mut ls = tokio::fs::read_dir(self.save_path.as_str()).await?;
         let mut result = vec![];
@@ -81,6 +224,7 @@ where
                                 parameters: vec![],
                                 version: file_name.replace(".sif", ""),
                                 name: dir_name.to_string_lossy().to_string(),
+                                hash: Some(file_path.to_string_lossy().to_string()),
                             });
                         }
                     }
@@ -90,9 +234,26 @@ where
         Ok(result)
     }
 
-    fn gen_load_script(&self, hash: &str) -> String {
-        let execution_path = self.execution_path.as_str();
-        format!("{execution_path} run {hash} \\")
+    /// Nothing to load, the command runs in the container instead
+    fn gen_load_script(&self, _hash: &str) -> String {
+        String::new()
+    }
+
+    /// The deployments writing the same image file are shared
+    fn deployment_key(&self, name: &str, parameters: &[String]) -> String {
+        format!("{name}/{}", ImageSpec::parse(parameters).file_name())
+    }
+
+    fn container_exec(&self, hash: &str) -> Option<ContainerExec> {
+        Some(ContainerExec {
+            runtime: self.execution_path.clone(),
+            image: hash.to_owned(),
+            mode: self.exec.mode,
+            binds: self.exec.binds.clone(),
+            overlays: self.exec.overlays.clone(),
+            clean_env: self.exec.clean_env,
+            gpu: self.exec.gpu,
+        })
     }
 
     async fn find_installed_hash(
@@ -106,11 +267,71 @@ where
         if !path.exists() {
             return Ok(None);
         }
-        let parameters = parameters.join("");
-        path.push(format!("{parameters}.sif"));
+        path.push(ImageSpec::parse(parameters).file_name());
         if !path.exists() {
             return Ok(None);
         }
         Ok(Some(path.to_string_lossy().to_string()))
     }
 }
+
+impl<Deps> ApptainerDeployer<Deps>
+where
+    Deps: AsRef<ApptainerDeployerState> + Send + Sync,
+{
+    fn credential(&self, name: &str) -> Option<&RegistryCredential> {
+        let registry = registry_of(name);
+        self.credentials.iter().find(|credential| credential.registry == registry)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{registry_of, ImageSpec};
+
+    #[test]
+    fn test_image_spec() {
+        let parameters = ["22.04".to_owned()];
+        let spec = ImageSpec::parse(&parameters);
+        assert_eq!(spec.file_name(), "22.04.sif");
+        assert_eq!(
+            spec.uri("ubuntu", Some("mirror.example.com")).unwrap().unwrap(),
+            "docker://mirror.example.com/ubuntu:22.04"
+        );
+
+        let parameters = ["1.0", "source=library", "digest=sha256:abc"].map(String::from);
+        let spec = ImageSpec::parse(&parameters);
+        assert_eq!(spec.file_name(), "library-sha256-abc.sif");
+        assert_eq!(
+            spec.uri("user/tools/app", None).unwrap().unwrap(),
+            "library://user/tools/app:sha256.abc"
+        );
+
+        let parameters = ["1.0", "source=oras"].map(String::from);
+        assert_eq!(ImageSpec::parse(&parameters).file_name(), "oras-1.0.sif");
+
+        let parameters = ["1.0", "source=oci-archive", "path=/data/app.tar"].map(String::from);
+        let spec = ImageSpec::parse(&parameters);
+        assert!(spec.file_name().starts_with("oci-archive-"));
+        let other = ["1.0", "source=oci-archive", "path=/data/other.tar"].map(String::from);
+        assert_ne!(spec.file_name(), ImageSpec::parse(&other).file_name());
+        assert_eq!(
+            spec.uri("app", None).unwrap().unwrap(),
+            "oci-archive:/data/app.tar"
+        );
+
+        let parameters =
+            ["1.0", "source=definition", "definition=Bootstrap: docker"].map(String::from);
+        let spec = ImageSpec::parse(&parameters);
+        assert!(spec.file_name().starts_with("def-"));
+        assert_eq!(spec.uri("app", None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_registry_of() {
+        assert_eq!(registry_of("ubuntu"), "docker.io");
+        assert_eq!(registry_of("library/ubuntu"), "docker.io");
+        assert_eq!(registry_of("ghcr.io/org/app"), "ghcr.io");
+        assert_eq!(registry_of("localhost:5000/app"), "localhost:5000");
+    }
+}
@@ -0,0 +1,227 @@
+use anyhow::Context;
+use dep_inj::DepInj;
+use domain::{
+    model::entity::SoftwareInstallOptions,
+    service::{DeployProgress, SoftwareDeployer},
+};
+use serde::Deserialize;
+
+use super::progress::{run_with_progress, GroupCommand, NoProgress};
+use crate::config::CondaConfig;
+use crate::infrastructure::command::{MaybeSsh, SshConfig};
+
+/// Deploys conda packages into environments named by the hash of the packages and channels,
+/// so that the same request reuses the environment.
+#[derive(DepInj)]
+#[target(CondaDeployer)]
+pub struct CondaDeployerState {
+    executable: String,
+    envs_dir: String,
+}
+
+impl CondaDeployerState {
+    pub fn new(config: &CondaConfig, save_path: &str, ssh: Option<&SshConfig>) -> Self {
+        let envs_dir = config.envs_dir.clone().unwrap_or_else(|| match ssh {
+            Some(ssh) => format!("{}/{}/.conda-envs", ssh.home_dir, ssh.save_dir),
+            None => format!("{save_path}/.conda-envs"),
+        });
+        Self {
+            executable: config.executable.clone(),
+            envs_dir,
+        }
+    }
+
+    fn prefix(&self, hash: &str) -> String {
+        format!("{}/{hash}", self.envs_dir)
+    }
+}
+
+/// The requested specs of an environment, exported with `--from-history`
+#[derive(Deserialize)]
+struct ExportedEnv {
+    #[serde(default)]
+    channels: Vec<String>,
+    #[serde(default)]
+    dependencies: Vec<String>,
+}
+
+#[async_trait::async_trait]
+impl<Deps> SoftwareDeployer for CondaDeployer<Deps>
+where
+    Deps: AsRef<CondaDeployerState> + AsRef<Option<SshConfig>> + MaybeSsh + Send + Sync,
+{
+    async fn install(&self, name: &str, parameters: Vec<String>) -> anyhow::Result<String> {
+        self.install_with_progress(name, parameters, &NoProgress).await
+    }
+
+    /// Create the environment with the space separated package specs from the channels,
+    /// only from the given channels if any, as the environment is keyed on them
+    async fn install_with_progress(
+        &self,
+        name: &str,
+        parameters: Vec<String>,
+        progress: &dyn DeployProgress,
+    ) -> anyhow::Result<String> {
+        let hash = env_hash(name, &parameters);
+        let mut command = GroupCommand::new(self.prj_ref(), &self.executable);
+        command
+            .args(["create", "-y", "-p", &self.prefix(&hash)])
+            .args(parameters.iter().flat_map(|channel| ["-c", channel]));
+        if !parameters.is_empty() {
+            command.arg("--override-channels");
+        }
+        command.args(name.split_whitespace());
+        run_with_progress(&mut command, progress, |_| None)
+            .await
+            .context("Unable to run conda create")?;
+        Ok(hash)
+    }
+
+    async fn uninstall(&self, hash: &str) -> anyhow::Result<()> {
+        let output = self
+            .prj_ref()
+            .command("rm")
+            .args(["-rf", &self.prefix(hash)])
+            .output()
+            .await
+            .context("Unable to remove conda environment")?;
+        if !output.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr))
+        }
+        Ok(())
+    }
+
+    /// A partial environment would be taken as installed
+    async fn clean_up(&self, name: &str, parameters: Vec<String>) -> anyhow::Result<()> {
+        self.uninstall(&env_hash(name, &parameters)).await
+    }
+
+    async fn load_installed(&self) -> anyhow::Result<Vec<SoftwareInstallOptions>> {
+        let output = self
+            .prj_ref()
+            .command("ls")
+            .args(["-1", &self.envs_dir])
+            .output()
+            .await
+            .context("Unable to list conda environments")?;
+        // The directory is created with the first environment
+        if !output.status.success() {
+            return Ok(vec![]);
+        }
+
+        let mut result = vec![];
+        for hash in String::from_utf8_lossy(&output.stdout).lines().map(str::trim) {
+            if hash.is_empty() {
+                continue;
+            }
+            match self.export(hash).await {
+                Ok(env) => result.push(SoftwareInstallOptions {
+                    parameters: env.channels,
+                    version: hash.to_owned(),
+                    name: env.dependencies.join(" "),
+                    hash: Some(hash.to_owned()),
+                }),
+                Err(e) => tracing::warn!(%hash, "Skipping conda environment: {e}"),
+            }
+        }
+        Ok(result)
+    }
+
+    /// The deployments of the same environment are shared, whatever the order of the packages
+    fn deployment_key(&self, name: &str, parameters: &[String]) -> String {
+        env_hash(name, parameters)
+    }
+
+    fn gen_load_script(&self, hash: &str) -> String {
+        let executable = self.executable.as_str();
+        let hook = if executable.ends_with("mamba") {
+            format!("{executable} shell hook -s bash")
+        } else {
+            format!("{executable} shell.bash hook")
+        };
+        format!(
+            "eval \"$({hook})\"\n{executable} activate {}",
+            self.prefix(hash)
+        )
+    }
+
+    async fn find_installed_hash(
+        &self,
+        name: &str,
+        parameters: &[String],
+    ) -> anyhow::Result<Option<String>> {
+        let hash = env_hash(name, parameters);
+        let output = self
+            .prj_ref()
+            .command("test")
+            .args(["-d", &format!("{}/conda-meta", self.prefix(&hash))])
+            .output()
+            .await
+            .context("Unable to find conda environment")?;
+        Ok(output.status.success().then_some(hash))
+    }
+}
+
+impl<Deps> CondaDeployer<Deps>
+where
+    Deps: AsRef<CondaDeployerState> + MaybeSsh + Send + Sync,
+{
+    async fn export(&self, hash: &str) -> anyhow::Result<ExportedEnv> {
+        let output = self
+            .prj_ref()
+            .command(&self.executable)
+            .args([
+                "env",
+                "export",
+                "-p",
+                &self.prefix(hash),
+                "--from-history",
+                "--json",
+            ])
+            .output()
+            .await
+            .context("Unable to run conda env export")?;
+        if !output.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr))
+        }
+        Ok(serde_json::from_slice(&output.stdout)?)
+    }
+}
+
+/// Hash of the package specs and channels, regardless of the order of the specs.
+/// The order of channels matters as it's their priority.
+fn env_hash(packages: &str, channels: &[String]) -> String {
+    let mut packages: Vec<&str> = packages.split_whitespace().collect();
+    packages.sort_unstable();
+    packages.dedup();
+
+    let mut hasher = blake3::Hasher::new();
+    for package in packages {
+        hasher.update(package.as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher.update(b"\0");
+    for channel in channels {
+        hasher.update(channel.as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher.finalize().to_hex()[..16].to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::env_hash;
+
+    #[test]
+    fn test_env_hash() {
+        let channels = ["conda-forge".to_owned(), "bioconda".to_owned()];
+        let hash = env_hash("samtools=1.17 bwa", &channels);
+        assert_eq!(hash.len(), 16);
+        assert_eq!(hash, env_hash("bwa  samtools=1.17", &channels));
+        assert_ne!(hash, env_hash("samtools=1.18 bwa", &channels));
+        assert_ne!(
+            hash,
+            env_hash("samtools=1.17 bwa", &["bioconda".to_owned()])
+        );
+    }
+}
@@ -1,4 +1,8 @@
 mod apptainer;
+mod conda;
+mod module;
+mod progress;
 mod spack;
+mod spack_env;
 
-pub use self::{apptainer::*, spack::*};
+pub use self::{apptainer::*, conda::*, module::*, spack::*, spack_env::*};
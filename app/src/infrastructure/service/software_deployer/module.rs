@@ -0,0 +1,352 @@
+use std::cmp::Ordering;
+
+use anyhow::Context;
+use dep_inj::DepInj;
+use domain::{model::entity::SoftwareInstallOptions, service::SoftwareDeployer};
+use serde::Deserialize;
+
+use crate::config::ModulesConfig;
+use crate::infrastructure::command::MaybeSsh;
+
+/// Finds site-installed software provided by Environment Modules or Lmod.
+///
+/// The specs are the same as Spack's, e.g. `gromacs` with `@2023.1` and `+cuda`,
+/// matching the module `gromacs/2023.1-cuda`.
+#[derive(DepInj)]
+#[target(ModuleDeployer)]
+pub struct ModuleDeployerState {
+    install_hook: Option<String>,
+}
+
+impl ModuleDeployerState {
+    pub fn new(config: &ModulesConfig) -> Self {
+        Self {
+            install_hook: config.install_hook.clone(),
+        }
+    }
+}
+
+/// Lists modules with Lmod's spider if available, otherwise `module -t avail`.
+/// `module` is a shell function, so it runs in a login shell.
+const LIST_MODULES: &str = r#"if [ -n "$LMOD_DIR" ]; then "$LMOD_DIR/spider" -o jsonSoftwarePage "$MODULEPATH"; else module -t avail 2>&1; fi"#;
+
+#[derive(Debug, PartialEq, Eq)]
+struct Module {
+    /// `name/version`
+    full_name: String,
+    is_default: bool,
+}
+
+#[derive(Deserialize)]
+struct SpiderPackage {
+    versions: Vec<SpiderVersion>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SpiderVersion {
+    full: String,
+    #[serde(default)]
+    mark_default: bool,
+}
+
+#[async_trait::async_trait]
+impl<Deps> SoftwareDeployer for ModuleDeployer<Deps>
+where
+    Deps: AsRef<ModuleDeployerState> + MaybeSsh + Send + Sync,
+{
+    /// Modules are installed by the site, so ask the admin hook if any
+    async fn install(&self, name: &str, parameters: Vec<String>) -> anyhow::Result<String> {
+        let Some(hook) = &self.install_hook else {
+            anyhow::bail!("Installing modules is unsupported, please ask the admin for {name}");
+        };
+        let output = self
+            .prj_ref()
+            .command(hook)
+            .arg(name)
+            .args(parameters)
+            .output()
+            .await
+            .context("Unable to run the module install hook")?;
+        if !output.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr))
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    }
+
+    async fn uninstall(&self, hash: &str) -> anyhow::Result<()> {
+        anyhow::bail!("Uninstalling modules is unsupported: {hash}")
+    }
+
+    async fn load_installed(&self) -> anyhow::Result<Vec<SoftwareInstallOptions>> {
+        Ok(self
+            .modules()
+            .await?
+            .into_iter()
+            .map(|module| {
+                let (name, version) = split_module(&module.full_name);
+                SoftwareInstallOptions {
+                    parameters: vec![],
+                    version: version.to_owned(),
+                    name: name.to_owned(),
+                    hash: Some(module.full_name.clone()),
+                }
+            })
+            .collect())
+    }
+
+    fn gen_load_script(&self, hash: &str) -> String {
+        format!("module load {hash}")
+    }
+
+    async fn find_installed_hash(
+        &self,
+        name: &str,
+        parameters: &[String],
+    ) -> anyhow::Result<Option<String>> {
+        let modules = self.modules().await?;
+        Ok(best_match(&modules, name, parameters).map(|module| module.full_name.clone()))
+    }
+}
+
+impl<Deps> ModuleDeployer<Deps>
+where
+    Deps: AsRef<ModuleDeployerState> + MaybeSsh + Send + Sync,
+{
+    async fn modules(&self) -> anyhow::Result<Vec<Module>> {
+        // The script is quoted for the remote shell
+        let script = if self.prj_ref().is_ssh() {
+            format!("'{LIST_MODULES}'")
+        } else {
+            LIST_MODULES.to_owned()
+        };
+        let output = self
+            .prj_ref()
+            .command("bash")
+            .args(["-lc", &script])
+            .output()
+            .await
+            .context("Unable to list modules")?;
+        if !output.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr))
+        }
+        parse_modules(&String::from_utf8_lossy(&output.stdout))
+    }
+}
+
+fn parse_modules(s: &str) -> anyhow::Result<Vec<Module>> {
+    if s.trim_start().starts_with('[') {
+        let packages: Vec<SpiderPackage> = serde_json::from_str(s)?;
+        return Ok(packages
+            .into_iter()
+            .flat_map(|package| package.versions)
+            .map(|version| Module {
+                full_name: version.full,
+                is_default: version.mark_default,
+            })
+            .collect());
+    }
+
+    // Lines are module paths ending with `:`, directories ending with `/`, or modules
+    // with markers like `gcc/12.2.0(default)`
+    Ok(s.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.ends_with(':') && !line.ends_with('/'))
+        .map(|line| {
+            let (full_name, marker) = match line.split_once('(') {
+                Some((full_name, marker)) => (full_name.trim(), marker.trim_end_matches(')')),
+                None => (line, ""),
+            };
+            Module {
+                full_name: full_name.to_owned(),
+                is_default: matches!(marker, "default" | "D"),
+            }
+        })
+        .collect())
+}
+
+/// Split `name/version`, where the name may contain `/` in hierarchies like `mpi/openmpi/4.1`
+fn split_module(full_name: &str) -> (&str, &str) {
+    full_name.rsplit_once('/').unwrap_or((full_name, ""))
+}
+
+/// The default module among the matches, or the latest one.
+///
+/// `@version` should be a prefix of the version ending at a separator like `.` or `-`,
+/// and `+variant` one of the parts of the version separated by `-` or `_`.
+/// Other parameters like `~variant` or `%compiler` are ignored.
+fn best_match<'a>(modules: &'a [Module], name: &str, parameters: &[String]) -> Option<&'a Module> {
+    let version = parameters.iter().find_map(|p| p.strip_prefix('@'));
+    let variants: Vec<String> = parameters
+        .iter()
+        .filter_map(|p| p.strip_prefix('+'))
+        .map(str::to_lowercase)
+        .collect();
+
+    modules
+        .iter()
+        .filter(|module| {
+            let (module_name, module_version) = split_module(&module.full_name);
+            let module_version = module_version.to_lowercase();
+            module_name.rsplit('/').next().is_some_and(|n| n.eq_ignore_ascii_case(name))
+                && version.is_none_or(|v| matches_version(&module_version, &v.to_lowercase()))
+                && variants
+                    .iter()
+                    .all(|variant| module_version.split(['-', '_']).any(|part| part == variant))
+        })
+        .max_by(|a, b| {
+            a.is_default
+                .cmp(&b.is_default)
+                .then_with(|| {
+                    compare_versions(split_module(&a.full_name).1, split_module(&b.full_name).1)
+                })
+                .then_with(|| a.full_name.cmp(&b.full_name))
+        })
+}
+
+/// Whether the version starts with the prefix, e.g. `12.2.0` with `12` but not `1`
+fn matches_version(version: &str, prefix: &str) -> bool {
+    version
+        .strip_prefix(prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '-', '_']))
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum VersionPart<'a> {
+    Number(u64),
+    Text(&'a str),
+}
+
+/// Compare versions like `9.4.0` and `12.2.0` by their parts, the numbers numerically
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    version_parts(a).cmp(version_parts(b))
+}
+
+/// The runs of digits and of the other alphanumerics, split at the separators
+fn version_parts(version: &str) -> impl Iterator<Item = VersionPart<'_>> {
+    version
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .flat_map(|part| {
+            let mut runs = vec![];
+            let mut start = 0;
+            for (i, c) in part.char_indices().skip(1) {
+                let prev = part.as_bytes()[i - 1];
+                if prev.is_ascii_digit() != c.is_ascii_digit() {
+                    runs.push(&part[start..i]);
+                    start = i;
+                }
+            }
+            runs.push(&part[start..]);
+            runs
+        })
+        .filter(|run| !run.is_empty())
+        .map(|run| match run.parse() {
+            Ok(number) => VersionPart::Number(number),
+            Err(_) => VersionPart::Text(run),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Ordering;
+
+    use indoc::indoc;
+
+    use super::{best_match, compare_versions, parse_modules, Module};
+
+    #[test]
+    fn test_parse_avail() {
+        let s = indoc! {"
+            /opt/modulefiles:
+            gcc/
+            gcc/11.2.0
+            gcc/12.2.0(default)
+            gromacs/2023.1
+            gromacs/2023.1-cuda
+            mpi/openmpi/4.1.5
+            /usr/share/lmod/lmod/modulefiles/Core:
+            lmod
+        "};
+        let modules = parse_modules(s).unwrap();
+        assert_eq!(modules.len(), 6);
+
+        let params = |p: &[&str]| p.iter().map(|&p| p.to_owned()).collect::<Vec<_>>();
+        let found = |name, p: &[&str]| {
+            best_match(&modules, name, &params(p)).map(|module| module.full_name.as_str())
+        };
+        assert_eq!(found("gcc", &[]), Some("gcc/12.2.0"));
+        assert_eq!(found("gcc", &["@11"]), Some("gcc/11.2.0"));
+        assert_eq!(
+            found("gromacs", &["@2023.1", "+cuda", "~mpi"]),
+            Some("gromacs/2023.1-cuda")
+        );
+        assert_eq!(found("openmpi", &["@4.1"]), Some("mpi/openmpi/4.1.5"));
+        assert_eq!(found("gromacs", &["+plumed"]), None);
+    }
+
+    #[test]
+    fn test_match_boundaries() {
+        let modules = [
+            "gcc/1.2.0",
+            "gcc/12.2.0",
+            "gromacs/2023.1-openmpi",
+            "gromacs/2023.1_cuda-mpi",
+        ]
+        .map(|full_name| Module {
+            full_name: full_name.to_owned(),
+            is_default: false,
+        });
+        let found = |name, p: &[&str]| {
+            let params: Vec<_> = p.iter().map(|&p| p.to_owned()).collect();
+            best_match(&modules, name, &params).map(|module| module.full_name.as_str())
+        };
+        assert_eq!(found("gcc", &["@1"]), Some("gcc/1.2.0"));
+        assert_eq!(found("gcc", &["@1.2"]), Some("gcc/1.2.0"));
+        assert_eq!(found("gcc", &["@12.2.0"]), Some("gcc/12.2.0"));
+        assert_eq!(found("gcc", &["@12.2.1"]), None);
+        assert_eq!(
+            found("gromacs", &["@2023.1", "+mpi"]),
+            Some("gromacs/2023.1_cuda-mpi")
+        );
+        assert_eq!(
+            found("gromacs", &["+openmpi"]),
+            Some("gromacs/2023.1-openmpi")
+        );
+        assert_eq!(found("gromacs", &["+cud"]), None);
+    }
+
+    #[test]
+    fn test_latest_version() {
+        let modules = ["gcc/9.4.0", "gcc/12.2.0", "gcc/12.10.0-rc1"].map(|full_name| Module {
+            full_name: full_name.to_owned(),
+            is_default: false,
+        });
+        let found = best_match(&modules, "gcc", &[]).map(|module| module.full_name.as_str());
+        assert_eq!(found, Some("gcc/12.10.0-rc1"));
+        assert_eq!(
+            best_match(&modules[..2], "gcc", &[]).map(|module| module.full_name.as_str()),
+            Some("gcc/12.2.0")
+        );
+
+        assert_eq!(compare_versions("9.4.0", "12.2.0"), Ordering::Less);
+        assert_eq!(compare_versions("2023.1", "2023.1-cuda"), Ordering::Less);
+        assert_eq!(compare_versions("4.1.5a", "4.1.5b"), Ordering::Less);
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_parse_spider() {
+        let s = r#"[
+            {
+                "package": "gcc",
+                "versions": [
+                    { "full": "gcc/11.2.0", "versionName": "11.2.0" },
+                    { "full": "gcc/12.2.0", "versionName": "12.2.0", "markDefault": true }
+                ]
+            }
+        ]"#;
+        let modules = parse_modules(s).unwrap();
+        assert_eq!(modules.len(), 2);
+        assert!(modules[1].is_default);
+    }
+}
@@ -0,0 +1,365 @@
+use std::ops::{Deref, DerefMut};
+use std::process::Stdio;
+use std::sync::Arc;
+
+use anyhow::Context;
+use domain::service::{DeployProcess, DeployProgress, ProcessSignal};
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::Command;
+
+use crate::infrastructure::command::{MaybeSsh, SshConfig};
+
+/// Prefixes the id of the remote process group, printed to stderr before running the program
+const GROUP_ID_PREFIX: &str = "deploy-process-group=";
+
+/// Drops the progress, for installing without reporting it
+pub(super) struct NoProgress;
+
+impl DeployProgress for NoProgress {
+    fn progress(&self, _message: String) {}
+
+    fn log(&self, _line: &str) {}
+
+    fn process(&self, _process: Arc<dyn DeployProcess>) {}
+}
+
+/// A command run in a process group of its own, also over ssh,
+/// so that all the processes it starts can be paused or killed together
+pub(super) struct GroupCommand {
+    command: Command,
+    ssh: Option<SshConfig>,
+}
+
+impl GroupCommand {
+    pub fn new<Deps>(deps: &Deps, program: &str) -> Self
+    where
+        Deps: AsRef<Option<SshConfig>>,
+    {
+        let ssh = deps.as_ref().clone();
+        let command = if ssh.is_some() {
+            // The shell leads the new session of `setsid`, then becomes the program
+            let mut command = deps.command("setsid");
+            command.args([
+                "-w",
+                "sh",
+                "-c",
+                &format!("'echo {GROUP_ID_PREFIX}$$ >&2; exec \"$0\" \"$@\"'"),
+                &shell_quote(program),
+            ]);
+            command
+        } else {
+            let mut command = deps.command(program);
+            command.process_group(0);
+            command
+        };
+        Self { command, ssh }
+    }
+
+    /// A command run locally, for deployers not using ssh
+    pub fn local(program: &str) -> Self {
+        let mut command = Command::new(program);
+        command.process_group(0);
+        Self { command, ssh: None }
+    }
+
+    /// Add an argument, quoted over ssh as the remote shell splits the joined arguments
+    pub fn arg(&mut self, arg: impl AsRef<str>) -> &mut Self {
+        if self.ssh.is_some() {
+            self.command.arg(shell_quote(arg.as_ref()));
+        } else {
+            self.command.arg(arg.as_ref());
+        }
+        self
+    }
+
+    pub fn args(&mut self, args: impl IntoIterator<Item = impl AsRef<str>>) -> &mut Self {
+        for arg in args {
+            self.arg(arg);
+        }
+        self
+    }
+}
+
+/// Quote the argument as a single word of the shell
+fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', r"'\''"))
+}
+
+impl Deref for GroupCommand {
+    type Target = Command;
+
+    fn deref(&self) -> &Command {
+        &self.command
+    }
+}
+
+impl DerefMut for GroupCommand {
+    fn deref_mut(&mut self) -> &mut Command {
+        &mut self.command
+    }
+}
+
+/// The process group of a [`GroupCommand`], signalled by `kill` where it runs
+struct ProcessGroup {
+    ssh: Option<SshConfig>,
+    id: u32,
+}
+
+impl AsRef<Option<SshConfig>> for ProcessGroup {
+    fn as_ref(&self) -> &Option<SshConfig> {
+        &self.ssh
+    }
+}
+
+impl ProcessGroup {
+    async fn kill(&self, signal: &str) -> anyhow::Result<()> {
+        let output = self
+            .command("kill")
+            .args([signal, "--", &format!("-{}", self.id)])
+            .output()
+            .await
+            .context("Unable to run kill")?;
+        if !output.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr))
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl DeployProcess for ProcessGroup {
+    async fn signal(&self, signal: ProcessSignal) -> anyhow::Result<()> {
+        let signal = match signal {
+            ProcessSignal::Stop => "-STOP",
+            ProcessSignal::Continue => "-CONT",
+            ProcessSignal::Terminate => "-TERM",
+            ProcessSignal::Kill => "-KILL",
+        };
+        self.kill(signal).await
+    }
+
+    async fn is_alive(&self) -> bool {
+        self.kill("-0").await.is_ok()
+    }
+}
+
+/// Run the command, passing each line of its outputs to the log,
+/// and the lines recognized by `parse` to the progress.
+///
+/// # return
+///
+/// The last line of the standard output.
+pub(super) async fn run_with_progress(
+    command: &mut GroupCommand,
+    progress: &dyn DeployProgress,
+    parse: impl Fn(&str) -> Option<String>,
+) -> anyhow::Result<String> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let stdout = child.stdout.take().context("No stdout of the command")?;
+    let stderr = child.stderr.take().context("No stderr of the command")?;
+    let ssh = command.ssh.clone();
+    if ssh.is_none() {
+        let id = child.id().context("The command exited")?;
+        progress.process(Arc::new(ProcessGroup { ssh: None, id }));
+    }
+
+    let on_line = |line: &str| {
+        progress.log(line);
+        if let Some(message) = parse(line) {
+            progress.progress(message);
+        }
+    };
+    let (mut last_line, mut last_error) = (String::new(), String::new());
+    let (stdout, stderr) = tokio::join!(
+        read_lines(stdout, |line| {
+            on_line(line);
+            line.clone_into(&mut last_line);
+        }),
+        read_lines(stderr, |line| {
+            let group_id = ssh.as_ref().and(line.strip_prefix(GROUP_ID_PREFIX));
+            if let Some(id) = group_id.and_then(|id| id.parse().ok()) {
+                progress.process(Arc::new(ProcessGroup {
+                    ssh: ssh.clone(),
+                    id,
+                }));
+                return;
+            }
+            on_line(line);
+            line.clone_into(&mut last_error);
+        }),
+    );
+    stdout?;
+    stderr?;
+
+    let status = child.wait().await?;
+    if !status.success() {
+        anyhow::bail!("The command exited with {status}: {last_error}")
+    }
+    Ok(last_line)
+}
+
+/// Read the lines ended by `\n` or `\r`, as progress bars are redrawn after `\r`
+async fn read_lines(
+    reader: impl AsyncRead + Unpin,
+    mut on_line: impl FnMut(&str),
+) -> std::io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        let buf = reader.fill_buf().await?;
+        if buf.is_empty() {
+            break;
+        }
+        let len = buf.len();
+        for &byte in buf {
+            if byte == b'\n' || byte == b'\r' {
+                if !line.is_empty() {
+                    on_line(String::from_utf8_lossy(&line).trim_end());
+                    line.clear();
+                }
+            } else {
+                line.push(byte);
+            }
+        }
+        reader.consume(len);
+    }
+    if !line.is_empty() {
+        on_line(String::from_utf8_lossy(&line).trim_end());
+    }
+    Ok(())
+}
+
+/// Progress of `spack install` like `==> Installing zlib-1.2.13-abcdefg [3/25]`
+pub(super) fn spack_progress(line: &str) -> Option<String> {
+    let installing = line.strip_prefix("==> Installing ")?;
+    let counted = installing.rsplit_once(" [").and_then(|(package, counter)| {
+        let (done, total) = counter.strip_suffix(']')?.split_once('/')?;
+        let done: usize = done.parse().ok()?;
+        let total: usize = total.parse().ok()?;
+        Some(format!("Installing {package}, {done}/{total} packages"))
+    });
+    Some(counted.unwrap_or_else(|| format!("Installing {installing}")))
+}
+
+/// Progress of `apptainer pull` or `build`, i.e. the download percentage or the stage
+pub(super) fn apptainer_progress(line: &str) -> Option<String> {
+    if let Some(stage) = line.strip_prefix("INFO:") {
+        return Some(stage.trim().to_owned());
+    }
+    let (before, _) = line.split_once('%')?;
+    let before = before.trim_end();
+    let start = before.rfind(|c: char| !c.is_ascii_digit() && c != '.').map_or(0, |i| i + 1);
+    let percent: f64 = before[start..].parse().ok()?;
+    Some(format!("Downloading {percent:.0}%"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use domain::service::{DeployProcess, DeployProgress, ProcessSignal};
+
+    use super::{
+        apptainer_progress, read_lines, run_with_progress, shell_quote, spack_progress,
+        GroupCommand,
+    };
+
+    #[derive(Default)]
+    struct LastProcess(Mutex<Option<Arc<dyn DeployProcess>>>);
+
+    impl DeployProgress for LastProcess {
+        fn progress(&self, _message: String) {}
+
+        fn log(&self, _line: &str) {}
+
+        fn process(&self, process: Arc<dyn DeployProcess>) {
+            *self.0.lock().unwrap() = Some(process);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_terminate_process_group() {
+        let progress = LastProcess::default();
+        let mut command = GroupCommand::local("sh");
+        command.args(["-c", "sleep 30 & sleep 30; wait"]);
+        let run = run_with_progress(&mut command, &progress, |_| None);
+        let terminate = async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            let process = progress.0.lock().unwrap().clone().unwrap();
+            process.signal(ProcessSignal::Terminate).await.unwrap();
+        };
+        // The outputs stay open until the background sleep is terminated too
+        let (result, ()) = tokio::time::timeout(Duration::from_secs(10), async {
+            tokio::join!(run, terminate)
+        })
+        .await
+        .unwrap();
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_shell_quote() {
+        let arg = "python=3.11 'it''s' \"$HOME\" `id`; *";
+        let output = tokio::process::Command::new("sh")
+            .args(["-c", &format!("printf %s {}", shell_quote(arg))])
+            .output()
+            .await
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), arg);
+    }
+
+    #[tokio::test]
+    async fn test_read_lines() {
+        let output = b"Copying blob 10%\rCopying blob 55%\r\nINFO:    Creating SIF file...\nlast";
+        let mut lines = vec![];
+        read_lines(output.as_slice(), |line| lines.push(line.to_owned())).await.unwrap();
+        assert_eq!(
+            lines,
+            [
+                "Copying blob 10%",
+                "Copying blob 55%",
+                "INFO:    Creating SIF file...",
+                "last"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spack_progress() {
+        assert_eq!(
+            spack_progress("==> Installing zlib-1.2.13-abcdefg [3/25]").unwrap(),
+            "Installing zlib-1.2.13-abcdefg, 3/25 packages"
+        );
+        assert_eq!(
+            spack_progress("==> Installing zlib-1.2.13-abcdefg").unwrap(),
+            "Installing zlib-1.2.13-abcdefg"
+        );
+        assert_eq!(
+            spack_progress("==> Fetching https://example.com/zlib.tar.gz"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_apptainer_progress() {
+        assert_eq!(
+            apptainer_progress(" 12.3MiB / 45.6MiB [====>------] 27 % 5.1 MiB/s 5s").unwrap(),
+            "Downloading 27%"
+        );
+        assert_eq!(
+            apptainer_progress("Copying blob 55.5%").unwrap(),
+            "Downloading 56%"
+        );
+        assert_eq!(
+            apptainer_progress("INFO:    Creating SIF file...").unwrap(),
+            "Creating SIF file..."
+        );
+        assert_eq!(apptainer_progress("Getting image source signatures"), None);
+    }
+}
@@ -2,10 +2,14 @@ use std::collections::HashMap;
 
 use anyhow::Context;
 use dep_inj::DepInj;
-use domain::{model::entity::SoftwareInstallOptions, service::SoftwareDeployer};
+use domain::{
+    model::entity::SoftwareInstallOptions,
+    service::{DeployProgress, SoftwareDeployer},
+};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use super::progress::{run_with_progress, spack_progress, GroupCommand, NoProgress};
 use crate::infrastructure::command::{MaybeSsh, SshConfig};
 
 #[derive(DepInj)]
@@ -45,6 +49,7 @@ impl From<SpackSpec> for SoftwareInstallOptions {
                 .collect(),
             version: val.version,
             name: val.name,
+            hash: Some(val.hash),
         }
     }
 }
@@ -75,36 +80,62 @@ where
     Deps: AsRef<SpackDeployerState> + AsRef<Option<SshConfig>> + MaybeSsh + Send + Sync,
 {
     async fn install(&self, name: &str, parameters: Vec<String>) -> anyhow::Result<String> {
+        self.install_with_progress(name, parameters, &NoProgress).await
+    }
+
+    async fn install_with_progress(
+        &self,
+        name: &str,
+        parameters: Vec<String>,
+        progress: &dyn DeployProgress,
+    ) -> anyhow::Result<String> {
         let paramters = parameters.join("");
+        let mut command = GroupCommand::new(self.prj_ref(), "spack");
+        command.args([
+            "install",
+            "-y",
+            // The build output is kept in the log of failed deployments
+            "-v",
+            "--fail-fast",
+            &format!("{name}{paramters}"),
+        ]);
+        let output = run_with_progress(&mut command, progress, spack_progress)
+            .await
+            .context("Unable to run spack install")?;
+
+        let hash = output
+            .trim()
+            .rsplit_once('-')
+            .context("No hash in the output of spack install")?
+            .1;
+        Ok(hash.to_owned())
+    }
+
+    async fn uninstall(&self, hash: &str) -> anyhow::Result<()> {
         let output = self
             .prj_ref()
             .command("spack")
-            .args([
-                "install",
-                "-y",
-                "--fail-fast",
-                &format!("{name}{paramters}"),
-            ])
+            .args(["uninstall", "-y", &format!("/{hash}")])
             .output()
             .await
-            .context("Unable to run spack install")?;
+            .context("Unable to run spack uninstall")?;
         if !output.status.success() {
             anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr))
         }
-
-        let output = String::from_utf8_lossy(&output.stdout);
-        let hash = output.trim().rsplit_once('-').unwrap().1;
-        Ok(hash.to_owned())
+        Ok(())
     }
 
-    async fn uninstall(&self, hash: &str) -> anyhow::Result<()> {
+    /// Spack removes the prefix of a partial install when installing it again,
+    /// leaving only the build stage to remove
+    async fn clean_up(&self, name: &str, parameters: Vec<String>) -> anyhow::Result<()> {
+        let paramters = parameters.join("");
         let output = self
             .prj_ref()
             .command("spack")
-            .args(["uninstall", &format!("/{hash}")])
+            .args(["clean", &format!("{name}{paramters}")])
             .output()
             .await
-            .context("Unable to run spack uninstall")?;
+            .context("Unable to run spack clean")?;
         if !output.status.success() {
             anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr))
         }
@@ -186,6 +217,7 @@ where
                     .collect(),
                 version: x.name("version").unwrap().as_str().to_string(),
                 name: x.name("packageName").unwrap().as_str().to_string(),
+                hash: Some(x.name("hash").unwrap().as_str().to_string()),
             })
             .collect())
     }
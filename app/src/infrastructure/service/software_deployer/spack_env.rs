@@ -0,0 +1,284 @@
+use std::process::Stdio;
+
+use anyhow::Context;
+use dep_inj::DepInj;
+use domain::{
+    model::entity::{task::deploy_software::DeployerType, SoftwareInstallOptions},
+    service::{DeployProgress, SoftwareDeployer},
+};
+use tokio::io::AsyncWriteExt;
+
+use super::progress::{run_with_progress, spack_progress, GroupCommand, NoProgress};
+use crate::infrastructure::command::{MaybeSsh, SshConfig};
+
+/// Marks an environment whose specs are all installed
+const INSTALLED_MARK: &str = ".installed";
+
+/// Deploys Spack environments from `spack.yaml` or `spack.lock` files into directories
+/// named by the hash of the file, so that the same stack reuses the environment.
+#[derive(DepInj)]
+#[target(SpackEnvDeployer)]
+pub struct SpackEnvDeployerState {
+    envs_dir: String,
+}
+
+impl SpackEnvDeployerState {
+    pub fn new(save_path: &str, ssh: Option<&SshConfig>) -> Self {
+        let envs_dir = match ssh {
+            Some(ssh) => format!("{}/{}/.spack-envs", ssh.home_dir, ssh.save_dir),
+            None => format!("{save_path}/.spack-envs"),
+        };
+        Self { envs_dir }
+    }
+
+    fn env_dir(&self, hash: &str) -> String {
+        format!("{}/{hash}", self.envs_dir)
+    }
+}
+
+#[async_trait::async_trait]
+impl<Deps> SoftwareDeployer for SpackEnvDeployer<Deps>
+where
+    Deps: AsRef<SpackEnvDeployerState> + AsRef<Option<SshConfig>> + MaybeSsh + Send + Sync,
+{
+    async fn install(&self, name: &str, parameters: Vec<String>) -> anyhow::Result<String> {
+        self.install_with_progress(name, parameters, &NoProgress).await
+    }
+
+    /// Create, concretize and install the environment from the content of `spack.yaml`,
+    /// or `spack.lock` if the parameters contain `lock`
+    async fn install_with_progress(
+        &self,
+        name: &str,
+        parameters: Vec<String>,
+        progress: &dyn DeployProgress,
+    ) -> anyhow::Result<String> {
+        let lock = is_lock(&parameters);
+        let hash = env_hash(name, lock);
+        let dir = self.env_dir(&hash);
+        // A half-done environment is created again
+        self.run("rm", &["-rf", &dir]).await?;
+        self.run("mkdir", &["-p", &self.envs_dir]).await?;
+
+        let file = format!("{dir}.{}", if lock { "lock" } else { "yaml" });
+        self.write(&file, name).await?;
+        self.spack(&["env", "create", "-d", &dir, &file], progress)
+            .await
+            .context("Unable to create spack environment")?;
+        // A lock file is concretized already
+        if !lock {
+            self.spack(&["-e", &dir, "concretize"], progress)
+                .await
+                .context("Unable to concretize spack environment")?;
+        }
+        self.spack(&["-e", &dir, "install", "-v"], progress)
+            .await
+            .context("Unable to install spack environment")?;
+        self.run("touch", &[&format!("{dir}/{INSTALLED_MARK}")]).await?;
+        Ok(hash)
+    }
+
+    /// Remove the environment, leaving the packages in the Spack store to `spack gc`
+    async fn uninstall(&self, hash: &str) -> anyhow::Result<()> {
+        let dir = self.env_dir(hash);
+        self.run(
+            "rm",
+            &["-rf", &dir, &format!("{dir}.yaml"), &format!("{dir}.lock")],
+        )
+        .await
+        .context("Unable to remove spack environment")?;
+        Ok(())
+    }
+
+    async fn clean_up(&self, name: &str, parameters: Vec<String>) -> anyhow::Result<()> {
+        self.uninstall(&env_hash(name, is_lock(&parameters))).await
+    }
+
+    async fn load_installed(&self) -> anyhow::Result<Vec<SoftwareInstallOptions>> {
+        let output = self
+            .prj_ref()
+            .command("ls")
+            .args(["-1", &self.envs_dir])
+            .output()
+            .await
+            .context("Unable to list spack environments")?;
+        // The directory is created with the first environment
+        if !output.status.success() {
+            return Ok(vec![]);
+        }
+
+        let mut result = vec![];
+        for hash in String::from_utf8_lossy(&output.stdout).lines().map(str::trim) {
+            // Skip the manifests next to the environments
+            if hash.is_empty() || hash.contains('.') || !self.is_installed(hash).await? {
+                continue;
+            }
+            result.push(SoftwareInstallOptions {
+                parameters: vec![],
+                version: hash.to_owned(),
+                name: "spack-env".to_owned(),
+                hash: Some(hash.to_owned()),
+            });
+        }
+        Ok(result)
+    }
+
+    /// The packages in the Spack store concretized for the installed environments
+    async fn dependencies(&self) -> anyhow::Result<Vec<(DeployerType, String)>> {
+        let mut result = vec![];
+        for env in self.load_installed().await? {
+            let dir = self.env_dir(env.hash.as_deref().unwrap_or_default());
+            let output = self
+                .prj_ref()
+                .command("cat")
+                .arg(format!("{dir}/spack.lock"))
+                .output()
+                .await
+                .context("Unable to read spack environment lock")?;
+            if !output.status.success() {
+                anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr))
+            }
+            let hashes = lock_hashes(&output.stdout)
+                .with_context(|| format!("Invalid lock of spack environment {dir}"))?;
+            result.extend(hashes.into_iter().map(|hash| (DeployerType::Spack, hash)));
+        }
+        Ok(result)
+    }
+
+    fn deployment_key(&self, name: &str, parameters: &[String]) -> String {
+        env_hash(name, is_lock(parameters))
+    }
+
+    fn gen_load_script(&self, hash: &str) -> String {
+        format!("eval \"$(spack env activate --sh {})\"", self.env_dir(hash))
+    }
+
+    async fn find_installed_hash(
+        &self,
+        name: &str,
+        parameters: &[String],
+    ) -> anyhow::Result<Option<String>> {
+        let hash = env_hash(name, is_lock(parameters));
+        Ok(self.is_installed(&hash).await?.then_some(hash))
+    }
+}
+
+impl<Deps> SpackEnvDeployer<Deps>
+where
+    Deps: AsRef<SpackEnvDeployerState> + AsRef<Option<SshConfig>> + MaybeSsh + Send + Sync,
+{
+    async fn is_installed(&self, hash: &str) -> anyhow::Result<bool> {
+        let output = self
+            .prj_ref()
+            .command("test")
+            .args(["-f", &format!("{}/{INSTALLED_MARK}", self.env_dir(hash))])
+            .output()
+            .await
+            .context("Unable to find spack environment")?;
+        Ok(output.status.success())
+    }
+
+    /// Run spack as a process group of the deployment
+    async fn spack(&self, args: &[&str], progress: &dyn DeployProgress) -> anyhow::Result<()> {
+        let mut command = GroupCommand::new(self.prj_ref(), "spack");
+        command.args(args);
+        run_with_progress(&mut command, progress, spack_progress).await?;
+        Ok(())
+    }
+
+    async fn run(&self, program: &str, args: &[&str]) -> anyhow::Result<()> {
+        let output = self
+            .prj_ref()
+            .command(program)
+            .args(args)
+            .output()
+            .await
+            .with_context(|| format!("Unable to run {program}"))?;
+        if !output.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr))
+        }
+        Ok(())
+    }
+
+    /// Write the file where the environment is created, which may be remote
+    async fn write(&self, path: &str, content: &str) -> anyhow::Result<()> {
+        let mut child = self
+            .prj_ref()
+            .command("tee")
+            .arg(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Unable to write spack environment file")?;
+        let mut stdin = child.stdin.take().context("No stdin of tee")?;
+        stdin.write_all(content.as_bytes()).await?;
+        drop(stdin);
+
+        let output = child.wait_with_output().await?;
+        if !output.status.success() {
+            anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr))
+        }
+        Ok(())
+    }
+}
+
+fn is_lock(parameters: &[String]) -> bool {
+    parameters.iter().any(|parameter| parameter == "lock")
+}
+
+/// Hashes of all the specs in `spack.lock`, including the dependencies of the roots
+fn lock_hashes(lock: &[u8]) -> anyhow::Result<Vec<String>> {
+    let lock: serde_json::Value = serde_json::from_slice(lock)?;
+    let specs = lock["concrete_specs"].as_object().context("No concrete specs")?;
+    Ok(specs.keys().cloned().collect())
+}
+
+/// Hash of the environment file, ignoring the trailing whitespace of lines
+fn env_hash(content: &str, lock: bool) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(if lock { b"lock\0" } else { b"yaml\0" });
+    for line in content.trim().lines() {
+        hasher.update(line.trim_end().as_bytes());
+        hasher.update(b"\n");
+    }
+    hasher.finalize().to_hex()[..16].to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{env_hash, lock_hashes};
+
+    #[test]
+    fn test_env_hash() {
+        let manifest =
+            "spack:\n  specs:\n  - gromacs@2023.1+cuda %gcc@12\n  concretizer:\n    unify: true\n";
+        let hash = env_hash(manifest, false);
+        assert_eq!(hash.len(), 16);
+        assert_eq!(hash, env_hash(&manifest.replace('\n', "  \r\n"), false));
+        assert_ne!(hash, env_hash(manifest, true));
+        assert_ne!(hash, env_hash(&manifest.replace("2023.1", "2023.2"), false));
+    }
+
+    #[test]
+    fn test_lock_hashes() {
+        let lock = r#"{
+            "_meta": {"file-type": "spack-lockfile", "lockfile-version": 5},
+            "roots": [{"hash": "tgsxw6z5nmtw3srxk5wcjtcmx6k4oh3v", "spec": "zlib"}],
+            "concrete_specs": {
+                "tgsxw6z5nmtw3srxk5wcjtcmx6k4oh3v": {"name": "zlib", "version": "1.3"},
+                "qwbrnbtdk5nn2bh5m3vjgywsh2zqmvfk": {"name": "gmake", "version": "4.4.1"}
+            }
+        }"#;
+        let mut hashes = lock_hashes(lock.as_bytes()).unwrap();
+        hashes.sort();
+        assert_eq!(
+            hashes,
+            [
+                "qwbrnbtdk5nn2bh5m3vjgywsh2zqmvfk",
+                "tgsxw6z5nmtw3srxk5wcjtcmx6k4oh3v"
+            ]
+        );
+        assert!(lock_hashes(b"{}").is_err());
+    }
+}
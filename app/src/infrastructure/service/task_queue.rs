@@ -0,0 +1,132 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::time::Duration;
+
+use dep_inj::DepInj;
+use domain::{
+    model::entity::task::TaskStatus,
+    service::{TaskEntity, TaskStatusReporter},
+};
+use infrastructure::sync::{AdmissionPermit, AdmissionQueue};
+use tokio::sync::Mutex;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use crate::{config::TaskQueueConfig, dto::TaskType};
+
+/// Admission queues limiting the count of running tasks of each type
+#[derive(DepInj)]
+#[target(TaskQueue)]
+pub struct TaskQueueState {
+    deploy_software: AdmissionQueue,
+    download_file: AdmissionQueue,
+    execute_usecase: AdmissionQueue,
+    upload_file: AdmissionQueue,
+    collect_output: AdmissionQueue,
+    queued: Mutex<HashMap<Uuid, CancellationToken>>,
+}
+
+impl TaskQueueState {
+    pub fn new(config: &TaskQueueConfig) -> Self {
+        Self {
+            deploy_software: AdmissionQueue::new(config.deploy_software),
+            download_file: AdmissionQueue::new(config.download_file),
+            execute_usecase: AdmissionQueue::new(config.execute_usecase),
+            upload_file: AdmissionQueue::new(config.upload_file),
+            collect_output: AdmissionQueue::new(config.collect_output),
+            queued: Mutex::default(),
+        }
+    }
+
+    fn queue(&self, r#type: &TaskType) -> &AdmissionQueue {
+        match r#type {
+            TaskType::DeploySoftware => &self.deploy_software,
+            TaskType::DownloadFile => &self.download_file,
+            TaskType::ExecuteUsecase => &self.execute_usecase,
+            TaskType::UploadFile => &self.upload_file,
+            TaskType::CollectOuput => &self.collect_output,
+        }
+    }
+}
+
+impl<Deps> TaskQueue<Deps>
+where
+    Deps: AsRef<TaskQueueState> + Send + Sync,
+{
+    /// Wait until the task is admitted, reporting `Queued` if it has to wait.
+    ///
+    /// # return
+    ///
+    /// `None` if the task is cancelled while queuing.
+    pub async fn admit<T>(
+        &self,
+        id: Uuid,
+        r#type: &TaskType,
+        priority: i32,
+    ) -> anyhow::Result<Option<AdmissionPermit>>
+    where
+        T: TaskEntity,
+        Deps: TaskStatusReporter<T>,
+    {
+        let queue = self.queue(r#type);
+        if let Some(permit) = queue.try_acquire() {
+            return Ok(Some(permit));
+        }
+
+        let admitted = async {
+            self.prj_ref().report(id, TaskStatus::Queued).await?;
+            anyhow::Ok(queue.acquire(priority).await)
+        };
+        self.cancellable::<T, _>(id, admitted).await?.transpose()
+    }
+
+    /// Wait before retrying a task, during which it can be cancelled like a queued one.
+    ///
+    /// # return
+    ///
+    /// `false` if the task is cancelled while waiting.
+    pub async fn delay<T>(&self, id: Uuid, duration: Duration) -> anyhow::Result<bool>
+    where
+        T: TaskEntity,
+        Deps: TaskStatusReporter<T>,
+    {
+        Ok(self.cancellable::<T, _>(id, sleep(duration)).await?.is_some())
+    }
+
+    async fn cancellable<T, F>(&self, id: Uuid, fut: F) -> anyhow::Result<Option<F::Output>>
+    where
+        T: TaskEntity,
+        Deps: TaskStatusReporter<T>,
+        F: Future,
+    {
+        let cancel_token = CancellationToken::new();
+        self.queued.lock().await.insert(id, cancel_token.clone());
+
+        tokio::select! {
+            output = fut => {
+                self.queued.lock().await.remove(&id);
+                Ok(Some(output))
+            }
+            _ = cancel_token.cancelled() => {
+                self.prj_ref().report(id, TaskStatus::Cancelled).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Cancel a task which is waiting for admission or retrying.
+    ///
+    /// # return
+    ///
+    /// Whether the task is found in queue.
+    pub async fn cancel(&self, id: Uuid) -> bool {
+        match self.queued.lock().await.remove(&id) {
+            Some(cancel_token) => {
+                cancel_token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use domain::model::entity::task::TaskStatus;
+use uuid::Uuid;
+
+use crate::dto::TaskType;
+
+/// Tasks started on the agent and not ended yet
+#[derive(Default)]
+pub struct TaskRegistry {
+    tasks: Mutex<HashMap<Uuid, ActiveTask>>,
+    /// Tasks being cancelled after an attempt timed out, whose cancellation isn't reported
+    timed_out: Mutex<HashSet<Uuid>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ActiveTask {
+    pub r#type: TaskType,
+    pub node_id: Uuid,
+    /// The last reported status
+    pub status: TaskStatus,
+}
+
+impl TaskRegistry {
+    /// Record a task which is going to run
+    pub fn track(&self, id: Uuid, node_id: Uuid, r#type: TaskType) {
+        self.tasks.lock().unwrap().entry(id).or_insert(ActiveTask {
+            r#type,
+            node_id,
+            status: TaskStatus::Queued,
+        });
+    }
+
+    /// Update the status of the task, and forget it once it ends
+    pub fn update(&self, id: Uuid, status: &TaskStatus) {
+        let mut tasks = self.tasks.lock().unwrap();
+        match status {
+            TaskStatus::Completed | TaskStatus::Failed | TaskStatus::Cancelled => {
+                tasks.remove(&id);
+            }
+            status => {
+                if let Some(task) = tasks.get_mut(&id) {
+                    task.status = status.clone();
+                }
+            }
+        }
+    }
+
+    pub fn set_timed_out(&self, id: Uuid, timed_out: bool) {
+        let mut tasks = self.timed_out.lock().unwrap();
+        if timed_out {
+            tasks.insert(id);
+        } else {
+            tasks.remove(&id);
+        }
+    }
+
+    pub fn is_timed_out(&self, id: Uuid) -> bool {
+        self.timed_out.lock().unwrap().contains(&id)
+    }
+
+    pub fn get(&self, id: Uuid) -> Option<ActiveTask> {
+        self.tasks.lock().unwrap().get(&id).cloned()
+    }
+
+    pub fn list(&self) -> Vec<(Uuid, ActiveTask)> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks.iter().map(|(id, task)| (*id, task.clone())).collect()
+    }
+}
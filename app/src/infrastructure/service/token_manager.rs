@@ -0,0 +1,243 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use arc_swap::ArcSwap;
+use chrono::Utc;
+use reqwest::Client;
+use reqwest_middleware::{ClientBuilder, ClientWithMiddleware};
+use tokio::sync::{Mutex, RwLock};
+use tokio::time::sleep;
+use url::Url;
+
+use super::keycloak::{self, GrantInfo, OFFLINE_ACCESS};
+use super::token_store::TokenStore;
+use crate::infrastructure::http::authorization::{expiration, Bearer};
+use crate::infrastructure::http::middleware::TimeoutMiddleware;
+use crate::infrastructure::metrics::Metrics;
+use crate::login::grant::poll_grant;
+
+/// Seconds before the access token expires to refresh it
+const REFRESH_AHEAD: i64 = 60;
+/// How long to wait before refreshing again if the access token has no expiration
+const UNKNOWN_EXP_INTERVAL: Duration = Duration::from_secs(60);
+/// How long to wait before obtaining tokens again after failures
+const RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How to obtain tokens
+pub enum Credential {
+    /// The client credentials grant with the client secret
+    ClientSecret(String),
+    /// The refresh token grant, saving the rotated refresh token for restarts.
+    /// When the refresh token is revoked or expired, the device flow runs again.
+    Device { store: TokenStore, offline: bool },
+}
+
+/// Keeps the access token valid for outbound requests
+pub struct TokenManager {
+    tokens: ArcSwap<Tokens>,
+    oidc_server: Url,
+    client_id: String,
+    credential: Credential,
+    client: ClientWithMiddleware,
+    /// Held while renewing tokens so that concurrent renewals happen once
+    renewing: Mutex<()>,
+    /// Written while re-authenticating, holding outbound requests
+    reauthenticating: RwLock<()>,
+    metrics: Arc<Metrics>,
+}
+
+#[derive(Debug)]
+struct Tokens {
+    access_token: Bearer,
+    refresh_token: String,
+}
+
+impl TokenManager {
+    pub fn new(
+        oidc_server: Url,
+        client_id: impl Into<String>,
+        credential: Credential,
+        grant_info: GrantInfo,
+        timeout: Duration,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Self {
+            tokens: ArcSwap::from_pointee(Tokens {
+                access_token: Bearer::new(&grant_info.access_token),
+                refresh_token: grant_info.refresh_token,
+            }),
+            oidc_server,
+            client_id: client_id.into(),
+            credential,
+            client: ClientBuilder::new(Client::new()).with(TimeoutMiddleware::new(timeout)).build(),
+            renewing: Mutex::default(),
+            reauthenticating: RwLock::default(),
+            metrics,
+        }
+    }
+
+    /// The access token with the `Bearer ` prefix, waiting while re-authenticating
+    pub async fn bearer(&self) -> String {
+        let _reauthenticated = self.reauthenticating.read().await;
+        self.tokens.load().access_token.as_str().to_owned()
+    }
+
+    /// Renew the tokens, unless they have been renewed since the `stale` bearer was taken.
+    ///
+    /// Re-authenticate if the refresh token is revoked or expired.
+    pub async fn renew(&self, stale: &str) -> anyhow::Result<()> {
+        let _guard = self.renewing.lock().await;
+        if self.tokens.load().access_token.as_str() != stale {
+            return Ok(());
+        }
+
+        match self.refresh().await {
+            Ok(()) => Ok(()),
+            Err(reqwest_middleware::Error::Reqwest(e))
+                if e.status().is_some_and(|status| status.is_client_error()) =>
+            {
+                tracing::warn!("Refresh token is rejected, re-authenticating: {e}");
+                self.reauthenticate().await;
+                Ok(())
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Refresh the access token shortly before it expires, forever
+    pub async fn keep_fresh(&self) {
+        loop {
+            let (bearer, exp) = {
+                let tokens = self.tokens.load();
+                let exp = expiration(tokens.access_token.token());
+                (tokens.access_token.as_str().to_owned(), exp)
+            };
+            let wait = match exp {
+                Some(exp) => {
+                    Duration::from_secs((exp - REFRESH_AHEAD - Utc::now().timestamp()).max(0) as u64)
+                }
+                None => UNKNOWN_EXP_INTERVAL,
+            };
+            sleep(wait).await;
+
+            if let Err(e) = self.renew(&bearer).await {
+                tracing::error!("Failed to refresh the access token ahead: {e:#}");
+                sleep(RETRY_INTERVAL).await;
+            }
+        }
+    }
+
+    /// Check that the tokens are usable, renewing the access token if it's expired
+    pub async fn check(&self) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            self.reauthenticating.try_read().is_ok(),
+            "Re-authenticating"
+        );
+        let (bearer, access_exp) = {
+            let tokens = self.tokens.load();
+            let exp = expiration(tokens.access_token.token());
+            (tokens.access_token.as_str().to_owned(), exp)
+        };
+        if access_exp.context("Invalid access token")? <= Utc::now().timestamp() {
+            self.renew(&bearer).await.context("Failed to renew the expired access token")?;
+        }
+        Ok(())
+    }
+
+    async fn refresh(&self) -> reqwest_middleware::Result<()> {
+        let token_url = self.oidc_server.join("token").unwrap();
+        let result = match &self.credential {
+            Credential::ClientSecret(secret) => {
+                keycloak::client_credentials(&self.client, token_url, &self.client_id, secret).await
+            }
+            Credential::Device { .. } => {
+                let refresh_token = self.tokens.load().refresh_token.clone();
+                keycloak::refresh_token(&self.client, token_url, &self.client_id, &refresh_token)
+                    .await
+            }
+        };
+        let grant_info = match result {
+            Ok(info) => info,
+            Err(e) => {
+                self.metrics.token_refreshes.with_label_values(&["failure"]).inc();
+                tracing::error!(cause = %e, "Refresh token failed");
+                return Err(e);
+            }
+        };
+
+        self.update(grant_info).await;
+        self.metrics.token_refreshes.with_label_values(&["success"]).inc();
+        Ok(())
+    }
+
+    /// Hold outbound requests and obtain new tokens through the configured grant until it succeeds
+    async fn reauthenticate(&self) {
+        let _holding = self.reauthenticating.write().await;
+        loop {
+            let result = match &self.credential {
+                Credential::ClientSecret(secret) => keycloak::client_credentials(
+                    &self.client,
+                    self.oidc_server.join("token").unwrap(),
+                    &self.client_id,
+                    secret,
+                )
+                .await
+                .map_err(anyhow::Error::from),
+                Credential::Device { store, offline } => {
+                    if let Err(e) = store.remove().await {
+                        tracing::error!("Failed to remove the saved refresh token: {e}");
+                    }
+                    self.device_flow(*offline).await
+                }
+            };
+            match result {
+                Ok(grant_info) => {
+                    self.update(grant_info).await;
+                    break;
+                }
+                Err(e) => {
+                    tracing::error!("Failed to re-authenticate: {e:#}");
+                    sleep(RETRY_INTERVAL).await;
+                }
+            }
+        }
+        tracing::info!("Re-authenticated, resuming requests");
+    }
+
+    async fn device_flow(&self, offline: bool) -> anyhow::Result<GrantInfo> {
+        let client = Client::new();
+        let info = keycloak::login(
+            &client,
+            self.oidc_server.join("auth/device").unwrap(),
+            &self.client_id,
+            offline.then_some(OFFLINE_ACCESS),
+        )
+        .await?;
+        tracing::error!(
+            user_code = %info.user_code,
+            "Login again required, please verify your identity at: {}",
+            info.verification_uri
+        );
+
+        poll_grant(keycloak::grant_request(
+            &client,
+            self.oidc_server.join("token").unwrap(),
+            &self.client_id,
+            &info.device_code,
+        ))
+        .await
+    }
+
+    async fn update(&self, grant_info: GrantInfo) {
+        if let Credential::Device { store, .. } = &self.credential {
+            if let Err(e) = store.save(&grant_info.refresh_token).await {
+                tracing::error!(path = %store.path().display(), "Failed to save refresh token: {e}");
+            }
+        }
+        self.tokens.store(Arc::new(Tokens {
+            access_token: Bearer::new(&grant_info.access_token),
+            refresh_token: grant_info.refresh_token,
+        }));
+    }
+}
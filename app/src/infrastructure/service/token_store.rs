@@ -0,0 +1,60 @@
+use std::fs::Permissions;
+use std::io;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+use crate::config::AgentConfig;
+
+/// Keeps the refresh token in a file readable only by the owner, reused on restart
+#[derive(Debug, Clone)]
+pub struct TokenStore {
+    path: PathBuf,
+}
+
+impl TokenStore {
+    pub fn new(config: &AgentConfig) -> Self {
+        Self {
+            path: Path::new(&config.save_path).join(&config.login.token_file),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The saved refresh token, `None` if not saved yet
+    pub async fn load(&self) -> io::Result<Option<String>> {
+        match fs::read_to_string(&self.path).await {
+            Ok(token) => Ok(Some(token.trim().to_owned()).filter(|token| !token.is_empty())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn save(&self, refresh_token: &str) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&self.path)
+            .await?;
+        // The mode only applies to a new file
+        file.set_permissions(Permissions::from_mode(0o600)).await?;
+        file.write_all(refresh_token.as_bytes()).await?;
+        file.flush().await
+    }
+
+    pub async fn remove(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
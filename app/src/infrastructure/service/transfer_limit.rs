@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use infrastructure::sync::RateLimiter;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::config::TransferConfig;
+
+/// Limits shared by all file uploads and downloads
+pub struct TransferLimit {
+    /// Max count of workers transmitting blocks for one file
+    pub workers_per_file: usize,
+    workers: Arc<Semaphore>,
+    bandwidth: Option<RateLimiter>,
+}
+
+impl TransferLimit {
+    pub fn new(config: &TransferConfig) -> Self {
+        Self {
+            workers_per_file: config.max_workers_per_file.max(1),
+            workers: Arc::new(Semaphore::new(config.max_workers.max(1))),
+            bandwidth: config.bandwidth.map(|b| RateLimiter::new(b.0)),
+        }
+    }
+
+    /// Wait for a free slot among all transmitting workers
+    pub async fn acquire_worker(&self) -> OwnedSemaphorePermit {
+        // The semaphore is never closed
+        self.workers.clone().acquire_owned().await.unwrap()
+    }
+
+    /// Wait until `bytes` are allowed to be transmitted
+    pub async fn consume(&self, bytes: u64) {
+        if let Some(bandwidth) = &self.bandwidth {
+            bandwidth.acquire(bytes).await;
+        }
+    }
+}
+
+/// How a file transmission ends
+pub enum TransferEnd {
+    Finished,
+    Cancelled,
+    /// Stopped because the agent is shutting down
+    Suspended,
+}
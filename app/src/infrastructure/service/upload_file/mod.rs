@@ -24,11 +24,15 @@ use url::Url;
 use uuid::Uuid;
 
 use self::supervisor::UploadFileSupervisor;
+use super::transfer_limit::{TransferEnd, TransferLimit};
 use crate::{
-    dto::{IncompleteOldUpload, PartialUploadInfo, StatusCode},
-    infrastructure::http::{
-        header::TASK_ID,
-        middleware::{RetryOnError, RetryStreamClient},
+    dto::{IncompleteOldUpload, PartialUploadInfo, StatusCode, TransferProgress},
+    infrastructure::{
+        http::{
+            header::TASK_ID,
+            middleware::{RetryOnError, RetryStreamClient},
+        },
+        metrics::Metrics,
     },
 };
 use crate::{
@@ -42,11 +46,15 @@ pub struct RawUploadFileService {
     base_url: Url,
     block_size: u64,
     client: ClientWithMiddleware,
-    #[builder(setter(transform = |client: ClientWithMiddleware, retries: u32| {
-        let policy = ExponentialBackoff::builder().build_with_max_retries(retries);
-        RetryStreamClient::new(client, policy, RetryOnError)
-    }))]
+    #[builder(setter(
+        transform = |client: ClientWithMiddleware, retries: u32, metrics: Arc<Metrics>| {
+            let policy = ExponentialBackoff::builder().build_with_max_retries(retries);
+            RetryStreamClient::new(client, policy, RetryOnError::new(metrics))
+        }
+    ))]
     stream_client: RetryStreamClient<ExponentialBackoff, RetryOnError>,
+    transfer_limit: Arc<TransferLimit>,
+    metrics: Arc<Metrics>,
 }
 
 #[derive(DepInj)]
@@ -57,6 +65,7 @@ pub struct UploadFileState {
     prepare_upload_url: Url,
     upload_info_url: Url,
     client: ClientWithMiddleware,
+    suspend: CancellationToken,
     inner: Arc<UploadFileServiceInner>,
 }
 
@@ -64,6 +73,8 @@ pub struct UploadFileServiceInner {
     block_size: u64,
     upload_url: Url,
     retry_stream_req: RetryStreamClient<ExponentialBackoff, RetryOnError>,
+    transfer_limit: Arc<TransferLimit>,
+    metrics: Arc<Metrics>,
 }
 
 impl From<RawUploadFileService> for UploadFileState {
@@ -74,6 +85,8 @@ impl From<RawUploadFileService> for UploadFileState {
             client,
             block_size,
             stream_client: retry_stream_req,
+            transfer_limit,
+            metrics,
         } = raw;
 
         Self {
@@ -84,10 +97,13 @@ impl From<RawUploadFileService> for UploadFileState {
                 .unwrap(),
             upload_info_url: base_url.join("file-storage/PartialUploadInfo/").unwrap(),
             client,
+            suspend: CancellationToken::new(),
             inner: Arc::new(UploadFileServiceInner {
                 block_size,
                 upload_url: base_url.join("file-storage/PartialUpload").unwrap(),
                 retry_stream_req,
+                transfer_limit,
+                metrics,
             }),
         }
     }
@@ -109,8 +125,17 @@ where
         match result {
             Ok("") => self.prj_ref().report(id, TaskStatus::Completed).await,
             Ok("cancel") => Ok(()),
+            Ok("suspend") => {
+                self.prj_ref()
+                    .report_msg(
+                        id,
+                        TaskStatus::Paused,
+                        "Agent is shutting down, the uploaded parts are kept",
+                    )
+                    .await
+            }
             Ok(msg) => self.prj_ref().report_msg(id, TaskStatus::Completed, msg).await,
-            Err(e) => self.prj_ref().report_msg(id, TaskStatus::Failed, &e.to_string()).await,
+            Err(e) => Err(e),
         }
     }
 
@@ -152,6 +177,28 @@ where
     }
 }
 
+impl<Deps> UploadFileService<Deps>
+where
+    Deps: AsRef<UploadFileState>,
+{
+    /// Stop all uploads, the uploaded parts are kept by the server.
+    /// Uploads started later are stopped at once.
+    pub fn suspend_all(&self) {
+        self.suspend.cancel();
+    }
+
+    /// Progress of the upload, absent if it isn't transmitting in blocks
+    pub async fn progress(&self, id: Uuid) -> Option<TransferProgress> {
+        self.id2supervisor.lock().await.get(&id).map(|supervisor| supervisor.progress())
+    }
+
+    /// Average bytes per second of the running uploads
+    pub async fn throughputs(&self) -> Vec<(Uuid, f64)> {
+        let map = self.id2supervisor.lock().await;
+        map.iter().map(|(id, supervisor)| (*id, supervisor.throughput())).collect()
+    }
+}
+
 impl<Deps> UploadFileService<Deps>
 where
     Deps: AsRef<UploadFileState> + TaskStatusReporter<UploadFile> + Scp + Send + Sync,
@@ -257,15 +304,17 @@ where
             file,
             task_file.file_id,
             task_file.path,
+            block_count,
             index_queue,
+            self.suspend.clone(),
         ));
         self.id2supervisor.lock().await.insert(task.id, supervisor.clone());
 
-        if supervisor.run().await {
-            return Ok("cancel");
+        match supervisor.run().await {
+            TransferEnd::Finished => Ok(""),
+            TransferEnd::Cancelled => Ok("cancel"),
+            TransferEnd::Suspended => Ok("suspend"),
         }
-
-        Ok("")
     }
 }
 
@@ -1,8 +1,9 @@
 use std::io;
 use std::io::SeekFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::sync::Weak;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crossbeam_queue::ArrayQueue;
 use infrastructure::sync::PauseToken;
@@ -13,14 +14,17 @@ use tokio::io::AsyncReadExt;
 use tokio::io::AsyncSeekExt;
 use tokio::sync::OwnedSemaphorePermit;
 use tokio::sync::{Mutex, Semaphore};
-use tokio::time::sleep;
+use tokio::time::{sleep, timeout};
 use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 use uuid::Uuid;
 
 use super::UploadFileServiceInner;
-use crate::infrastructure::http::header::TASK_ID;
+use crate::dto::TransferProgress;
+use crate::infrastructure::{http::header::TASK_ID, service::transfer_limit::TransferEnd};
 
-const MAX_WORKER_COUNT: usize = 16;
+/// How long to wait for the running workers when suspending
+const SUSPEND_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct UploadFileSupervisor {
     // Used in request
@@ -30,11 +34,17 @@ pub struct UploadFileSupervisor {
     file_id: Uuid,
     file_path: String,
     index_queue: ArrayQueue<u64>,
+    block_count: u64,
+    finished_blocks: AtomicU64,
+    transferred_bytes: AtomicU64,
+    started_at: Instant,
     // status control resources
+    worker_count: usize,
     start_guard: Arc<Semaphore>,
     pub(super) pause_token: PauseToken,
     pub(super) cancel_workers: Mutex<CancellationToken>,
     pub(super) cancel_upload: CancellationToken,
+    suspend: CancellationToken,
     // The tools we need
     service: Weak<UploadFileServiceInner>,
 }
@@ -51,8 +61,8 @@ impl UploadFileSupervisor {
     ///
     /// # return
     ///
-    /// The routine is cancelled or not.
-    pub async fn run(self: Arc<Self>) -> bool {
+    /// How the routine ends.
+    pub async fn run(self: Arc<Self>) -> TransferEnd {
         let task_id = self.task_id;
 
         loop {
@@ -65,7 +75,8 @@ impl UploadFileSupervisor {
                             cancel_token: self.cancel_workers.lock().await.clone(),
                             supervisor: Arc::downgrade(&self),
                         };
-                        tokio::spawn(async move {
+                        let span = tracing::info_span!("upload_block", block_index);
+                        let routine = async move {
                             if let Err(e) = worker.start().await {
                                 tracing::error!(
                                     %task_id,
@@ -73,42 +84,84 @@ impl UploadFileSupervisor {
                                     "Upload part failed: {e}"
                                 );
                             }
-                        });
-                    } else if self.start_guard.available_permits() + 1 < MAX_WORKER_COUNT {
-                        // Plus the just acquired one is less than 16,
+                        };
+                        tokio::spawn(routine.instrument(span));
+                    } else if self.start_guard.available_permits() + 1 < self.worker_count {
+                        // Plus the just acquired one is less than the total,
                         // means there are still some workers are running.
                         // Wait for a moment and then try again.
                         sleep(Duration::from_secs(1)).await;
                     } else {
-                        break false;
+                        break TransferEnd::Finished;
                     };
                 }
                 _ = self.cancel_upload.cancelled() => {
                     self.cancel_workers.lock().await.cancel();
-                    break true;
+                    break TransferEnd::Cancelled;
+                }
+                _ = self.suspend.cancelled() => {
+                    self.stop_workers().await;
+                    break TransferEnd::Suspended;
                 }
             }
         }
     }
 
+    pub fn progress(&self) -> TransferProgress {
+        TransferProgress {
+            total_blocks: self.block_count,
+            finished_blocks: self.finished_blocks.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Average bytes per second since started
+    pub fn throughput(&self) -> f64 {
+        let bytes = self.transferred_bytes.load(Ordering::Relaxed);
+        bytes as f64 / self.started_at.elapsed().as_secs_f64().max(1.0)
+    }
+
+    /// Wait for the running workers to finish their blocks,
+    /// and cancel them if it takes too long.
+    async fn stop_workers(&self) {
+        let all = self.worker_count as u32;
+        if timeout(SUSPEND_TIMEOUT, self.start_guard.acquire_many(all)).await.is_err() {
+            self.cancel_workers.lock().await.cancel();
+            // The semaphore is never closed
+            let _ = self.start_guard.acquire_many(all).await;
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         sv: &Arc<UploadFileServiceInner>,
         task_id: Uuid,
         file: File,
         file_id: Uuid,
         file_path: String,
+        block_count: u64,
         index_queue: ArrayQueue<u64>,
+        suspend: CancellationToken,
     ) -> Self {
+        let worker_count = sv.transfer_limit.workers_per_file;
+        // Blocks not in the queue are uploaded before
+        let finished_blocks = AtomicU64::new(block_count.saturating_sub(index_queue.len() as u64));
+
         Self {
             task_id,
             file: Mutex::new(file),
             file_id,
             file_path,
             index_queue,
-            start_guard: Arc::new(Semaphore::new(MAX_WORKER_COUNT)),
+            block_count,
+            finished_blocks,
+            transferred_bytes: AtomicU64::new(0),
+            started_at: Instant::now(),
+            worker_count,
+            start_guard: Arc::new(Semaphore::new(worker_count)),
             pause_token: PauseToken::default(),
             cancel_workers: Mutex::default(),
             cancel_upload: CancellationToken::default(),
+            suspend,
             service: Arc::downgrade(sv),
         }
     }
@@ -134,6 +187,7 @@ impl UploadFileWorker {
         let supervisor_sv = self.supervisor.upgrade().unwrap();
         let upload_file_sv = supervisor_sv.service.upgrade().unwrap();
 
+        let _permit = upload_file_sv.transfer_limit.acquire_worker().await;
         let mut buf = vec![0u8; upload_file_sv.block_size as usize];
 
         {
@@ -159,6 +213,7 @@ impl UploadFileWorker {
             }
         }
 
+        upload_file_sv.transfer_limit.consume(buf.len() as u64).await;
         upload_file_sv
             .retry_stream_req
             .execute(|client| async {
@@ -180,6 +235,13 @@ impl UploadFileWorker {
             })
             .await?
             .error_for_status()?;
+        supervisor_sv.finished_blocks.fetch_add(1, Ordering::Relaxed);
+        supervisor_sv.transferred_bytes.fetch_add(buf.len() as u64, Ordering::Relaxed);
+        upload_file_sv
+            .metrics
+            .transfer_bytes
+            .with_label_values(&["upload"])
+            .inc_by(buf.len() as u64);
 
         Ok(())
     }
@@ -0,0 +1,366 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytesize::ByteSize;
+use dep_inj::DepInj;
+use domain::model::entity::task::TaskStatus;
+use service::prelude::JobServiceState;
+use tokio::fs;
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+use crate::config::WorkspaceConfig;
+use crate::dto::TaskType;
+use crate::infrastructure::command::{MaybeSsh, SshConfig};
+
+/// Marks a workspace having failed tasks, so that it's still known after restarting
+const FAILED_MARK: &str = ".failed";
+/// How long to wait after the last upload before removing a workspace,
+/// as the other outputs of the node may be uploaded later.
+const UPLOAD_GRACE: Duration = Duration::from_secs(10 * 60);
+/// How long to keep a workspace after its last task ended, as the next task of the node
+/// may not have arrived yet.
+const IDLE_GRACE: Duration = Duration::from_secs(10 * 60);
+
+/// Tracks the workspaces of nodes, i.e. `<save_dir>/<node_id>` locally and remotely
+#[derive(DepInj)]
+#[target(WorkspaceManager)]
+pub struct WorkspaceState {
+    save_dir: PathBuf,
+    config: WorkspaceConfig,
+    /// task id -> (node id, is uploading), from the task being queued until it ends
+    tasks: Mutex<HashMap<Uuid, (Uuid, bool)>>,
+    nodes: Mutex<HashMap<Uuid, NodeWorkspace>>,
+    reclaimed: AtomicU64,
+}
+
+#[derive(Default)]
+struct NodeWorkspace {
+    running: usize,
+    uploaded_at: Option<SystemTime>,
+    ended_at: Option<SystemTime>,
+}
+
+/// Disk usage of a workspace
+#[derive(Clone, Copy)]
+struct Usage {
+    size: u64,
+    modified: SystemTime,
+    failed: bool,
+}
+
+impl WorkspaceState {
+    pub fn new(save_dir: &str, config: WorkspaceConfig) -> Self {
+        Self {
+            save_dir: PathBuf::from(save_dir),
+            config,
+            tasks: Mutex::default(),
+            nodes: Mutex::default(),
+            reclaimed: AtomicU64::new(0),
+        }
+    }
+
+    /// Total bytes reclaimed since the agent started
+    pub fn reclaimed(&self) -> u64 {
+        self.reclaimed.load(Ordering::Relaxed)
+    }
+}
+
+impl<Deps> WorkspaceManager<Deps>
+where
+    Deps: AsRef<WorkspaceState>
+        + AsRef<JobServiceState>
+        + AsRef<Option<SshConfig>>
+        + MaybeSsh
+        + Send
+        + Sync,
+{
+    /// Record that the task is queued or running in the workspace of its node
+    pub fn track(&self, task_id: Uuid, node_id: Uuid, r#type: &TaskType) {
+        let upload = matches!(r#type, TaskType::UploadFile);
+        if self.tasks.lock().unwrap().insert(task_id, (node_id, upload)).is_none() {
+            self.nodes.lock().unwrap().entry(node_id).or_default().running += 1;
+        }
+    }
+
+    /// Update the workspace when the task reports its status
+    pub async fn on_status(&self, task_id: Uuid, status: &TaskStatus) {
+        if !matches!(
+            status,
+            TaskStatus::Completed | TaskStatus::Failed | TaskStatus::Cancelled
+        ) {
+            return;
+        }
+        let Some((node_id, upload)) = self.tasks.lock().unwrap().remove(&task_id) else {
+            return;
+        };
+
+        {
+            let mut nodes = self.nodes.lock().unwrap();
+            let node = nodes.entry(node_id).or_default();
+            node.running = node.running.saturating_sub(1);
+            node.ended_at = Some(SystemTime::now());
+            if upload && matches!(status, TaskStatus::Completed) {
+                node.uploaded_at = Some(SystemTime::now());
+            }
+        }
+
+        if matches!(status, TaskStatus::Failed) {
+            let dir = self.save_dir.join(node_id.to_string());
+            let mark = async {
+                fs::create_dir_all(&dir).await?;
+                fs::write(dir.join(FAILED_MARK), task_id.to_string()).await
+            };
+            if let Err(e) = mark.await {
+                tracing::warn!(%node_id, "Failed to mark workspace as failed: {e}");
+            }
+            if let Err(e) = self.mark_remote_failed(node_id).await {
+                tracing::warn!(%node_id, "Failed to mark remote workspace as failed: {e}");
+            }
+        }
+    }
+
+    /// Remove the workspaces according to the retention rules
+    pub async fn collect_garbage(&self) -> anyhow::Result<()> {
+        let mut usages = local_usages(self.save_dir.clone()).await?;
+        if self.prj_ref().is_ssh() {
+            for (node_id, remote) in self.remote_usages().await? {
+                let usage = usages.entry(node_id).or_insert(Usage {
+                    size: 0,
+                    modified: UNIX_EPOCH,
+                    failed: false,
+                });
+                usage.size += remote.size;
+                usage.modified = usage.modified.max(remote.modified);
+                usage.failed |= remote.failed;
+            }
+        }
+
+        let now = SystemTime::now();
+        let max_age = Duration::from_secs(self.config.max_age * 60 * 60);
+        let mut total: u64 = usages.values().map(|usage| usage.size).sum();
+        let mut candidates = Vec::new();
+        let mut removed = Vec::new();
+        // The jobs outlive their tasks, and may be restored without them
+        let job_nodes = AsRef::<JobServiceState>::as_ref(self.prj_ref()).nodes();
+
+        for (node_id, usage) in usages {
+            if job_nodes.contains(&node_id.to_string()) {
+                continue;
+            }
+            let uploaded_at = {
+                let nodes = self.nodes.lock().unwrap();
+                match nodes.get(&node_id) {
+                    Some(node) if node.running > 0 => continue,
+                    Some(node) if node.ended_at.is_some_and(|t| elapsed(now, t) < IDLE_GRACE) => {
+                        continue
+                    }
+                    Some(node) => node.uploaded_at,
+                    None => None,
+                }
+            };
+            if usage.failed && self.config.keep_failed {
+                continue;
+            }
+
+            let uploaded = self.config.remove_after_upload
+                && uploaded_at.is_some_and(|t| elapsed(now, t) >= UPLOAD_GRACE);
+            if uploaded || elapsed(now, usage.modified) >= max_age {
+                removed.push((node_id, usage));
+            } else {
+                candidates.push((node_id, usage));
+            }
+        }
+
+        if let Some(quota) = self.config.quota {
+            total -= removed.iter().map(|(_, usage)| usage.size).sum::<u64>();
+            // The oldest ones first
+            candidates.sort_by_key(|(_, usage)| usage.modified);
+            for candidate in candidates {
+                if total <= quota.0 {
+                    break;
+                }
+                total -= candidate.1.size;
+                removed.push(candidate);
+            }
+        }
+
+        let mut reclaimed = 0;
+        let mut count = 0;
+        for (node_id, usage) in removed {
+            match self.remove(node_id).await {
+                Ok(()) => {
+                    reclaimed += usage.size;
+                    count += 1;
+                }
+                Err(e) => tracing::error!(%node_id, "Failed to remove workspace: {e}"),
+            }
+        }
+
+        if count > 0 {
+            self.reclaimed.fetch_add(reclaimed, Ordering::Relaxed);
+            tracing::info!(
+                "Removed {count} workspaces, reclaimed {} ({} in total)",
+                ByteSize(reclaimed),
+                ByteSize(self.reclaimed())
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn remove(&self, node_id: Uuid) -> anyhow::Result<()> {
+        let node = node_id.to_string();
+        let local = self.save_dir.join(&node);
+        if local.exists() {
+            fs::remove_dir_all(&local).await?;
+        }
+
+        if let Some(ssh) = AsRef::<Option<SshConfig>>::as_ref(self.prj_ref()) {
+            let remote = format!("{}/{}/{node}", ssh.home_dir, ssh.save_dir);
+            let output = self.prj_ref().command("rm").args(["-rf", &remote]).output().await?;
+            if !output.status.success() {
+                anyhow::bail!(
+                    "Failed to remove {remote}: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                );
+            }
+        }
+
+        self.nodes.lock().unwrap().remove(&node_id);
+        tracing::debug!(%node_id, "Workspace removed");
+        Ok(())
+    }
+
+    async fn remote_usages(&self) -> anyhow::Result<HashMap<Uuid, Usage>> {
+        let Some(ssh) = AsRef::<Option<SshConfig>>::as_ref(self.prj_ref()) else {
+            return Ok(HashMap::new());
+        };
+
+        let output = self
+            .prj_ref()
+            .command("du")
+            .args(["-sk", "--time", "--time-style=+%s"])
+            .arg(format!("{}/{}/*", ssh.home_dir, ssh.save_dir))
+            .output()
+            .await?;
+        // `du` fails if some files are unreadable, but still prints the others
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut usages: HashMap<_, _> = stdout.lines().filter_map(parse_du_line).collect();
+
+        let output = self
+            .prj_ref()
+            .command("ls")
+            .arg("-1d")
+            .arg(format!("{}/{}/*/{FAILED_MARK}", ssh.home_dir, ssh.save_dir))
+            .output()
+            .await?;
+        // `ls` fails if no workspace is marked
+        for mark in String::from_utf8_lossy(&output.stdout).lines() {
+            let node_id = Path::new(mark).parent().and_then(Path::file_name);
+            let node_id = node_id.and_then(|id| id.to_str()?.parse().ok());
+            if let Some(usage) = node_id.and_then(|id| usages.get_mut(&id)) {
+                usage.failed = true;
+            }
+        }
+        Ok(usages)
+    }
+
+    /// Mark the workspace on the remote side as well, as it's cleaned separately
+    async fn mark_remote_failed(&self, node_id: Uuid) -> anyhow::Result<()> {
+        let Some(ssh) = AsRef::<Option<SshConfig>>::as_ref(self.prj_ref()) else {
+            return Ok(());
+        };
+
+        let dir = format!("{}/{}/{node_id}", ssh.home_dir, ssh.save_dir);
+        let mark = format!("{dir}/{FAILED_MARK}");
+        for (program, args) in [("mkdir", ["-p", &dir]), ("touch", ["--", &mark])] {
+            let output = self.prj_ref().command(program).args(args).output().await?;
+            if !output.status.success() {
+                anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a line like `<KiB>\t<mtime>\t<path>`
+fn parse_du_line(line: &str) -> Option<(Uuid, Usage)> {
+    let mut fields = line.splitn(3, '\t');
+    let size: u64 = fields.next()?.parse().ok()?;
+    let modified: u64 = fields.next()?.parse().ok()?;
+    let node_id = Path::new(fields.next()?).file_name()?.to_str()?.parse().ok()?;
+
+    let usage = Usage {
+        size: size * 1024,
+        modified: UNIX_EPOCH + Duration::from_secs(modified),
+        failed: false,
+    };
+    Some((node_id, usage))
+}
+
+async fn local_usages(save_dir: PathBuf) -> io::Result<HashMap<Uuid, Usage>> {
+    tokio::task::spawn_blocking(move || {
+        let mut usages = HashMap::new();
+        if !save_dir.exists() {
+            return Ok(usages);
+        }
+
+        for entry in std::fs::read_dir(&save_dir)? {
+            let entry = entry?;
+            // Only the directories of nodes are managed
+            let Some(node_id) = entry.file_name().to_str().and_then(|s| s.parse().ok()) else {
+                continue;
+            };
+            if !entry.file_type()?.is_dir() {
+                continue;
+            }
+
+            let mut usage = Usage {
+                size: 0,
+                modified: UNIX_EPOCH,
+                failed: entry.path().join(FAILED_MARK).exists(),
+            };
+            for entry in WalkDir::new(entry.path()).into_iter().filter_map(Result::ok) {
+                let metadata = entry.metadata()?;
+                if metadata.is_file() {
+                    usage.size += metadata.len();
+                }
+                if let Ok(modified) = metadata.modified() {
+                    usage.modified = usage.modified.max(modified);
+                }
+            }
+            usages.insert(node_id, usage);
+        }
+
+        Ok(usages)
+    })
+    .await
+    .unwrap()
+}
+
+fn elapsed(now: SystemTime, since: SystemTime) -> Duration {
+    now.duration_since(since).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::parse_du_line;
+
+    #[test]
+    fn test_parse_du_line() {
+        let line = "2048\t1700000000\t~/tasks/10b712f0-5577-4f79-a582-330b51abbc13";
+        let (node_id, usage) = parse_du_line(line).unwrap();
+        assert_eq!(node_id.to_string(), "10b712f0-5577-4f79-a582-330b51abbc13");
+        assert_eq!(usage.size, 2048 * 1024);
+        assert_eq!(usage.modified, UNIX_EPOCH + Duration::from_secs(1700000000));
+
+        assert!(parse_du_line("4\t1700000000\t~/tasks/.jobs.json").is_none());
+    }
+}
@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use opentelemetry::global;
+use opentelemetry::propagation::{Extractor, Injector};
+use opentelemetry::sdk::propagation::TraceContextPropagator;
+use rdkafka::message::{BorrowedHeaders, Headers};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+
+use crate::dto::TaskCommand;
+
+/// Propagate trace context in the W3C `traceparent` and `tracestate` headers
+pub fn init_propagator() {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+}
+
+/// The root span of handling a task command,
+/// continuing the trace of the backend if its context is in the Kafka headers.
+pub fn task_span(id: Uuid, command: &TaskCommand, headers: Option<&BorrowedHeaders>) -> Span {
+    let span = tracing::info_span!("task", task_id = %id, ?command);
+    if let Some(headers) = headers {
+        let cx = global::get_text_map_propagator(|p| p.extract(&KafkaHeaders(headers)));
+        span.set_parent(cx);
+    }
+    span
+}
+
+/// Write the context of the current span into the request headers
+pub fn inject_context(headers: &mut HeaderMap) {
+    let cx = Span::current().context();
+    global::get_text_map_propagator(|p| p.inject_context(&cx, &mut HttpHeaders(headers)));
+}
+
+/// The `traceparent` of the current span, passed to subprocesses in the `TRACEPARENT` variable
+pub fn traceparent() -> Option<String> {
+    let cx = Span::current().context();
+    let mut fields: HashMap<String, String> = HashMap::new();
+    global::get_text_map_propagator(|p| p.inject_context(&cx, &mut fields));
+    fields.remove("traceparent")
+}
+
+struct KafkaHeaders<'a>(&'a BorrowedHeaders);
+
+impl Extractor for KafkaHeaders<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|header| header.key.eq_ignore_ascii_case(key))
+            .and_then(|header| header.value)
+            .and_then(|value| std::str::from_utf8(value).ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.iter().map(|header| header.key).collect()
+    }
+}
+
+struct HttpHeaders<'a>(&'a mut HeaderMap);
+
+impl Injector for HttpHeaders<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
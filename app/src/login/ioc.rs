@@ -5,8 +5,8 @@ use crate::{
     infrastructure::{
         command::SshConfig,
         service::resource_stat::{
-            Pbs, ResourceStat, ResourceStatImpl, SchedulerStat, SchedulerTotalResources,
-            SchedulerUsedResources, Slurm, TotalResources, UsedResources,
+            Inventory, Lsf, Pbs, ResourceStat, ResourceStatImpl, SchedulerStat,
+            SchedulerTotalResources, SchedulerUsedResources, Slurm, TotalResources, UsedResources,
         },
     },
 };
@@ -50,7 +50,7 @@ impl SchedulerStat for BootLoader {
         match self.job_scheduler {
             JobScheduler::Pbs => Pbs::inj_ref(self).total().await,
             JobScheduler::Slurm => Slurm::inj_ref(self).total().await,
-            JobScheduler::Lsf => Ok(SchedulerTotalResources::default()),
+            JobScheduler::Lsf => or_default(Lsf::inj_ref(self).total().await),
         }
     }
 
@@ -58,9 +58,25 @@ impl SchedulerStat for BootLoader {
         match self.job_scheduler {
             JobScheduler::Pbs => Pbs::inj_ref(self).used().await,
             JobScheduler::Slurm => Slurm::inj_ref(self).used().await,
-            JobScheduler::Lsf => Ok(SchedulerUsedResources::default()),
+            JobScheduler::Lsf => or_default(Lsf::inj_ref(self).used().await),
         }
     }
+
+    async fn inventory(&self) -> anyhow::Result<Inventory> {
+        match self.job_scheduler {
+            JobScheduler::Pbs => Pbs::inj_ref(self).inventory().await,
+            JobScheduler::Slurm => Slurm::inj_ref(self).inventory().await,
+            JobScheduler::Lsf => Lsf::inj_ref(self).inventory().await,
+        }
+    }
+}
+
+/// `bhosts` and `lshosts` may be unavailable to the user, which shouldn't prevent logging in
+fn or_default<T: Default>(resources: anyhow::Result<T>) -> anyhow::Result<T> {
+    Ok(resources.unwrap_or_else(|e| {
+        println!("Cannot count the resources of LSF, reporting none: {e:#}");
+        T::default()
+    }))
 }
 
 #[async_trait::async_trait]
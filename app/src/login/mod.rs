@@ -1,34 +1,114 @@
 mod counter;
-mod grant;
+pub mod grant;
 mod ioc;
 
-use anyhow::bail;
+use anyhow::{bail, Context};
 use reqwest::header::AUTHORIZATION;
 use reqwest::Client;
+use reqwest_middleware::ClientBuilder;
+use url::Url;
 
 use self::grant::{poll_grant, PollError};
 use self::ioc::BootLoader;
 use crate::config::AgentConfig;
 use crate::infrastructure::http::authorization::Bearer;
 use crate::infrastructure::service::keycloak::LoginInfo;
-use crate::infrastructure::service::keycloak::{self, GrantInfo};
+use crate::infrastructure::service::keycloak::{self, GrantInfo, OFFLINE_ACCESS};
 use crate::infrastructure::service::resource_stat::ResourceStat;
+use crate::infrastructure::service::token_store::TokenStore;
 use crate::login::counter::{counter, recover_cursor};
 
 /// Login,
 /// initialize `TOKEN` in `crate::token`,
 /// print the fetched agent ID.
 ///
+/// Use the client credentials grant if the client secret is configured,
+/// otherwise reuse the saved refresh token, and run the device flow only when both are unavailable.
+///
 /// Return error when login fails.
 pub async fn go(agent_config: &AgentConfig) -> anyhow::Result<GrantInfo> {
     let client_id = &agent_config.client_id;
     let resouce_stat = BootLoader::new(agent_config)?;
     let client = Client::new();
+    let token_url = agent_config.oidc_server.join("token").unwrap();
+    let store = TokenStore::new(agent_config);
+
+    let grant_info = if let Some(secret) = &agent_config.login.client_secret {
+        let client = ClientBuilder::new(client.clone()).build();
+        keycloak::client_credentials(&client, token_url, client_id, secret)
+            .await
+            .context("Client credentials grant failed")?
+    } else {
+        let grant_info = match reuse_refresh_token(&client, &store, token_url, client_id).await {
+            Some(info) => info,
+            None => device_flow(&client, agent_config).await?,
+        };
+        store.save(&grant_info.refresh_token).await.with_context(|| {
+            format!(
+                "Failed to save the refresh token at {}",
+                store.path().display()
+            )
+        })?;
+        grant_info
+    };
 
+    // Register agent itself with resources in computing orchestration system
+    let bearer = Bearer::new(&grant_info.access_token);
+    let reg_url = agent_config.server.join("agent/Register").unwrap();
+    let status = client
+        .post(reg_url)
+        .header(AUTHORIZATION, bearer.as_str())
+        .json(&resouce_stat.total().await?)
+        .send()
+        .await?
+        .status();
+    if !status.is_success() {
+        bail!("failed to register in computing orchestration system: response status={status}");
+    }
+
+    let agent_id = bearer.payload()?.sub;
+    println!("Your agent ID: {agent_id}");
+
+    Ok(grant_info)
+}
+
+/// Refresh with the saved token, `None` if it's absent or unusable
+async fn reuse_refresh_token(
+    client: &Client,
+    store: &TokenStore,
+    token_url: Url,
+    client_id: &str,
+) -> Option<GrantInfo> {
+    let refresh_token = match store.load().await {
+        Ok(token) => token?,
+        Err(e) => {
+            println!("Cannot read the saved refresh token: {e}");
+            return None;
+        }
+    };
+
+    let client = ClientBuilder::new(client.clone()).build();
+    match keycloak::refresh_token(&client, token_url, client_id, &refresh_token).await {
+        Ok(info) => Some(info),
+        Err(e) => {
+            println!("The saved refresh token is unusable, login again: {e}");
+            if let Err(e) = store.remove().await {
+                println!("Cannot remove the saved refresh token: {e}");
+            }
+            None
+        }
+    }
+}
+
+/// The OAuth device flow, waiting for a person to approve the device
+async fn device_flow(client: &Client, agent_config: &AgentConfig) -> anyhow::Result<GrantInfo> {
+    let client_id = &agent_config.client_id;
+    let scope = agent_config.login.offline.then_some(OFFLINE_ACCESS);
     let data: LoginInfo = keycloak::login(
-        &client,
+        client,
         agent_config.oidc_server.join("auth/device").unwrap(),
         client_id,
+        scope,
     )
     .await?;
     println!("{data}");
@@ -43,7 +123,7 @@ pub async fn go(agent_config: &AgentConfig) -> anyhow::Result<GrantInfo> {
             }
             info = poll_grant(
                 keycloak::grant_request(
-                    &client,
+                    client,
                     agent_config.oidc_server.join("token").unwrap(),
                     client_id,
                     &data.device_code,
@@ -55,23 +135,6 @@ pub async fn go(agent_config: &AgentConfig) -> anyhow::Result<GrantInfo> {
     };
     recover_cursor()?;
 
-    // Register agent itself with resources in computing orchestration system
-    let bearer = Bearer::new(&grant_info.access_token);
-    let reg_url = agent_config.server.join("agent/Register").unwrap();
-    let status = client
-        .post(reg_url)
-        .header(AUTHORIZATION, bearer.as_str())
-        .json(&resouce_stat.total().await?)
-        .send()
-        .await?
-        .status();
-    if !status.is_success() {
-        bail!("failed to register in computing orchestration system: response status={status}");
-    }
-
-    let agent_id = bearer.payload()?.sub;
-    println!("Your agent ID: {agent_id}");
-
     Ok(grant_info)
 }
 
@@ -4,18 +4,29 @@ mod dto;
 mod infrastructure;
 mod login;
 
+use std::path::Path;
 use std::sync::Arc;
 use std::time::Duration;
 
 use alice_infrastructure::config::build_config;
 use anyhow::Context;
 use colored::Colorize;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::timeout;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
 
 use self::background_service::prelude::*;
 use self::config::AgentConfig;
 use self::infrastructure::http::authorization::JwtPayload;
 use self::infrastructure::ioc::Container;
-use self::infrastructure::service::keycloak::GrantInfo;
+use self::infrastructure::trace;
+
+/// File saving the jobs being watched when shutting down
+const JOBS_FILE: &str = ".jobs.json";
+/// File saving when the deployed software was last used
+const SOFTWARE_USES_FILE: &str = ".software-uses.json";
+/// How long to wait for the suspended transfers to save their progress
+const SUSPEND_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[tokio::main(worker_threads = 32)]
 async fn main() -> anyhow::Result<()> {
@@ -23,46 +34,120 @@ async fn main() -> anyhow::Result<()> {
     let agent_config: AgentConfig = config.try_deserialize()?;
 
     // Don't log before login because it will break the login interface
-    let GrantInfo {
-        access_token,
-        refresh_token,
-    } = login::go(&agent_config).await.with_context(|| "Login failed".red())?;
+    let grant_info = login::go(&agent_config).await.with_context(|| "Login failed".red())?;
+    let access_token = grant_info.access_token.clone();
 
     alice_infrastructure::telemetry::init_telemetry(&agent_config.common.telemetry)
         .with_context(|| "Failed to initialize logger".red())?;
+    trace::init_propagator();
 
     let container = Arc::new(
-        Container::new(&agent_config, &access_token, refresh_token)
+        Container::new(&agent_config, grant_info)
             .await
             .with_context(|| "Cannot build IOC container".red())?,
     );
 
-    let background_services = async {
+    let jobs_file = Path::new(&agent_config.save_path).join(JOBS_FILE);
+    if let Err(e) = container.load_jobs(&jobs_file).await {
+        tracing::error!("Failed to load jobs saved at last shutdown: {e}");
+    }
+    let software_uses_file = Path::new(&agent_config.save_path).join(SOFTWARE_USES_FILE);
+    if let Err(e) = container.load_software_uses(&software_uses_file).await {
+        tracing::error!("Failed to load software uses saved at last shutdown: {e}");
+    }
+
+    let shutdown = CancellationToken::new();
+    let tasks = TaskTracker::new();
+
+    let (mq_handle, background_services) = async {
         let topic = JwtPayload::from_token(&access_token)?.preferred_username;
-        let mq =
-            KafkaMessageQueue::new(container.clone(), &agent_config.common.mq, [topic]).await?;
+        let mq = KafkaMessageQueue::new(
+            container.clone(),
+            tasks.clone(),
+            &agent_config.common.mq,
+            [topic],
+        )
+        .await?;
+
+        let resource_reporter = ResourceReporter::new(
+            container.clone(),
+            agent_config.server.clone(),
+            agent_config.resource_report.clone(),
+            agent_config.deployers(),
+        );
 
-        let resource_reporter =
-            ResourceReporter::new(container.clone(), agent_config.server.clone());
+        let admin_api = if agent_config.admin.enable {
+            Some(AdminApi::bind(container.clone(), &agent_config).await?)
+        } else {
+            None
+        };
 
         let refresh_jobs_interval = Duration::from_secs(agent_config.refresh_jobs_interval.max(5));
 
-        let background_services = [
-            tokio::spawn(async move { mq.run().await }),
+        let shutdown = shutdown.clone();
+        let mq_handle = tokio::spawn(async move { mq.run(shutdown).await });
+        let mut background_services = vec![
             tokio::spawn(async move { resource_reporter.run().await }),
             tokio::spawn(refresh_jobs(container.clone(), refresh_jobs_interval)),
+            tokio::spawn(refresh_token(container.clone())),
         ];
+        if agent_config.workspace.enable {
+            let interval = Duration::from_secs(agent_config.workspace.interval.max(60));
+            background_services.push(tokio::spawn(clean_workspaces(container.clone(), interval)));
+        }
+        if agent_config.software_cache.enable {
+            let interval = Duration::from_secs(agent_config.software_cache.interval.max(60));
+            background_services.push(tokio::spawn(evict_software(container.clone(), interval)));
+        }
+        if agent_config.log_tail.enable {
+            let interval = Duration::from_secs(agent_config.log_tail.interval.max(1));
+            background_services.push(tokio::spawn(tail_logs(container.clone(), interval)));
+        }
+        if let Some(admin_api) = admin_api {
+            background_services.push(tokio::spawn(admin_api.run()));
+        }
         tracing::info!("COS Agent Started");
 
-        Result::<_, anyhow::Error>::Ok(background_services)
+        Result::<_, anyhow::Error>::Ok((mq_handle, background_services))
     }
     .await
     .with_context(|| "Cannot setup background services".red())?;
 
-    tokio::signal::ctrl_c().await.unwrap();
-    tracing::info!("Stoping Services (ctrl-c handling).");
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => tracing::info!("Stopping services (SIGINT)."),
+        _ = terminate.recv() => tracing::info!("Stopping services (SIGTERM)."),
+    }
+
+    // Stop consuming new commands
+    shutdown.cancel();
+    let _ = mq_handle.await;
+    tasks.close();
+
+    let drain_timeout = Duration::from_secs(agent_config.shutdown_timeout);
+    if timeout(drain_timeout, tasks.wait()).await.is_err() {
+        tracing::info!(
+            "{} tasks are still running, suspending file transmissions",
+            tasks.len()
+        );
+        container.suspend_transfers();
+        if timeout(SUSPEND_TIMEOUT, tasks.wait()).await.is_err() {
+            tracing::warn!("{} tasks are left unfinished", tasks.len());
+        }
+    }
+
     for handle in background_services {
         handle.abort();
     }
+    container
+        .save_jobs(&jobs_file)
+        .await
+        .with_context(|| "Failed to save jobs".red())?;
+    container
+        .save_software_uses(&software_uses_file)
+        .await
+        .with_context(|| "Failed to save software uses".red())?;
+
+    tracing::info!("Services stopped.");
     Ok(())
 }
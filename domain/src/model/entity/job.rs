@@ -1,6 +1,6 @@
 use std::sync::Arc;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone)]
 pub struct Job {
@@ -13,18 +13,36 @@ pub struct Job {
     pub resource_used: JobResources,
 }
 
-#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum JobState {
     Queuing,
     Running,
     Suspended,
     Completing,
     Completed,
-    Failed,
+    Failed(FailureReason),
     #[default]
     Unknown,
 }
 
+/// 作业失败原因
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize, strum::Display)]
+pub enum FailureReason {
+    /// 程序自身出错
+    #[default]
+    #[strum(serialize = "application error")]
+    Application,
+    /// 节点故障或启动失败
+    #[strum(serialize = "node failure")]
+    NodeFail,
+    /// 超出墙钟时间限制
+    #[strum(serialize = "timeout")]
+    Timeout,
+    /// 内存不足
+    #[strum(serialize = "out of memory")]
+    OutOfMemory,
+}
+
 /// 作业使用的资源
 #[derive(Debug, Clone, Default, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -5,6 +5,9 @@ pub struct SoftwareInstallOptions {
     pub parameters: Vec<String>,
     pub version: String,
     pub name: String,
+    /// 部署器中的标识，如 spack 哈希、镜像路径
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
 }
 
 impl PartialEq for SoftwareInstallOptions {
@@ -1,7 +1,7 @@
 use serde::Deserialize;
 use uuid::Uuid;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct CollectOutput {
     /// 从哪收集
@@ -15,7 +15,7 @@ pub struct CollectOutput {
 }
 
 /// 从哪里收集
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type")]
 pub enum CollectFrom {
     /// 收集文件输出
@@ -28,7 +28,7 @@ pub enum CollectFrom {
 }
 
 /// 结果输出形式
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type")]
 pub enum CollectTo {
     /// 输出为文件
@@ -40,7 +40,7 @@ pub enum CollectTo {
 }
 
 /// 收集规则
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type", content = "content")]
 pub enum CollectRule {
     /// 正则匹配
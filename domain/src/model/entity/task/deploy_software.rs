@@ -1,13 +1,13 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DeploySoftware {
     pub facility_kind: FacilityKind,
 }
 
 /// 软件环境技术
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type")]
 pub enum FacilityKind {
     /// spack
@@ -18,6 +18,15 @@ pub enum FacilityKind {
         /// 安装参数
         argument_list: Vec<String>,
     },
+    /// spack 环境，固定完整的软件栈
+    #[serde(rename_all = "camelCase")]
+    SpackEnv {
+        /// `spack.yaml` 或 `spack.lock` 的内容
+        manifest: String,
+        /// 内容是否为 `spack.lock`
+        #[serde(default)]
+        lock: bool,
+    },
     /// singularity
     #[serde(rename_all = "camelCase")]
     Singularity {
@@ -25,11 +34,122 @@ pub enum FacilityKind {
         image: String,
         /// 镜像 tag
         tag: String,
+        /// 镜像来源，默认从 Docker 仓库拉取
+        #[serde(default)]
+        source: ImageSource,
+        /// 内容摘要，如 `sha256:...`，指定时按摘要固定镜像
+        #[serde(default)]
+        digest: Option<String>,
+    },
+    /// conda、mamba 或 micromamba
+    #[serde(rename_all = "camelCase")]
+    Conda {
+        /// 包规格，如 `samtools=1.17`
+        packages: Vec<String>,
+        /// 频道，如 `conda-forge`、`bioconda`
+        #[serde(default)]
+        channels: Vec<String>,
+    },
+}
+
+/// 镜像来源
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ImageSource {
+    /// `docker://`
+    #[default]
+    Docker,
+    /// `oras://`
+    Oras,
+    /// `library://`
+    Library,
+    /// 根据定义文件构建
+    Definition {
+        /// 定义文件内容
+        content: String,
     },
+    /// 集群上 `docker save` 导出的归档
+    DockerArchive {
+        /// 归档路径
+        path: String,
+    },
+    /// 集群上的 OCI 归档
+    OciArchive {
+        /// 归档路径
+        path: String,
+    },
+}
+
+impl FacilityKind {
+    /// 部署所用的部署器，以及传给它的软件名与参数
+    pub fn into_deployment(self) -> (DeployerType, String, Vec<String>) {
+        match self {
+            Self::Spack {
+                name,
+                argument_list,
+            } => (DeployerType::Spack, name, argument_list),
+            Self::SpackEnv { manifest, lock } => (
+                DeployerType::SpackEnv,
+                manifest,
+                lock.then(|| "lock".to_string()).into_iter().collect(),
+            ),
+            Self::Singularity {
+                image,
+                tag,
+                source,
+                digest,
+            } => (
+                DeployerType::Apptainer,
+                image,
+                source.parameters(tag, digest),
+            ),
+            Self::Conda { packages, channels } => {
+                (DeployerType::Conda, packages.join(" "), channels)
+            }
+        }
+    }
+}
+
+impl ImageSource {
+    /// 传给部署器的参数：首个为 tag，其后为 `键=值` 形式的来源与摘要
+    pub fn parameters(&self, tag: String, digest: Option<String>) -> Vec<String> {
+        let mut parameters = vec![tag];
+        match self {
+            Self::Docker => {}
+            Self::Oras => parameters.push("source=oras".to_string()),
+            Self::Library => parameters.push("source=library".to_string()),
+            Self::Definition { content } => {
+                parameters.push("source=definition".to_string());
+                parameters.push(format!("definition={content}"));
+            }
+            Self::DockerArchive { path } => {
+                parameters.push("source=docker-archive".to_string());
+                parameters.push(format!("path={path}"));
+            }
+            Self::OciArchive { path } => {
+                parameters.push("source=oci-archive".to_string());
+                parameters.push(format!("path={path}"));
+            }
+        }
+        if let Some(digest) = digest {
+            parameters.push(format!("digest={digest}"));
+        }
+        parameters
+    }
 }
 
-#[derive(Debug, enum_map::Enum)]
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, enum_map::Enum, strum::Display,
+)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
 pub enum DeployerType {
     Spack,
+    #[serde(rename = "spack-env")]
+    #[strum(serialize = "spack-env")]
+    SpackEnv,
     Apptainer,
+    Conda,
+    /// 站点通过 Environment Modules 或 Lmod 提供的软件
+    Module,
 }
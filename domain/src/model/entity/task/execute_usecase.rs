@@ -1,10 +1,10 @@
 use std::collections::HashMap;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use super::deploy_software::FacilityKind;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ExecuteUsecase {
     /// 执行名称
@@ -22,7 +22,7 @@ pub struct ExecuteUsecase {
     pub requirements: Option<Requirements>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum StdInKind {
     #[serde(rename_all = "camelCase")]
@@ -32,7 +32,7 @@ pub enum StdInKind {
 }
 
 /// 节点使用资源需求
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Requirements {
     /// 核心数
@@ -45,4 +45,6 @@ pub struct Requirements {
     pub max_cpu_time: Option<usize>,
     /// 定时终止 (utc 0 时区 时间戳)
     pub stop_time: Option<usize>,
+    /// 内存 (MiB)
+    pub memory: Option<usize>,
 }
@@ -16,7 +16,7 @@ pub use self::{
 use serde::Serialize;
 use uuid::Uuid;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Task<T> {
     pub id: Uuid,
     pub node_id: Uuid,
@@ -1,7 +1,7 @@
 use serde::Deserialize;
 use uuid::Uuid;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UploadFile {
     pub file_id: Uuid,
@@ -16,7 +16,7 @@ pub struct UploadFile {
 }
 
 /// 输出校验器
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct OutValidator {
     /// 校验规则
@@ -28,7 +28,7 @@ pub struct OutValidator {
 }
 
 /// 校验规则
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 #[serde(tag = "type", content = "content")]
 pub enum ValidateRule {
     /// 匹配正则
@@ -38,7 +38,7 @@ pub enum ValidateRule {
 }
 
 /// 验证过后的操作
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub enum ValidatedOperation {
     /// 报告成功
     ReportSuccess,
@@ -1,8 +1,12 @@
 use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
 
 use crate::model::entity::task::execute_usecase::{Requirements, StdInKind};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct ScriptInfo {
     pub id: String,
     pub parent_id: String,
@@ -13,4 +17,183 @@ pub struct ScriptInfo {
     pub environments: HashMap<String, String>,
     pub std_in: Option<StdInKind>,
     pub requirements: Option<Requirements>,
+    /// 在容器中执行时的包装方式
+    pub container: Option<ContainerExec>,
+}
+
+/// 在容器中执行作业命令的方式
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContainerExec {
+    /// 容器运行时，如 `apptainer`
+    pub runtime: String,
+    /// 镜像路径
+    pub image: String,
+    pub mode: ExecMode,
+    /// 绑定挂载，如 `/scratch` 或 `/data:/data:ro`
+    pub binds: Vec<String>,
+    /// 叠加层
+    pub overlays: Vec<String>,
+    /// 不继承宿主机的环境变量，作业的环境变量仍会传入
+    pub clean_env: bool,
+    pub gpu: Option<GpuFlag>,
+}
+
+/// 容器的执行方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecMode {
+    /// 在容器中执行作业命令
+    #[default]
+    Exec,
+    /// 执行镜像的 runscript，作业命令作为其参数
+    Run,
+}
+
+/// 容器的 GPU 支持
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GpuFlag {
+    /// NVIDIA，`--nv`
+    Nv,
+    /// AMD，`--rocm`
+    Rocm,
+}
+
+impl ContainerExec {
+    /// 用容器包装命令，在作业的工作目录 `workdir` 中执行。
+    ///
+    /// PBS 及启用 MPI 的 Slurm 作业由宿主机的 mpirun 启动包装后的命令，即混合模式；
+    /// LSF 作业的命令直接由 bsub 提交，不经 mpirun 启动。
+    pub fn wrap(
+        &self,
+        command: &str,
+        workdir: &str,
+        environments: &HashMap<String, String>,
+    ) -> String {
+        let mode = match self.mode {
+            ExecMode::Exec => "exec",
+            ExecMode::Run => "run",
+        };
+        let mut args = vec![self.runtime.clone(), mode.to_string()];
+        args.push(format!("--bind {workdir} --pwd {workdir}"));
+        args.extend(self.binds.iter().map(|bind| format!("--bind {bind}")));
+        args.extend(self.overlays.iter().map(|overlay| format!("--overlay {overlay}")));
+        match self.gpu {
+            Some(GpuFlag::Nv) => args.push("--nv".to_string()),
+            Some(GpuFlag::Rocm) => args.push("--rocm".to_string()),
+            None => {}
+        }
+        if self.clean_env {
+            args.push("--cleanenv".to_string());
+            // 环境变量已在脚本中导出
+            let mut keys: Vec<_> = environments.keys().collect();
+            keys.sort();
+            args.extend(keys.into_iter().map(|key| format!("--env {key}=\"${key}\"")));
+        }
+        args.push(self.image.clone());
+        args.push(command.to_string());
+        args.join(" ")
+    }
+}
+
+/// 排队中作业的等待情况
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QueueEstimate {
+    /// 调度器预计的开始时间
+    pub start_time: Option<String>,
+    /// 等待原因，如 Priority、Resources、QOSMaxJobsPerUser
+    pub reason: Option<String>,
+    /// 在同一分区或队列的排队作业中的位置，从 1 开始
+    pub position: Option<usize>,
+}
+
+impl QueueEstimate {
+    pub fn is_empty(&self) -> bool {
+        self.start_time.is_none() && self.reason.is_none() && self.position.is_none()
+    }
+}
+
+impl fmt::Display for QueueEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut parts = Vec::new();
+        if let Some(position) = self.position {
+            parts.push(format!("position {position} in queue"));
+        }
+        if let Some(start_time) = &self.start_time {
+            parts.push(format!("estimated to start at {start_time}"));
+        }
+        if let Some(reason) = &self.reason {
+            parts.push(format!("pending for {reason}"));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// 因基础设施原因失败的作业的重新提交策略
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct ResubmitPolicy {
+    /// 每个作业最多重新提交的次数
+    #[serde(default = "ResubmitPolicy::default_max_attempts")]
+    pub max_attempts: u32,
+    /// 节点故障时是否重新排队
+    #[serde(default = "ResubmitPolicy::default_node_fail")]
+    pub node_fail: bool,
+    /// 超时后墙钟时间的放大倍数，为空时不重新提交
+    #[serde(default = "ResubmitPolicy::default_scale")]
+    pub timeout_scale: Option<f64>,
+    /// 内存不足后内存的放大倍数，为空时不重新提交
+    #[serde(default = "ResubmitPolicy::default_scale")]
+    pub memory_scale: Option<f64>,
+}
+
+impl Default for ResubmitPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: Self::default_max_attempts(),
+            node_fail: Self::default_node_fail(),
+            timeout_scale: Self::default_scale(),
+            memory_scale: Self::default_scale(),
+        }
+    }
+}
+
+impl ResubmitPolicy {
+    pub fn default_max_attempts() -> u32 {
+        2
+    }
+
+    pub fn default_node_fail() -> bool {
+        true
+    }
+
+    pub fn default_scale() -> Option<f64> {
+        Some(1.5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{ContainerExec, GpuFlag};
+
+    #[test]
+    fn test_container_exec() {
+        let container = ContainerExec {
+            runtime: "apptainer".to_owned(),
+            image: "/images/app/1.0.sif".to_owned(),
+            binds: vec!["/data:/data:ro".to_owned()],
+            clean_env: true,
+            gpu: Some(GpuFlag::Nv),
+            ..Default::default()
+        };
+        let environments = HashMap::from([("OMP_NUM_THREADS".to_owned(), "4".to_owned())]);
+        assert_eq!(
+            container.wrap("app -i in.txt", "$SLURM_SUBMIT_DIR", &environments),
+            "apptainer exec --bind $SLURM_SUBMIT_DIR --pwd $SLURM_SUBMIT_DIR --bind /data:/data:ro \
+             --nv --cleanenv --env OMP_NUM_THREADS=\"$OMP_NUM_THREADS\" /images/app/1.0.sif \
+             app -i in.txt"
+        );
+    }
 }
@@ -1,5 +1,7 @@
+use std::collections::HashMap;
+
 use crate::model::entity::Job;
-use crate::model::vo::job::ScriptInfo;
+use crate::model::vo::job::{QueueEstimate, ScriptInfo};
 
 #[async_trait::async_trait]
 pub trait JobScheduler {
@@ -10,6 +12,15 @@ pub trait JobScheduler {
     async fn delete_job(&self, job_id: &str) -> anyhow::Result<()>;
     async fn pause_job(&self, job_id: &str) -> anyhow::Result<()>;
     async fn continue_job(&self, job_id: &str) -> anyhow::Result<()>;
+
+    /// 排队中作业的预计开始时间、等待原因和排队位置，以作业 id 为键，
+    /// 一次查询得出全部作业的，调度器不支持时为空
+    async fn queue_estimates(
+        &self,
+        _job_ids: &[String],
+    ) -> anyhow::Result<HashMap<String, QueueEstimate>> {
+        Ok(HashMap::new())
+    }
 }
 
 pub trait PbsJobScheduler: JobScheduler {}
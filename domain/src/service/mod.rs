@@ -11,7 +11,7 @@ pub use self::{
     file_load::FileLoadService,
     job_scheduler::JobScheduler,
     job_service::JobService,
-    software_deployer::{SoftwareDeployer, SelectSoftwareDeployer},
+    software_deployer::{SoftwareDeployer, SelectSoftwareDeployer, DeployProgress, DeployProcess, ProcessSignal, DeployLogStore},
     task_entity::TaskEntity,
     task_service::TaskService,
     task_status_reporter::{TaskStatusReporter, JobResourcesReporter},
@@ -1,16 +1,89 @@
-use crate::model::entity::{task::deploy_software::DeployerType, SoftwareInstallOptions};
+use std::sync::Arc;
+
+use uuid::Uuid;
+
+use crate::model::{
+    entity::{task::deploy_software::DeployerType, SoftwareInstallOptions},
+    vo::job::ContainerExec,
+};
 
 #[async_trait::async_trait]
 pub trait SoftwareDeployer {
     async fn install(&self, name: &str, parameters: Vec<String>) -> anyhow::Result<String>;
+    /// 安装并报告过程中的输出与进度，默认只在结束时返回
+    async fn install_with_progress(
+        &self,
+        name: &str,
+        parameters: Vec<String>,
+        _progress: &dyn DeployProgress,
+    ) -> anyhow::Result<String> {
+        self.install(name, parameters).await
+    }
     async fn uninstall(&self, hash: &str) -> anyhow::Result<()>;
+    /// 清理取消的安装留下的部分文件
+    async fn clean_up(&self, _name: &str, _parameters: Vec<String>) -> anyhow::Result<()> {
+        Ok(())
+    }
     async fn load_installed(&self) -> anyhow::Result<Vec<SoftwareInstallOptions>>;
+    /// 已安装的软件所依赖的其他部署器的软件，不能被淘汰
+    async fn dependencies(&self) -> anyhow::Result<Vec<(DeployerType, String)>> {
+        Ok(vec![])
+    }
     async fn find_installed_hash(
         &self,
         name: &str,
         parameters: &[String],
     ) -> anyhow::Result<Option<String>>;
     fn gen_load_script(&self, hash: &str) -> String;
+    /// 部署相同软件的任务共享同一部署的键，默认为名称与原顺序的参数
+    fn deployment_key(&self, name: &str, parameters: &[String]) -> String {
+        let mut key = name.trim().to_owned();
+        for parameter in parameters {
+            key.push('\0');
+            key.push_str(parameter);
+        }
+        key
+    }
+    /// 容器类软件在容器中执行作业命令，而非生成加载脚本
+    fn container_exec(&self, _hash: &str) -> Option<ContainerExec> {
+        None
+    }
+}
+
+/// 接收部署过程的输出
+pub trait DeployProgress: Send + Sync {
+    /// 进度，如已安装的包数、下载的百分比
+    fn progress(&self, message: String);
+    /// 部署器输出的一行
+    fn log(&self, line: &str);
+    /// 部署器启动了新的进程组，之后的暂停、继续与取消作用于它
+    fn process(&self, process: Arc<dyn DeployProcess>);
+}
+
+/// 部署器启动的进程组，可能在远程
+#[async_trait::async_trait]
+pub trait DeployProcess: Send + Sync {
+    async fn signal(&self, signal: ProcessSignal) -> anyhow::Result<()>;
+    /// 进程组中是否还有进程
+    async fn is_alive(&self) -> bool;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessSignal {
+    /// SIGSTOP
+    Stop,
+    /// SIGCONT
+    Continue,
+    /// SIGTERM
+    Terminate,
+    /// SIGKILL
+    Kill,
+}
+
+#[async_trait::async_trait]
+pub trait DeployLogStore {
+    /// 保存部署失败的任务的完整日志并发送给后端，返回在哪里可以查看
+    async fn save_deploy_log(&self, id: Uuid, log: &str) -> anyhow::Result<String>;
 }
 
 pub trait SelectSoftwareDeployer {
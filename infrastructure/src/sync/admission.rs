@@ -0,0 +1,188 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::oneshot;
+
+/// A semaphore whose waiters are admitted by priority,
+/// and in FIFO order among the same priority.
+#[derive(Debug, Clone)]
+pub struct AdmissionQueue(Arc<Mutex<State>>);
+
+/// Give the permit back to the queue when dropped.
+#[derive(Debug)]
+pub struct AdmissionPermit(Arc<Mutex<State>>);
+
+#[derive(Debug)]
+struct State {
+    permits: usize,
+    seq: u64,
+    waiters: BinaryHeap<Waiter>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    priority: i32,
+    seq: u64,
+    tx: oneshot::Sender<()>,
+}
+
+/// Release the permit if the waiting future is dropped after being admitted.
+struct WaitGuard<'a> {
+    rx: Option<oneshot::Receiver<()>>,
+    state: &'a Arc<Mutex<State>>,
+}
+
+impl AdmissionQueue {
+    pub fn new(permits: usize) -> Self {
+        Self(Arc::new(Mutex::new(State {
+            permits,
+            seq: 0,
+            waiters: BinaryHeap::new(),
+        })))
+    }
+
+    /// Take a permit without waiting.
+    /// Fail if there is no available permit or someone is already waiting.
+    pub fn try_acquire(&self) -> Option<AdmissionPermit> {
+        let mut state = self.0.lock().unwrap();
+        if state.permits > 0 && state.waiters.is_empty() {
+            state.permits -= 1;
+            Some(AdmissionPermit(self.0.clone()))
+        } else {
+            None
+        }
+    }
+
+    /// Wait for a permit. The greater `priority` is, the earlier it's admitted.
+    pub async fn acquire(&self, priority: i32) -> AdmissionPermit {
+        let rx = {
+            let mut state = self.0.lock().unwrap();
+            if state.permits > 0 && state.waiters.is_empty() {
+                state.permits -= 1;
+                return AdmissionPermit(self.0.clone());
+            }
+
+            let (tx, rx) = oneshot::channel();
+            state.seq += 1;
+            let seq = state.seq;
+            state.waiters.push(Waiter { priority, seq, tx });
+            rx
+        };
+
+        let mut guard = WaitGuard {
+            rx: Some(rx),
+            state: &self.0,
+        };
+        // The sender is only dropped after sending.
+        let _ = guard.rx.as_mut().unwrap().await;
+        guard.rx = None;
+
+        AdmissionPermit(self.0.clone())
+    }
+
+    /// Count of tasks waiting for admission
+    pub fn waiting(&self) -> usize {
+        self.0.lock().unwrap().waiters.len()
+    }
+}
+
+fn release(state: &Mutex<State>) {
+    let mut state = state.lock().unwrap();
+    while let Some(waiter) = state.waiters.pop() {
+        // Skip the waiters which have gone
+        if waiter.tx.send(()).is_ok() {
+            return;
+        }
+    }
+    state.permits += 1;
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        release(&self.0);
+    }
+}
+
+impl Drop for WaitGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(mut rx) = self.rx.take() {
+            rx.close();
+            if rx.try_recv().is_ok() {
+                release(self.state);
+            }
+        }
+    }
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority).then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use super::AdmissionQueue;
+
+    #[tokio::test]
+    async fn test_priority() {
+        let queue = AdmissionQueue::new(1);
+        let first = queue.try_acquire().unwrap();
+        assert!(queue.try_acquire().is_none());
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for (name, priority) in [("low", 0), ("high", 10), ("low2", 0)] {
+            let queue = queue.clone();
+            let order = order.clone();
+            handles.push(tokio::spawn(async move {
+                let _permit = queue.acquire(priority).await;
+                order.lock().unwrap().push(name);
+            }));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(queue.waiting(), 3);
+
+        drop(first);
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), ["high", "low", "low2"]);
+        assert!(queue.try_acquire().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_waiter() {
+        let queue = AdmissionQueue::new(1);
+        let first = queue.try_acquire().unwrap();
+
+        let waiting = {
+            let queue = queue.clone();
+            tokio::spawn(async move { queue.acquire(0).await })
+        };
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        waiting.abort();
+        let _ = waiting.await;
+
+        drop(first);
+        assert!(queue.try_acquire().is_some());
+    }
+}
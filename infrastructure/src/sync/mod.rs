@@ -1,4 +1,8 @@
+mod admission;
 mod pause;
+mod rate_limit;
 pub mod timer;
 
+pub use self::admission::{AdmissionPermit, AdmissionQueue};
 pub use self::pause::{PausableFuture, PauseToken};
+pub use self::rate_limit::RateLimiter;
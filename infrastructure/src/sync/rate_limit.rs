@@ -0,0 +1,53 @@
+use std::sync::Mutex;
+use std::time::Duration;
+
+use tokio::time::{sleep, Instant};
+
+/// A token bucket which allows bursts of at most one second.
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// tokens per second
+    rate: f64,
+    state: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    /// It's negative when the bucket is in debt
+    available: f64,
+    last: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(rate: u64) -> Self {
+        let rate = rate.max(1) as f64;
+        Self {
+            rate,
+            state: Mutex::new(Bucket {
+                available: rate,
+                last: Instant::now(),
+            }),
+        }
+    }
+
+    /// Take `amount` tokens, waiting until the debt is paid off.
+    pub async fn acquire(&self, amount: u64) {
+        let wait = {
+            let mut bucket = self.state.lock().unwrap();
+            let now = Instant::now();
+            let refilled = now.duration_since(bucket.last).as_secs_f64() * self.rate;
+            bucket.available = (bucket.available + refilled).min(self.rate) - amount as f64;
+            bucket.last = now;
+
+            if bucket.available < 0. {
+                Duration::from_secs_f64(-bucket.available / self.rate)
+            } else {
+                Duration::ZERO
+            }
+        };
+
+        if !wait.is_zero() {
+            sleep(wait).await;
+        }
+    }
+}
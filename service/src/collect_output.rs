@@ -38,13 +38,8 @@ where
         tokio::select! {
             res = self.run(task) => {
                 self.cancel_map.lock().await.remove(&task_id);
-                let status = if let Err(e) = res {
-                    tracing::error!(%task_id, "Collect output: {e}");
-                    TaskStatus::Failed
-                } else {
-                    TaskStatus::Completed
-                };
-                self.prj_ref().report(task_id, status).await?;
+                res.context("Collect output")?;
+                self.prj_ref().report(task_id, TaskStatus::Completed).await?;
             },
             _ = cancel_token.cancelled() => (),
         }
@@ -1,37 +1,120 @@
 use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::Context;
 use dep_inj::DepInj;
 use domain::{
     model::entity::task::{deploy_software::*, Task, TaskStatus},
-    service::{SelectSoftwareDeployer, TaskService, TaskStatusReporter},
+    service::{
+        DeployLogStore, DeployProcess, DeployProgress, ProcessSignal, SelectSoftwareDeployer,
+        SoftwareDeployer, TaskService, TaskStatusReporter,
+    },
 };
-use tokio::sync::{Mutex, Semaphore};
+use tokio::sync::{mpsc, watch, Mutex};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
-#[derive(DepInj)]
+use crate::job::JobServiceState;
+
+/// Min interval between two progress messages of a deployment
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+/// Lines at the end of the log put into the failure message
+const LOG_TAIL_LINES: usize = 20;
+/// Time for the processes of a cancelled deployment to exit after SIGTERM
+const TERMINATE_GRACE: Duration = Duration::from_secs(10);
+
+#[derive(Default, DepInj)]
 #[target(DeploySoftwareService)]
 pub struct DeploySoftwareState {
-    start_guard: Semaphore,
-    cancel_map: Mutex<HashMap<Uuid, CancellationToken>>,
+    deployments: Mutex<HashMap<Uuid, Arc<Deployment>>>,
+    /// The deployments in progress, shared by the tasks deploying the same software
+    flights: std::sync::Mutex<HashMap<FlightKey, Flight>>,
+    spack: bool,
+    /// Deploy Spack software from site modules
+    modules: bool,
+    /// Ask the admin to install the missing modules instead of installing them with Spack
+    module_hook: bool,
 }
 
 impl DeploySoftwareState {
-    pub fn new(permits: usize) -> Self {
+    pub fn new(spack: bool, modules: bool, module_hook: bool) -> Self {
         Self {
-            start_guard: Semaphore::new(permits),
-            cancel_map: Mutex::default(),
+            spack,
+            modules,
+            module_hook,
+            ..Default::default()
         }
     }
 }
 
+/// Controls a running deployment
+#[derive(Default)]
+struct Deployment {
+    cancel: CancellationToken,
+    /// The process group started last by the deployer
+    process: std::sync::Mutex<Option<Arc<dyn DeployProcess>>>,
+    paused: AtomicBool,
+    /// Whether the deployer started installing, leaving files to clean up if cancelled
+    installing: AtomicBool,
+    /// The tasks waiting for this deployment, also reported the progress
+    followers: std::sync::Mutex<Vec<Uuid>>,
+}
+
+impl Deployment {
+    fn process(&self) -> Option<Arc<dyn DeployProcess>> {
+        self.process.lock().unwrap().clone()
+    }
+}
+
+/// The deployer and its key of the software, see `SoftwareDeployer::deployment_key`
+type FlightKey = (DeployerType, String);
+/// The result of a deployment passed to the waiting tasks
+type FlightResult = Result<(), String>;
+
+/// A deployment led by the first task deploying the software
+#[derive(Clone)]
+struct Flight {
+    leader: Uuid,
+    deployment: Arc<Deployment>,
+    /// `None` until the deployment ends, closed without a result if it's cancelled
+    result: watch::Receiver<Option<FlightResult>>,
+}
+
+enum Joined {
+    Lead(watch::Sender<Option<FlightResult>>),
+    Follow(Flight),
+}
+
+enum Followed {
+    Ended(FlightResult),
+    Cancelled,
+    /// The leader is cancelled, leaving the deployment to another task
+    LeaderGone,
+}
+
+/// Ends the flight when the leader returns, including when cancelled
+struct FlightGuard<'a> {
+    flights: &'a std::sync::Mutex<HashMap<FlightKey, Flight>>,
+    key: FlightKey,
+}
+
+impl Drop for FlightGuard<'_> {
+    fn drop(&mut self) {
+        self.flights.lock().unwrap().remove(&self.key);
+    }
+}
+
 #[async_trait::async_trait]
 impl<Deps> TaskService for DeploySoftwareService<Deps>
 where
     Deps: AsRef<DeploySoftwareState>
+        + AsRef<JobServiceState>
         + TaskStatusReporter<DeploySoftware>
         + SelectSoftwareDeployer
+        + DeployLogStore
         + Send
         + Sync,
 {
@@ -39,82 +122,319 @@ where
 
     async fn start(&self, task: Task<Self::Body>) -> anyhow::Result<()> {
         let id = task.id;
-        self.prj_ref().report(id, TaskStatus::Queued).await?;
+        self.prj_ref().report(id, TaskStatus::Started).await?;
 
-        let cancel_token = CancellationToken::new();
-        self.cancel_map.lock().await.insert(id, cancel_token.clone());
+        let deployment = Arc::<Deployment>::default();
+        self.deployments.lock().await.insert(id, deployment.clone());
 
-        tokio::select! {
-            res = self.run(task) => {
-                self.cancel_map.lock().await.remove(&id);
-                if let Err(e) = res {
-                    tracing::error!(task_id = %id, "Deploy software: {e}");
-                    self.prj_ref().report_msg(id, TaskStatus::Failed, &e.to_string()).await?;
-
-                } else {
-                    self.prj_ref().report(id, TaskStatus::Completed).await?;
+        let (r#type, name, parameters) = task.body.facility_kind.into_deployment();
+        let r#type = self.route(r#type, &name, &parameters).await;
+        let deployer = self.prj_ref().select(r#type);
+        let key = (r#type, deployer.deployment_key(&name, &parameters));
+        // Only the first task deploys the software, the others share its result
+        let sender = loop {
+            let flight = match self.join(&key, id, &deployment) {
+                Joined::Lead(sender) => break sender,
+                Joined::Follow(flight) => flight,
+            };
+            let followed = self.follow(id, &flight, &deployment).await;
+            flight.deployment.followers.lock().unwrap().retain(|&follower| follower != id);
+            let result = match followed {
+                Followed::Ended(result) => result,
+                Followed::Cancelled => {
+                    self.deployments.lock().await.remove(&id);
+                    return self.prj_ref().report(id, TaskStatus::Cancelled).await;
                 }
-            },
-            _ = cancel_token.cancelled() => (),
-        }
+                Followed::LeaderGone => continue,
+            };
+            self.deployments.lock().await.remove(&id);
+            result.map_err(anyhow::Error::msg).context("Deploy software")?;
+            self.report_completed(id).await;
+            return Ok(());
+        };
+        // Dropped before the sender, so that the followers find the flight ended
+        let _flight = FlightGuard {
+            flights: &self.flights,
+            key,
+        };
 
+        let mut run = Box::pin(self.run(id, r#type, &name, parameters.clone(), &deployment));
+        let result = tokio::select! {
+            result = &mut run => Some(result),
+            _ = deployment.cancel.cancelled() => None,
+        };
+        let Some(result) = result else {
+            terminate(&deployment, run).await;
+            self.deployments.lock().await.remove(&id);
+            if deployment.installing.load(Ordering::SeqCst) {
+                if let Err(e) = deployer.clean_up(&name, parameters).await {
+                    tracing::warn!(task_id = %id, "Failed to clean up cancelled deployment: {e:#}");
+                }
+            }
+            return self.prj_ref().report(id, TaskStatus::Cancelled).await;
+        };
+
+        self.deployments.lock().await.remove(&id);
+        let _ = sender.send(Some(
+            result.as_ref().map(|_| ()).map_err(|e| format!("{e:#}")),
+        ));
+        result.context("Deploy software")?;
+        self.report_completed(id).await;
         Ok(())
     }
 
-    async fn pause(&self, _id: Uuid) -> anyhow::Result<()> {
-        Ok(())
+    async fn pause(&self, id: Uuid) -> anyhow::Result<()> {
+        let deployment = self.deployment(id).await?;
+        deployment.paused.store(true, Ordering::SeqCst);
+        if let Some(process) = deployment.process() {
+            process.signal(ProcessSignal::Stop).await?;
+        }
+        self.prj_ref().report(id, TaskStatus::Paused).await
     }
 
-    async fn resume(&self, _id: Uuid) -> anyhow::Result<()> {
-        Ok(())
+    async fn resume(&self, id: Uuid) -> anyhow::Result<()> {
+        let deployment = self.deployment(id).await?;
+        deployment.paused.store(false, Ordering::SeqCst);
+        if let Some(process) = deployment.process() {
+            process.signal(ProcessSignal::Continue).await?;
+        }
+        self.prj_ref().report(id, TaskStatus::Resumed).await
     }
 
+    /// The deployment is cancelled by `start`, after its processes are stopped and cleaned up
     async fn cancel(&self, id: Uuid) -> anyhow::Result<()> {
-        let cancel_token = self.cancel_map.lock().await.remove(&id).context("Task not found")?;
-        cancel_token.cancel();
-
-        self.prj_ref().report(id, TaskStatus::Cancelled).await
+        self.deployment(id).await?.cancel.cancel();
+        Ok(())
     }
 }
 
 impl<Deps> DeploySoftwareService<Deps>
 where
     Deps: AsRef<DeploySoftwareState>
+        + AsRef<JobServiceState>
         + TaskStatusReporter<DeploySoftware>
         + SelectSoftwareDeployer
+        + DeployLogStore
         + Send
         + Sync,
 {
-    async fn run(&self, task: Task<DeploySoftware>) -> anyhow::Result<()> {
-        // controling the numbers of running task via semaphore
-        let _running = self.start_guard.acquire().await?;
-        self.prj_ref().report(task.id, TaskStatus::Started).await?;
-
-        match task.body.facility_kind {
-            FacilityKind::Spack {
-                name,
-                argument_list,
-            } => {
-                let deployer = self.prj_ref().select(DeployerType::Spack);
-
-                if let Ok(Some(_)) = deployer.find_installed_hash(&name, &argument_list).await {
-                    return Ok(());
-                }
+    async fn deployment(&self, id: Uuid) -> anyhow::Result<Arc<Deployment>> {
+        self.deployments.lock().await.get(&id).cloned().context("Task not found")
+    }
 
-                deployer.install(&name, argument_list).await?;
-            }
-            FacilityKind::Singularity { image, tag } => {
-                let deployer = self.prj_ref().select(DeployerType::Apptainer);
+    /// The software is deployed, so failing to report it mustn't retry the deployment
+    async fn report_completed(&self, id: Uuid) {
+        if let Err(e) = self.prj_ref().report(id, TaskStatus::Completed).await {
+            tracing::error!(task_id = %id, "Failed to report the deployed software: {e}");
+        }
+    }
 
-                let tag = vec![tag];
-                if let Ok(Some(_)) = deployer.find_installed_hash(&image, &tag).await {
-                    return Ok(());
+    /// The deployer of the software, as site modules take the place of Spack packages.
+    ///
+    /// Spack packages already installed are preferred as for jobs, then the site modules.
+    /// The missing ones are installed by the module hook if any, otherwise by Spack.
+    async fn route(&self, r#type: DeployerType, name: &str, parameters: &[String]) -> DeployerType {
+        if r#type != DeployerType::Spack || !self.modules {
+            return r#type;
+        }
+        let installed = |r#type| async move {
+            let deployer = self.prj_ref().select(r#type);
+            match deployer.find_installed_hash(name, parameters).await {
+                Ok(hash) => hash.is_some(),
+                Err(e) => {
+                    tracing::warn!(%r#type, "Failed to find installed software: {e:#}");
+                    false
                 }
-
-                deployer.install(&image, tag).await?;
             }
         };
 
+        if self.spack && installed(DeployerType::Spack).await {
+            DeployerType::Spack
+        } else if installed(DeployerType::Module).await || self.module_hook || !self.spack {
+            DeployerType::Module
+        } else {
+            DeployerType::Spack
+        }
+    }
+
+    /// Lead the deployment of the software, or follow the task deploying it
+    fn join(&self, key: &FlightKey, id: Uuid, deployment: &Arc<Deployment>) -> Joined {
+        let mut flights = self.flights.lock().unwrap();
+        if let Some(flight) = flights.get(key) {
+            flight.deployment.followers.lock().unwrap().push(id);
+            return Joined::Follow(flight.clone());
+        }
+
+        let (sender, result) = watch::channel(None);
+        let flight = Flight {
+            leader: id,
+            deployment: deployment.clone(),
+            result,
+        };
+        flights.insert(key.clone(), flight);
+        Joined::Lead(sender)
+    }
+
+    async fn follow(&self, id: Uuid, flight: &Flight, deployment: &Deployment) -> Followed {
+        let message = format!(
+            "Waiting for task {} deploying the same software",
+            flight.leader
+        );
+        if let Err(e) = self.prj_ref().report_msg(id, TaskStatus::Started, &message).await {
+            tracing::warn!("Failed to report deploy progress: {e}");
+        }
+
+        let mut result = flight.result.clone();
+        tokio::select! {
+            ended = result.wait_for(Option::is_some) => match ended {
+                Ok(ended) => Followed::Ended(ended.clone().unwrap()),
+                Err(_) => Followed::LeaderGone,
+            },
+            _ = deployment.cancel.cancelled() => Followed::Cancelled,
+        }
+    }
+
+    async fn run(
+        &self,
+        id: Uuid,
+        r#type: DeployerType,
+        name: &str,
+        parameters: Vec<String>,
+        deployment: &Deployment,
+    ) -> anyhow::Result<()> {
+        let deployer = self.prj_ref().select(r#type);
+        if let Ok(Some(hash)) = deployer.find_installed_hash(name, &parameters).await {
+            self.record_use(r#type, hash);
+            return Ok(());
+        }
+
+        self.install(id, deployer, name, parameters.clone(), deployment).await?;
+        // Newly installed software is not evicted before being used
+        match deployer.find_installed_hash(name, &parameters).await {
+            Ok(Some(hash)) => self.record_use(r#type, hash),
+            Ok(None) => tracing::warn!(task_id = %id, "Installed software not found"),
+            Err(e) => tracing::warn!(task_id = %id, "Failed to find installed software: {e}"),
+        }
         Ok(())
     }
+
+    fn record_use(&self, r#type: DeployerType, hash: String) {
+        AsRef::<JobServiceState>::as_ref(self.prj_ref()).record_use(r#type, hash);
+    }
+
+    /// Install the software, reporting the progress at intervals.
+    ///
+    /// The full log is saved if it fails, with the end of it appended to the error.
+    async fn install(
+        &self,
+        id: Uuid,
+        deployer: &(dyn SoftwareDeployer + Send + Sync),
+        name: &str,
+        parameters: Vec<String>,
+        deployment: &Deployment,
+    ) -> anyhow::Result<()> {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let progress = Progress {
+            sender,
+            log: std::sync::Mutex::default(),
+            deployment,
+        };
+
+        deployment.installing.store(true, Ordering::SeqCst);
+        let install = async move {
+            let result = deployer.install_with_progress(name, parameters, &progress).await;
+            // Dropping the sender ends the reporting
+            (result, progress.log.into_inner().unwrap())
+        };
+        let report = async {
+            let mut reported_at: Option<Instant> = None;
+            while let Some(message) = receiver.recv().await {
+                if reported_at.is_some_and(|at| at.elapsed() < PROGRESS_INTERVAL) {
+                    continue;
+                }
+                reported_at = Some(Instant::now());
+                let followers = deployment.followers.lock().unwrap().clone();
+                for id in std::iter::once(id).chain(followers) {
+                    let reported = self.prj_ref().report_msg(id, TaskStatus::Started, &message);
+                    if let Err(e) = reported.await {
+                        tracing::warn!("Failed to report deploy progress: {e}");
+                    }
+                }
+            }
+        };
+        let ((result, log), ()) = tokio::join!(install, report);
+
+        let Err(e) = result else {
+            return Ok(());
+        };
+        if log.is_empty() {
+            return Err(e);
+        }
+        let lines: Vec<&str> = log.lines().collect();
+        let tail = lines[lines.len().saturating_sub(LOG_TAIL_LINES)..].join("\n");
+        match self.prj_ref().save_deploy_log(id, &log).await {
+            Ok(location) => Err(anyhow::anyhow!("{e:#}\n{tail}\nThe full log is {location}")),
+            Err(save_error) => {
+                tracing::warn!("Failed to save deploy log: {save_error}");
+                Err(anyhow::anyhow!("{e:#}\n{tail}"))
+            }
+        }
+    }
+}
+
+/// Terminate the processes of the cancelled deployment, killing them if not exited in time
+async fn terminate(deployment: &Deployment, run: impl Future<Output = anyhow::Result<()>>) {
+    let Some(process) = deployment.process() else {
+        return;
+    };
+    // A stopped process handles SIGTERM once continued
+    for signal in [ProcessSignal::Terminate, ProcessSignal::Continue] {
+        if let Err(e) = process.signal(signal).await {
+            tracing::debug!("Failed to signal the deployment: {e:#}");
+        }
+    }
+    // The run ends with the processes, unless they ignore SIGTERM
+    let _ = tokio::time::timeout(TERMINATE_GRACE, run).await;
+
+    // Another process may be started before the run ends
+    let Some(process) = deployment.process() else {
+        return;
+    };
+    if process.is_alive().await {
+        if let Err(e) = process.signal(ProcessSignal::Kill).await {
+            tracing::warn!("Failed to kill the deployment: {e:#}");
+        }
+    }
+}
+
+/// Passes the progress to the reporting, and keeps the log
+struct Progress<'a> {
+    sender: mpsc::UnboundedSender<String>,
+    log: std::sync::Mutex<String>,
+    deployment: &'a Deployment,
+}
+
+impl DeployProgress for Progress<'_> {
+    fn progress(&self, message: String) {
+        let _ = self.sender.send(message);
+    }
+
+    fn log(&self, line: &str) {
+        let mut log = self.log.lock().unwrap();
+        log.push_str(line);
+        log.push('\n');
+    }
+
+    fn process(&self, process: Arc<dyn DeployProcess>) {
+        // Paused between two processes
+        if self.deployment.paused.load(Ordering::SeqCst) {
+            let process = process.clone();
+            tokio::spawn(async move {
+                if let Err(e) = process.signal(ProcessSignal::Stop).await {
+                    tracing::warn!("Failed to pause the deployment: {e:#}");
+                }
+            });
+        }
+        *self.deployment.process.lock().unwrap() = Some(process);
+    }
 }
@@ -1,4 +1,5 @@
-use std::time::Duration;
+use std::collections::HashSet;
+use std::time::{Duration, SystemTime};
 
 use anyhow::Context;
 use dashmap::DashMap;
@@ -6,38 +7,188 @@ use dep_inj::DepInj;
 use domain::{
     model::{
         entity::{
-            job::{Job, JobState},
+            job::{FailureReason, Job, JobState},
             task::{
                 deploy_software::{DeployerType, FacilityKind},
-                execute_usecase::ExecuteUsecase,
+                execute_usecase::{ExecuteUsecase, Requirements},
                 Task, TaskStatus,
             },
         },
-        vo::job::ScriptInfo,
+        vo::job::{QueueEstimate, ResubmitPolicy, ScriptInfo},
     },
     service::{
         JobResourcesReporter, JobScheduler, JobService, SelectSoftwareDeployer, TaskService,
     },
 };
 use tokio::time::sleep;
+use tracing::Instrument;
 use uuid::Uuid;
 
 #[derive(DepInj, Default)]
 #[target(JobServiceImpl)]
 pub struct JobServiceState {
     repo: DashMap<Uuid, Job>,
+    submissions: DashMap<Uuid, Submission>,
+    /// What's known of the jobs watched again after restarting without their scripts,
+    /// e.g. saved by older agents
+    restored: DashMap<Uuid, JobContext>,
+    /// The last reported estimates of queued jobs
+    estimates: DashMap<Uuid, QueueEstimate>,
+    /// When the software was last used by a job or installed
+    last_used: DashMap<(DeployerType, String), SystemTime>,
     spack: bool,
     apptainer: bool,
+    conda: bool,
+    /// Fall back to site modules for Spack software
+    modules: bool,
+    resubmit: ResubmitPolicy,
+}
+
+/// The script of a job, kept for resubmitting
+struct Submission {
+    info: ScriptInfo,
+    attempts: u32,
+    /// The deployer and hash of the software used by the job
+    software: Option<(DeployerType, String)>,
+}
+
+/// What's saved with a watched job, to watch it again after restarting
+#[derive(Debug, Clone, Default)]
+pub struct JobContext {
+    /// The node whose workspace the job runs in
+    pub node_id: Option<String>,
+    /// The deployer and hash of the software used by the job,
+    /// `None` if unknown, e.g. saved by older agents
+    pub software: Option<Option<(DeployerType, String)>>,
+    /// The script of the job and the times it was resubmitted, for resubmitting it again,
+    /// `None` if unknown
+    pub script: Option<(ScriptInfo, u32)>,
 }
 
 impl JobServiceState {
-    pub fn new(spack: bool, apptainer: bool) -> Self {
+    pub fn new(
+        spack: bool,
+        apptainer: bool,
+        conda: bool,
+        modules: bool,
+        resubmit: ResubmitPolicy,
+    ) -> Self {
         Self {
             spack,
             apptainer,
+            conda,
+            modules,
+            resubmit,
             ..Default::default()
         }
     }
+
+    /// Jobs being watched, with the ids of their tasks
+    pub fn jobs(&self) -> Vec<(Uuid, Job)> {
+        self.repo.iter().map(|entry| (*entry.key(), entry.value().clone())).collect()
+    }
+
+    /// The job submitted by the task
+    pub fn job(&self, task_id: Uuid) -> Option<Job> {
+        self.repo.get(&task_id).map(|job| job.clone())
+    }
+
+    /// Running jobs with the directories of their outputs.
+    ///
+    /// Jobs watched again after restarting without their scripts are left out,
+    /// as their directories are unknown.
+    pub fn running(&self) -> Vec<(Uuid, String)> {
+        self.repo
+            .iter()
+            .filter(|entry| entry.value().state == JobState::Running)
+            .filter_map(|entry| {
+                let submission = self.submissions.get(entry.key())?;
+                Some((*entry.key(), submission.info.parent_id.clone()))
+            })
+            .collect()
+    }
+
+    /// Software used by the watched jobs, which must not be removed.
+    ///
+    /// `None` if the software of some jobs watched again after restarting is unknown.
+    pub fn software_in_use(&self) -> Option<HashSet<(DeployerType, String)>> {
+        let mut in_use: HashSet<_> =
+            self.submissions.iter().filter_map(|entry| entry.software.clone()).collect();
+        for entry in self.restored.iter() {
+            in_use.extend(entry.software.clone()?);
+        }
+        Some(in_use)
+    }
+
+    /// When the software was last used by a job or installed, `None` if never recorded
+    pub fn last_used(&self, r#type: DeployerType, hash: &str) -> Option<SystemTime> {
+        self.last_used.get(&(r#type, hash.to_owned())).map(|time| *time)
+    }
+
+    /// Record that the software is used now
+    pub fn record_use(&self, r#type: DeployerType, hash: String) {
+        self.last_used.insert((r#type, hash), SystemTime::now());
+    }
+
+    /// All recorded uses of software, for saving
+    pub fn software_uses(&self) -> Vec<(DeployerType, String, SystemTime)> {
+        self.last_used
+            .iter()
+            .map(|entry| (entry.key().0, entry.key().1.clone(), *entry.value()))
+            .collect()
+    }
+
+    /// Restore the uses of software saved before, keeping the later ones recorded since
+    pub fn restore_software_uses(
+        &self,
+        uses: impl IntoIterator<Item = (DeployerType, String, SystemTime)>,
+    ) {
+        for (r#type, hash, time) in uses {
+            let mut last_used = self.last_used.entry((r#type, hash)).or_insert(time);
+            *last_used = (*last_used).max(time);
+        }
+    }
+
+    /// The nodes of the watched jobs, whose workspaces must not be removed
+    pub fn nodes(&self) -> HashSet<String> {
+        self.repo
+            .iter()
+            .filter_map(|entry| self.context(*entry.key()).node_id)
+            .collect()
+    }
+
+    /// What's saved with the job to watch it again
+    pub fn context(&self, task_id: Uuid) -> JobContext {
+        if let Some(submission) = self.submissions.get(&task_id) {
+            return JobContext {
+                node_id: Some(submission.info.parent_id.clone()),
+                software: Some(submission.software.clone()),
+                script: Some((submission.info.clone(), submission.attempts)),
+            };
+        }
+        self.restored.get(&task_id).map(|context| context.clone()).unwrap_or_default()
+    }
+
+    /// Watch the jobs again, e.g. those saved before the agent restarted
+    pub fn restore(&self, jobs: impl IntoIterator<Item = (Uuid, Job, JobContext)>) {
+        for (id, job, context) in jobs {
+            self.repo.insert(id, job);
+            if let (Some((info, attempts)), Some(software)) =
+                (context.script.clone(), context.software.clone())
+            {
+                self.submissions.insert(
+                    id,
+                    Submission {
+                        info,
+                        attempts,
+                        software,
+                    },
+                );
+            } else {
+                self.restored.insert(id, context);
+            }
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -63,6 +214,8 @@ where
         } = task.body;
 
         let mut load_software: String = "".to_string();
+        let mut container = None;
+        let mut software = None;
 
         match facility_kind {
             FacilityKind::Spack {
@@ -73,25 +226,63 @@ where
                     let deployer = self.prj_ref().select(DeployerType::Spack);
                     if let Some(hash) = deployer.find_installed_hash(&name, &argument_list).await? {
                         load_software = deployer.gen_load_script(&hash);
+                        software = Some((DeployerType::Spack, hash));
+                    }
+                }
+                if self.modules && load_software.is_empty() {
+                    let deployer = self.prj_ref().select(DeployerType::Module);
+                    if let Some(module) =
+                        deployer.find_installed_hash(&name, &argument_list).await?
+                    {
+                        load_software = deployer.gen_load_script(&module);
+                        software = Some((DeployerType::Module, module));
+                    }
+                }
+            }
+            FacilityKind::SpackEnv { manifest, lock } => {
+                if self.spack {
+                    let deployer = self.prj_ref().select(DeployerType::SpackEnv);
+                    let parameters: Vec<String> =
+                        lock.then(|| "lock".to_string()).into_iter().collect();
+                    if let Some(hash) = deployer.find_installed_hash(&manifest, &parameters).await?
+                    {
+                        load_software = deployer.gen_load_script(&hash);
+                        software = Some((DeployerType::SpackEnv, hash));
                     }
                 }
             }
-            FacilityKind::Singularity { image, tag } => {
+            FacilityKind::Singularity {
+                image,
+                tag,
+                source,
+                digest,
+            } => {
                 if self.apptainer {
                     let deployer = self.prj_ref().select(DeployerType::Apptainer);
-                    if let Some(hash) = deployer.find_installed_hash(&image, &[tag]).await? {
+                    let parameters = source.parameters(tag, digest);
+                    if let Some(hash) = deployer.find_installed_hash(&image, &parameters).await? {
+                        container = deployer.container_exec(&hash);
+                        software = Some((DeployerType::Apptainer, hash));
+                    }
+                }
+            }
+            FacilityKind::Conda { packages, channels } => {
+                if self.conda {
+                    let deployer = self.prj_ref().select(DeployerType::Conda);
+                    let packages = packages.join(" ");
+                    if let Some(hash) = deployer.find_installed_hash(&packages, &channels).await? {
                         load_software = deployer.gen_load_script(&hash);
+                        software = Some((DeployerType::Conda, hash));
                     }
                 }
             }
         }
 
-        if (self.spack || self.apptainer) && load_software.is_empty() {
-            tracing::error!(task_id = %task.id, "Execute usecase: software not found");
-            return self
-                .prj_ref()
-                .report_msg(task.id, TaskStatus::Failed, "Software not found")
-                .await;
+        if (self.spack || self.apptainer || self.conda || self.modules)
+            && load_software.is_empty()
+            && container.is_none()
+        {
+            anyhow::bail!("Software not found");
         }
 
         let info = ScriptInfo {
@@ -104,9 +295,23 @@ where
             environments,
             std_in,
             requirements,
+            container,
         };
-        let job_id = self.prj_ref().submit_job_script(info).await?;
+        if let Some((r#type, hash)) = &software {
+            self.record_use(*r#type, hash.clone());
+        }
+        let job_id = self.prj_ref().submit_job_script(info.clone()).await?;
         tracing::info!("Started job id: *{job_id}*");
+        self.submissions.insert(
+            task.id,
+            Submission {
+                info,
+                attempts: 0,
+                software,
+            },
+        );
+        // The job is submitted, so errors from now on mustn't fail the task,
+        // as retrying it submits another job and leaves this one running.
         let mut retry_time = 10;
         let mut interval = 1;
         let job = loop {
@@ -114,7 +319,13 @@ where
                 Ok(job) => break job,
                 Err(e) => {
                     if retry_time <= 0 {
-                        anyhow::bail!(e)
+                        tracing::warn!(%job_id, "Failed to get the submitted job: {e}");
+                        // Refreshing finds out its state later
+                        break Job {
+                            id: job_id.into(),
+                            state: JobState::Queuing,
+                            ..Default::default()
+                        };
                     }
                 }
             };
@@ -132,18 +343,27 @@ where
                 self.repo.insert(task.id, job);
                 TaskStatus::Started
             }
-            JobState::Failed | JobState::Unknown => {
-                return self.report_failure(task.id, &job).await
+            JobState::Failed(_) | JobState::Unknown => {
+                self.submissions.remove(&task.id);
+                self.restored.remove(&task.id);
+                anyhow::bail!(failure_message(&job))
             }
             /* The following arms shouldn't be met at the beginning */
             JobState::Suspended => {
                 self.repo.insert(task.id, job);
                 TaskStatus::Paused
             }
-            JobState::Completed => TaskStatus::Completed,
+            JobState::Completed => {
+                self.submissions.remove(&task.id);
+                self.restored.remove(&task.id);
+                TaskStatus::Completed
+            }
         };
 
-        self.prj_ref().report(task.id, status).await
+        if let Err(e) = self.prj_ref().report(task.id, status).await {
+            tracing::error!(task_id = %task.id, "Failed to report the submitted job: {e}");
+        }
+        Ok(())
     }
 
     async fn pause(&self, id: Uuid) -> anyhow::Result<()> {
@@ -173,6 +393,9 @@ where
         let mut retry_times = 0;
         let job_id = loop {
             if let Some(job_id) = self.repo.remove(&id).map(|job| job.1.id.clone()) {
+                self.submissions.remove(&id);
+                self.restored.remove(&id);
+                self.estimates.remove(&id);
                 break Ok(job_id);
             };
             if retry_times > 10 {
@@ -196,10 +419,12 @@ where
         tracing::info!("Refreshing state of jobs");
         let ids: Vec<Uuid> = self.repo.iter().map(|entry| *entry.key()).collect();
         for id in ids {
-            if let Err(e) = self.refresh(id).await {
+            let span = tracing::info_span!("refresh_job", task_id = %id);
+            if let Err(e) = self.refresh(id).instrument(span).await {
                 tracing::error!(task_id = %id, "Execute usecase: {e}");
             }
         }
+        self.report_estimates().await;
     }
 }
 
@@ -214,6 +439,9 @@ impl<Deps> JobServiceImpl<Deps> {
             .map(|job| (job.id.clone(), job.state))
             .context("Job not found")?;
         let job = self.prj_ref().get_job(&job_id).await?;
+        if job.state != JobState::Queuing {
+            self.estimates.remove(&id);
+        }
 
         match job.state {
             JobState::Queuing => (),
@@ -234,13 +462,27 @@ impl<Deps> JobServiceImpl<Deps> {
                     self.prj_ref().report(id, TaskStatus::Paused).await?;
                 }
             }
-            JobState::Failed | JobState::Unknown => {
+            JobState::Failed(reason) => {
+                tracing::info!(job_id = %job.id, %reason, "Job failed");
+                if self.resubmit(id, &job, reason).await? {
+                    return Ok(());
+                }
                 self.repo.remove(&id);
+                self.submissions.remove(&id);
+                self.restored.remove(&id);
+                self.report_failure(id, &job).await?;
+            }
+            JobState::Unknown => {
+                self.repo.remove(&id);
+                self.submissions.remove(&id);
+                self.restored.remove(&id);
                 tracing::info!(job_id = %job.id, "Job failed");
                 self.report_failure(id, &job).await?;
             }
             JobState::Completed => {
                 self.repo.remove(&id);
+                self.submissions.remove(&id);
+                self.restored.remove(&id);
                 tracing::info!(job_id = %job.id, "Job completed");
                 self.prj_ref()
                     .report_resources(id, TaskStatus::Completed, job.resource_used)
@@ -251,19 +493,143 @@ impl<Deps> JobServiceImpl<Deps> {
         Ok(())
     }
 
+    /// Resubmit the job if it failed for infrastructure reasons, according to the policy.
+    ///
+    /// # return
+    ///
+    /// Whether the job is resubmitted.
+    async fn resubmit(&self, id: Uuid, job: &Job, reason: FailureReason) -> anyhow::Result<bool>
+    where
+        Deps: AsRef<JobServiceState> + JobResourcesReporter + JobScheduler + Send + Sync,
+    {
+        let policy = self.resubmit;
+        let Some((mut info, attempts, software)) = self.submissions.get(&id).map(|submission| {
+            (
+                submission.info.clone(),
+                submission.attempts + 1,
+                submission.software.clone(),
+            )
+        }) else {
+            return Ok(false);
+        };
+        if attempts > policy.max_attempts {
+            return Ok(false);
+        }
+
+        let change = match reason {
+            FailureReason::NodeFail if policy.node_fail => String::new(),
+            FailureReason::Timeout => {
+                let Some(scale) = policy.timeout_scale else {
+                    return Ok(false);
+                };
+                let requirements = info.requirements.get_or_insert_with(Requirements::default);
+                let wall_time = requirements
+                    .max_wall_time
+                    .or(Some(job.resource_used.wall_time as usize))
+                    .filter(|&t| t > 0);
+                let Some(wall_time) = wall_time else {
+                    return Ok(false);
+                };
+                let wall_time = (wall_time as f64 * scale).ceil() as usize;
+                requirements.max_wall_time = Some(wall_time);
+                format!(" with wall time {wall_time}s")
+            }
+            FailureReason::OutOfMemory => {
+                let Some(scale) = policy.memory_scale else {
+                    return Ok(false);
+                };
+                let requirements = info.requirements.get_or_insert_with(Requirements::default);
+                let Some(memory) = requirements.memory else {
+                    return Ok(false);
+                };
+                let memory = (memory as f64 * scale).ceil() as usize;
+                requirements.memory = Some(memory);
+                format!(" with memory {memory}MiB")
+            }
+            _ => return Ok(false),
+        };
+
+        let job_id = self.prj_ref().submit_job_script(info.clone()).await?;
+        tracing::info!(%job_id, "Resubmitted job after {reason}");
+        if let Some((r#type, hash)) = &software {
+            self.record_use(*r#type, hash.clone());
+        }
+        self.submissions.insert(
+            id,
+            Submission {
+                info,
+                attempts,
+                software,
+            },
+        );
+        self.repo.insert(
+            id,
+            Job {
+                id: job_id.as_str().into(),
+                state: JobState::Queuing,
+                ..Default::default()
+            },
+        );
+
+        let message = format!(
+            "Job {} failed for {reason}, resubmitted as job {job_id}{change} (attempt {attempts}/{})",
+            job.id, policy.max_attempts
+        );
+        self.prj_ref().report_msg(id, TaskStatus::Queued, &message).await?;
+        Ok(true)
+    }
+
+    /// Report where the queued jobs are in the queue when it changes,
+    /// from a single query of the scheduler for all of them
+    async fn report_estimates(&self)
+    where
+        Deps: AsRef<JobServiceState> + JobResourcesReporter + JobScheduler + Send + Sync,
+    {
+        let queued: Vec<(Uuid, String)> = self
+            .repo
+            .iter()
+            .filter(|entry| entry.value().state == JobState::Queuing)
+            .map(|entry| (*entry.key(), entry.value().id.to_string()))
+            .collect();
+        if queued.is_empty() {
+            return;
+        }
+
+        let job_ids: Vec<String> = queued.iter().map(|(_, job_id)| job_id.clone()).collect();
+        let mut estimates = match self.prj_ref().queue_estimates(&job_ids).await {
+            Ok(estimates) => estimates,
+            Err(e) => {
+                tracing::debug!("Failed to estimate the queued jobs: {e}");
+                return;
+            }
+        };
+        for (id, job_id) in queued {
+            let Some(estimate) = estimates.remove(&job_id).filter(|e| !e.is_empty()) else {
+                continue;
+            };
+            if self.estimates.get(&id).is_some_and(|previous| *previous == estimate) {
+                continue;
+            }
+
+            let message = format!("Job {job_id} is queued: {estimate}");
+            self.estimates.insert(id, estimate);
+            if let Err(e) = self.prj_ref().report_msg(id, TaskStatus::Queued, &message).await {
+                tracing::error!(task_id = %id, "Failed to report the queued job: {e}");
+            }
+        }
+    }
+
     async fn report_failure(&self, id: Uuid, job: &Job) -> anyhow::Result<()>
     where
         Deps: JobResourcesReporter + Send + Sync,
     {
-        self.prj_ref()
-            .report_msg(
-                id,
-                TaskStatus::Failed,
-                &format!(
-                    "Job exit with {}\nError Output:\n{}",
-                    job.exit_status_code, job.error_output
-                ),
-            )
-            .await
+        self.prj_ref().report_msg(id, TaskStatus::Failed, &failure_message(job)).await
     }
 }
+
+fn failure_message(job: &Job) -> String {
+    format!(
+        "Job exit with {}\nError Output:\n{}",
+        job.exit_status_code, job.error_output
+    )
+}
@@ -7,6 +7,6 @@ pub mod prelude {
     pub use super::{
         collect_output::{CollectOutputService, CollectOutputState},
         deploy_software::{DeploySoftwareService, DeploySoftwareState},
-        job::{JobServiceImpl, JobServiceState},
+        job::{JobContext, JobServiceImpl, JobServiceState},
     };
 }